@@ -0,0 +1,24 @@
+'use strict'
+
+const assert = require('node:assert')
+const path = require('node:path')
+const fs = require('node:fs')
+const test = require('node:test')
+
+const { deref, derefFile } = require('..')
+
+const fixtures = path.join(__dirname, '..', '..', '..', 'jsonref-core', 'fixtures')
+
+test('derefFile matches the expected output', async () => {
+  const output = await derefFile(path.join(fixtures, 'nested_relative', 'base.json'), { referenceKey: '__reference__' })
+  const expected = JSON.parse(fs.readFileSync(path.join(fixtures, 'nested_relative', 'expected.json'), 'utf8'))
+  assert.deepStrictEqual(output, expected)
+})
+
+test('deref applies options and rejects bad refs', async () => {
+  const schema = { properties: { a: { title: 'a' }, b: { $ref: '#/properties/a' } } }
+  assert.deepStrictEqual(await deref(schema, { stripKeywords: ['title'] }), { properties: { a: {}, b: {} } })
+
+  await assert.rejects(deref({ $ref: '#/missing' }))
+  await assert.rejects(deref(schema, { refScope: 'nowhere' }))
+})
@@ -0,0 +1,19 @@
+export interface DerefOptions {
+  referenceKey?: string
+  maxDepth?: number
+  absoluteRefs?: boolean
+  bundleThreshold?: number
+  refScope?: 'all' | 'internal' | 'external'
+  flattenAllOf?: boolean
+  normalizeDefinitions?: '$defs' | 'definitions'
+  stripKeywords?: string[]
+  idPolicy?: 'keep' | 'strip' | 'fragment'
+  mergeExtensions?: boolean
+  sourceKey?: string
+  dedupeThreshold?: number
+  codegenProfile?: boolean
+}
+
+export function derefFile(path: string, options?: DerefOptions): Promise<any>
+
+export function deref(schema: object | string, options?: DerefOptions & { baseUri?: string }): Promise<any>
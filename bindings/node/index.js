@@ -0,0 +1,22 @@
+'use strict'
+
+const native = require('./jsonref.node')
+
+/**
+ * Dereference the schema file at `path`. Resolves to the dereferenced schema.
+ */
+async function derefFile(path, options) {
+  return JSON.parse(await native.derefFile(path, options))
+}
+
+/**
+ * Dereference a schema, given as an object or a JSON string. Relative refs are resolved
+ * against `options.baseUri`, or the current directory when it is not given.
+ */
+async function deref(schema, options = {}) {
+  const { baseUri, ...rest } = options
+  const json = typeof schema === 'string' ? schema : JSON.stringify(schema)
+  return JSON.parse(await native.derefStr(json, baseUri, rest))
+}
+
+module.exports = { deref, derefFile }
@@ -0,0 +1,147 @@
+//! napi-rs module behind the `jsonref-rs` npm package. Dereferencing runs on the libuv thread
+//! pool so it never blocks the event loop. Schemas cross the boundary as JSON strings and are
+//! parsed by the JavaScript wrapper.
+
+use ::jsonref::{DefinitionsKeyword, IdPolicy, JsonRef, RefScope};
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use serde_json::Value;
+
+/// The `JsonRef` options, named as its setters without `set_`.
+#[napi(object)]
+#[derive(Default)]
+pub struct DerefOptions {
+    pub reference_key: Option<String>,
+    pub max_depth: Option<u32>,
+    pub absolute_refs: Option<bool>,
+    pub bundle_threshold: Option<u32>,
+    pub ref_scope: Option<String>,
+    pub flatten_all_of: Option<bool>,
+    pub normalize_definitions: Option<String>,
+    pub strip_keywords: Option<Vec<String>>,
+    pub id_policy: Option<String>,
+    pub merge_extensions: Option<bool>,
+    pub source_key: Option<String>,
+    pub dedupe_threshold: Option<u32>,
+    pub codegen_profile: Option<bool>,
+}
+
+enum Source {
+    File(String),
+    Json { schema: String, base_uri: Option<String> },
+}
+
+pub struct DerefTask {
+    source: Source,
+    options: DerefOptions,
+}
+
+impl Task for DerefTask {
+    type Output = String;
+    type JsValue = String;
+
+    fn compute(&mut self) -> Result<String> {
+        let mut jsonref = configure(&self.options)?;
+        let output = match &self.source {
+            Source::File(path) => jsonref.deref_file(path),
+            Source::Json { schema, base_uri } => {
+                let mut value: Value = serde_json::from_str(schema).map_err(|error| invalid(error.to_string()))?;
+                match base_uri {
+                    Some(base_uri) => jsonref.deref_value_with_url(&mut value, base_uri),
+                    None => jsonref.deref_value(&mut value),
+                }
+                .map(|_| value)
+            }
+        };
+        output
+            .map(|output| output.to_string())
+            .map_err(|error| Error::new(Status::GenericFailure, error.to_string()))
+    }
+
+    fn resolve(&mut self, _env: Env, output: String) -> Result<String> {
+        Ok(output)
+    }
+}
+
+/// Dereference the schema file at `path`, resolving to JSON.
+#[napi(ts_return_type = "Promise<string>")]
+pub fn deref_file(path: String, options: Option<DerefOptions>) -> AsyncTask<DerefTask> {
+    AsyncTask::new(DerefTask {
+        source: Source::File(path),
+        options: options.unwrap_or_default(),
+    })
+}
+
+/// Dereference the JSON schema `schema`, resolving relative refs against `base_uri` or the
+/// current directory, resolving to JSON.
+#[napi(ts_return_type = "Promise<string>")]
+pub fn deref_str(schema: String, base_uri: Option<String>, options: Option<DerefOptions>) -> AsyncTask<DerefTask> {
+    AsyncTask::new(DerefTask {
+        source: Source::Json { schema, base_uri },
+        options: options.unwrap_or_default(),
+    })
+}
+
+fn invalid(reason: String) -> Error {
+    Error::new(Status::InvalidArg, reason)
+}
+
+/// A `JsonRef` with `options` applied to it.
+fn configure(options: &DerefOptions) -> Result<JsonRef> {
+    let mut jsonref = JsonRef::new();
+    if let Some(reference_key) = &options.reference_key {
+        jsonref.set_reference_key(reference_key);
+    }
+    if let Some(max_depth) = options.max_depth {
+        jsonref.set_max_depth(max_depth as usize);
+    }
+    if let Some(absolute_refs) = options.absolute_refs {
+        jsonref.set_absolute_refs(absolute_refs);
+    }
+    if let Some(bundle_threshold) = options.bundle_threshold {
+        jsonref.set_bundle_threshold(bundle_threshold as usize);
+    }
+    if let Some(ref_scope) = &options.ref_scope {
+        jsonref.set_ref_scope(match ref_scope.as_str() {
+            "all" => RefScope::All,
+            "internal" => RefScope::Internal,
+            "external" => RefScope::External,
+            other => return Err(invalid(format!("unknown refScope {:?}", other))),
+        });
+    }
+    if let Some(flatten_all_of) = options.flatten_all_of {
+        jsonref.set_flatten_all_of(flatten_all_of);
+    }
+    if let Some(keyword) = &options.normalize_definitions {
+        jsonref.set_normalize_definitions(match keyword.as_str() {
+            "$defs" => DefinitionsKeyword::Defs,
+            "definitions" => DefinitionsKeyword::Definitions,
+            other => return Err(invalid(format!("unknown normalizeDefinitions {:?}", other))),
+        });
+    }
+    if let Some(keywords) = &options.strip_keywords {
+        let keywords: Vec<&str> = keywords.iter().map(String::as_str).collect();
+        jsonref.set_strip_keywords(&keywords);
+    }
+    if let Some(id_policy) = &options.id_policy {
+        jsonref.set_id_policy(match id_policy.as_str() {
+            "keep" => IdPolicy::Keep,
+            "strip" => IdPolicy::Strip,
+            "fragment" => IdPolicy::Fragment,
+            other => return Err(invalid(format!("unknown idPolicy {:?}", other))),
+        });
+    }
+    if let Some(merge_extensions) = options.merge_extensions {
+        jsonref.set_merge_extensions(merge_extensions);
+    }
+    if let Some(source_key) = &options.source_key {
+        jsonref.set_source_key(source_key);
+    }
+    if let Some(dedupe_threshold) = options.dedupe_threshold {
+        jsonref.set_dedupe_threshold(dedupe_threshold as usize);
+    }
+    if let Some(codegen_profile) = options.codegen_profile {
+        jsonref.set_codegen_profile(codegen_profile);
+    }
+    Ok(jsonref)
+}
@@ -0,0 +1,33 @@
+"""Fast JSON Schema dereferencing backed by the jsonref Rust crate.
+
+Every function takes the jsonref options as keyword arguments, named as the ``JsonRef``
+setters without ``set_``: ``reference_key``, ``max_depth``, ``absolute_refs``,
+``bundle_threshold``, ``ref_scope`` (``"all"``, ``"internal"`` or ``"external"``),
+``flatten_all_of``, ``normalize_definitions`` (``"$defs"`` or ``"definitions"``),
+``strip_keywords``, ``id_policy`` (``"keep"``, ``"strip"`` or ``"fragment"``),
+``merge_extensions``, ``source_key``, ``dedupe_threshold`` and ``codegen_profile``.
+"""
+
+import json
+
+from . import _native
+from ._native import JsonRefError
+
+__all__ = ["deref", "deref_file", "deref_str", "JsonRefError"]
+
+
+def deref_file(path, **options):
+    """Dereference the schema file at ``path`` and return it as Python objects."""
+    return json.loads(_native.deref_file(str(path), **options))
+
+
+def deref_str(schema, base_uri=None, **options):
+    """Dereference a schema given as a JSON string. Relative refs are resolved against
+    ``base_uri``, or the current directory when it is not given."""
+    return json.loads(_native.deref_str(schema, base_uri, **options))
+
+
+def deref(obj, base_uri=None, **options):
+    """Dereference a schema already loaded as Python objects. Unlike ``jsonref.replace_refs``
+    the result is plain dicts and lists rather than lazy proxies."""
+    return deref_str(json.dumps(obj), base_uri, **options)
@@ -0,0 +1,97 @@
+//! pyo3 module behind the `jsonref_rs` Python package. Schemas cross the boundary as JSON strings
+//! and are parsed by the Python wrapper.
+
+use ::jsonref::{DefinitionsKeyword, IdPolicy, JsonRef, RefScope};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use serde_json::Value;
+
+create_exception!(_native, JsonRefError, PyException);
+
+/// Dereference the schema file at `path`, returning JSON.
+#[pyfunction]
+#[pyo3(signature = (path, **options))]
+fn deref_file(py: Python<'_>, path: &str, options: Option<&Bound<'_, PyDict>>) -> PyResult<String> {
+    let mut jsonref = configure(options)?;
+    let output = py
+        .allow_threads(|| jsonref.deref_file(path))
+        .map_err(|error| JsonRefError::new_err(error.to_string()))?;
+    Ok(output.to_string())
+}
+
+/// Dereference the JSON schema `schema`, resolving relative refs against `base_uri` or the
+/// current directory, returning JSON.
+#[pyfunction]
+#[pyo3(signature = (schema, base_uri=None, **options))]
+fn deref_str(
+    py: Python<'_>,
+    schema: &str,
+    base_uri: Option<&str>,
+    options: Option<&Bound<'_, PyDict>>,
+) -> PyResult<String> {
+    let mut jsonref = configure(options)?;
+    let mut value: Value = serde_json::from_str(schema).map_err(|error| PyValueError::new_err(error.to_string()))?;
+    py.allow_threads(|| match base_uri {
+        Some(base_uri) => jsonref.deref_value_with_url(&mut value, base_uri),
+        None => jsonref.deref_value(&mut value),
+    })
+    .map_err(|error| JsonRefError::new_err(error.to_string()))?;
+    Ok(value.to_string())
+}
+
+/// A `JsonRef` with the keyword arguments applied to it.
+fn configure(options: Option<&Bound<'_, PyDict>>) -> PyResult<JsonRef> {
+    let mut jsonref = JsonRef::new();
+    let options = match options {
+        Some(options) => options,
+        None => return Ok(jsonref),
+    };
+    for (key, value) in options.iter() {
+        let key: String = key.extract()?;
+        match key.as_str() {
+            "reference_key" => jsonref.set_reference_key(&value.extract::<String>()?),
+            "max_depth" => jsonref.set_max_depth(value.extract()?),
+            "absolute_refs" => jsonref.set_absolute_refs(value.extract()?),
+            "bundle_threshold" => jsonref.set_bundle_threshold(value.extract()?),
+            "ref_scope" => jsonref.set_ref_scope(match value.extract::<String>()?.as_str() {
+                "all" => RefScope::All,
+                "internal" => RefScope::Internal,
+                "external" => RefScope::External,
+                other => return Err(PyValueError::new_err(format!("unknown ref_scope {:?}", other))),
+            }),
+            "flatten_all_of" => jsonref.set_flatten_all_of(value.extract()?),
+            "normalize_definitions" => jsonref.set_normalize_definitions(match value.extract::<String>()?.as_str() {
+                "$defs" => DefinitionsKeyword::Defs,
+                "definitions" => DefinitionsKeyword::Definitions,
+                other => return Err(PyValueError::new_err(format!("unknown normalize_definitions {:?}", other))),
+            }),
+            "strip_keywords" => {
+                let keywords: Vec<String> = value.extract()?;
+                let keywords: Vec<&str> = keywords.iter().map(String::as_str).collect();
+                jsonref.set_strip_keywords(&keywords);
+            }
+            "id_policy" => jsonref.set_id_policy(match value.extract::<String>()?.as_str() {
+                "keep" => IdPolicy::Keep,
+                "strip" => IdPolicy::Strip,
+                "fragment" => IdPolicy::Fragment,
+                other => return Err(PyValueError::new_err(format!("unknown id_policy {:?}", other))),
+            }),
+            "merge_extensions" => jsonref.set_merge_extensions(value.extract()?),
+            "source_key" => jsonref.set_source_key(&value.extract::<String>()?),
+            "dedupe_threshold" => jsonref.set_dedupe_threshold(value.extract()?),
+            "codegen_profile" => jsonref.set_codegen_profile(value.extract()?),
+            other => return Err(PyTypeError::new_err(format!("unexpected option {:?}", other))),
+        }
+    }
+    Ok(jsonref)
+}
+
+#[pymodule]
+fn _native(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("JsonRefError", m.py().get_type_bound::<JsonRefError>())?;
+    m.add_function(wrap_pyfunction!(deref_file, m)?)?;
+    m.add_function(wrap_pyfunction!(deref_str, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,24 @@
+import json
+import pathlib
+
+import pytest
+
+import jsonref_rs
+
+FIXTURES = pathlib.Path(__file__).parents[3] / "jsonref-core" / "fixtures"
+
+
+def test_deref_file_matches_expected():
+    output = jsonref_rs.deref_file(FIXTURES / "nested_relative" / "base.json", reference_key="__reference__")
+    expected = json.loads((FIXTURES / "nested_relative" / "expected.json").read_text())
+    assert output == expected
+
+
+def test_options_and_errors():
+    schema = {"properties": {"a": {"title": "a"}, "b": {"$ref": "#/properties/a"}}}
+    assert jsonref_rs.deref(schema, strip_keywords=["title"]) == {"properties": {"a": {}, "b": {}}}
+
+    with pytest.raises(TypeError):
+        jsonref_rs.deref(schema, unknown_option=True)
+    with pytest.raises(jsonref_rs.JsonRefError):
+        jsonref_rs.deref({"$ref": "#/missing"})
@@ -0,0 +1,41 @@
+/* C API for jsonref, built with `cargo rustc -p jsonref-core --release --features ffi --crate-type cdylib`. */
+
+#ifndef JSONREF_H
+#define JSONREF_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The schema was dereferenced and the output is the dereferenced JSON. */
+#define JSONREF_OK 0
+/* A pointer argument was null or a string was not UTF-8. */
+#define JSONREF_INVALID_ARGUMENT 1
+/* The input was not valid JSON. */
+#define JSONREF_INVALID_JSON 2
+/* Dereferencing failed, for example because a $ref could not be found. */
+#define JSONREF_DEREF_ERROR 3
+/* jsonref panicked, which is a bug in jsonref. */
+#define JSONREF_PANIC 4
+
+/*
+ * Dereference the UTF-8 JSON schema in `input`, `input_len` bytes long. Relative refs are
+ * resolved against the NUL terminated `base_url`, or the current directory if it is NULL.
+ *
+ * Returns one of the JSONREF_ codes. Unless `output` is NULL, `*output` is set to a NUL
+ * terminated string: the dereferenced JSON on success, otherwise an error message. It must be
+ * freed with jsonref_free.
+ */
+int jsonref_deref(const uint8_t *input, size_t input_len, const char *base_url, char **output);
+
+/* Free a string returned by jsonref_deref. Does nothing if `string` is NULL. */
+void jsonref_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,444 @@
+//! Inventories of the fields a schema describes, for analysing a schema programmatically.
+
+use crate::schema::children;
+use crate::{escape_pointer, join_id};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io;
+use url::Url;
+
+/// A property described by a schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldInfo {
+    /// The names of the property and its parents joined by `/`, e.g. `address/street`. Properties
+    /// of array items are listed under the array's name.
+    pub path: String,
+    /// JSON pointer to the property's schema.
+    pub pointer: String,
+    /// The JSON types the property allows, from `type` or inferred from its keywords.
+    pub types: Vec<String>,
+    /// The property's `title`.
+    pub title: Option<String>,
+    /// Whether the property is in its parent's `required`.
+    pub required: bool,
+    /// Whether the property's schema, or one of its parents, came from a `$ref`.
+    pub from_ref: bool,
+}
+
+/// Every property described by `schema`, parents before children.
+///
+/// Meant for dereferenced schemas: `$ref`s still in the document, such as bundled ones, are
+/// followed if they point into it and the fields found through them are marked `from_ref`.
+///
+/// ```
+/// use jsonref::analyze::fields;
+/// use serde_json::json;
+///
+/// let schema = json!(
+///     {"required": ["name"],
+///      "properties": {"name": {"type": "string", "title": "Name"},
+///                     "address": {"$ref": "#/$defs/address"}},
+///      "$defs": {"address": {"properties": {"street": {"type": "string"}}}}}
+/// );
+///
+/// let found = fields(&schema);
+///
+/// assert_eq!(
+///     found.iter().map(|field| field.path.as_str()).collect::<Vec<_>>(),
+///     vec!["address", "address/street", "name"]
+/// );
+/// assert!(found[1].from_ref);
+/// assert!(found[2].required);
+/// assert_eq!(found[2].title.as_deref(), Some("Name"));
+/// ```
+pub fn fields(schema: &Value) -> Vec<FieldInfo> {
+    fields_with_markers(schema, &[])
+}
+
+/// Like [`fields`], also marking schemas containing any of `markers` as `from_ref`. Use the key
+/// given to [`crate::JsonRef::set_source_key`] or [`crate::JsonRef::set_reference_key`] to find
+/// the fields that were inlined.
+pub fn fields_with_markers(schema: &Value, markers: &[&str]) -> Vec<FieldInfo> {
+    let mut walker = Walker {
+        root: schema,
+        markers,
+        found: vec![],
+        refs: vec![],
+    };
+    walker.walk(schema, "", "", false);
+    walker.found
+}
+
+/// The header written by [`write_csv`] and [`write_tsv`].
+pub const FIELD_COLUMNS: [&str; 6] = ["path", "types", "title", "required", "from_ref", "pointer"];
+
+/// Write `fields` as CSV with a header row, quoting values as RFC 4180 describes. Multiple types
+/// are separated by `|`.
+///
+/// ```
+/// use jsonref::analyze::{fields, write_csv};
+/// use serde_json::json;
+///
+/// let schema = json!({"properties": {"name": {"type": ["string", "null"], "title": "Name, full"}}});
+///
+/// let mut csv = vec![];
+/// write_csv(&mut csv, &fields(&schema)).unwrap();
+///
+/// assert_eq!(
+///     String::from_utf8(csv).unwrap(),
+///     "path,types,title,required,from_ref,pointer\nname,string|null,\"Name, full\",false,false,/properties/name\n"
+/// );
+/// ```
+pub fn write_csv<W: io::Write>(mut writer: W, fields: &[FieldInfo]) -> io::Result<()> {
+    write_rows(&mut writer, fields, ",", |value| {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_owned()
+        }
+    })
+}
+
+/// Write `fields` as tab separated values with a header row. Tabs and line breaks within values
+/// are replaced with spaces.
+pub fn write_tsv<W: io::Write>(mut writer: W, fields: &[FieldInfo]) -> io::Result<()> {
+    write_rows(&mut writer, fields, "\t", |value| value.replace(['\t', '\n', '\r'], " "))
+}
+
+fn write_rows<W: io::Write>(
+    writer: &mut W,
+    fields: &[FieldInfo],
+    delimiter: &str,
+    escape: impl Fn(&str) -> String,
+) -> io::Result<()> {
+    writeln!(writer, "{}", FIELD_COLUMNS.join(delimiter))?;
+    for field in fields {
+        let row = [
+            field.path.clone(),
+            field.types.join("|"),
+            field.title.clone().unwrap_or_default(),
+            field.required.to_string(),
+            field.from_ref.to_string(),
+            field.pointer.clone(),
+        ];
+        let row: Vec<String> = row.iter().map(|value| escape(value)).collect();
+        writeln!(writer, "{}", row.join(delimiter))?;
+    }
+    Ok(())
+}
+
+/// Measures of a schema's size and complexity, from [`stats`] or [`crate::JsonRef::stats`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaStats {
+    /// Entries in `properties` across all subschemas of the output.
+    pub properties: usize,
+    /// Entries in `$defs` and `definitions` across all subschemas of the source documents.
+    pub definitions: usize,
+    /// The deepest nesting of subschemas in the output, the root being at depth 0.
+    pub max_depth: usize,
+    /// `$ref`s in the source documents.
+    pub refs: usize,
+    /// For each `$ref` target, as an absolute URL with fragment, how many `$ref`s point to it.
+    pub fan_in: BTreeMap<String, usize>,
+    /// For each source document, how many `$ref`s it contains.
+    pub fan_out: BTreeMap<String, usize>,
+    /// Serialized size in bytes of the source documents.
+    pub size_before: usize,
+    /// Serialized size in bytes of the output.
+    pub size_after: usize,
+    /// Serialized size in bytes of the subschemas of the output that repeat an identical earlier
+    /// one, not counting those within a repeat. Roughly what moving repeats into `$defs` with
+    /// [`crate::JsonRef::set_dedupe_threshold`] would save, or keeping the output as a
+    /// [`crate::share::SharedValue`] does.
+    pub size_repeated: usize,
+}
+
+/// Statistics of the dereferenced `output` of the source documents `sources`, given as URL and
+/// document pairs. Relative `$ref`s are resolved against the document URL and any `$id`s.
+///
+/// ```
+/// use jsonref::analyze::stats;
+/// use serde_json::json;
+///
+/// let source = json!(
+///     {"$defs": {"name": {"type": "string"}},
+///      "properties": {"first": {"$ref": "#/$defs/name"}, "last": {"$ref": "#/$defs/name"}}}
+/// );
+/// let output = json!(
+///     {"$defs": {"name": {"type": "string"}},
+///      "properties": {"first": {"type": "string"}, "last": {"type": "string"}}}
+/// );
+///
+/// let report = stats(&[("file:///schema.json", &source)], &output);
+///
+/// assert_eq!(report.properties, 2);
+/// assert_eq!(report.definitions, 1);
+/// assert_eq!(report.max_depth, 1);
+/// assert_eq!(report.fan_in["file:///schema.json#/$defs/name"], 2);
+/// assert_eq!(report.fan_out["file:///schema.json"], 2);
+/// assert_eq!(report.size_repeated, r#"{"type":"string"}"#.len() * 2);
+/// ```
+pub fn stats(sources: &[(&str, &Value)], output: &Value) -> SchemaStats {
+    let mut report = SchemaStats {
+        size_after: size(output),
+        size_repeated: repeated_size(output, &mut HashSet::new()),
+        ..SchemaStats::default()
+    };
+    report.max_depth = count_schemas(output, 0, &mut report, &mut |report, schema| {
+        report.properties += schema.get("properties").and_then(Value::as_object).map_or(0, |properties| properties.len());
+    });
+
+    for (url, document) in sources {
+        report.size_before += size(document);
+        let mut definitions = 0;
+        count_schemas(document, 0, &mut definitions, &mut |definitions, schema| {
+            for keyword in ["$defs", "definitions"] {
+                *definitions += schema.get(keyword).and_then(Value::as_object).map_or(0, |defs| defs.len());
+            }
+        });
+        report.definitions += definitions;
+
+        let mut targets = vec![];
+        collect_refs(document, url.to_string(), &mut targets);
+        report.refs += targets.len();
+        report.fan_out.insert(url.to_string(), targets.len());
+        for target in targets {
+            *report.fan_in.entry(target).or_insert(0) += 1;
+        }
+    }
+    report
+}
+
+/// The document dependency graph of the source documents `sources`, given as URL and document
+/// pairs: for each document, the other documents its `$ref`s point into, as URLs without
+/// fragments. Refs within a document are left out.
+///
+/// ```
+/// use jsonref::analyze::ref_graph;
+/// use serde_json::json;
+///
+/// let base = json!({"properties": {"name": {"$ref": "name.json"}, "id": {"$ref": "#/$defs/id"}}});
+/// let name = json!({"type": "string"});
+///
+/// let graph = ref_graph(&[("file:///base.json", &base), ("file:///name.json", &name)]);
+///
+/// assert_eq!(graph["file:///base.json"].iter().collect::<Vec<_>>(), ["file:///name.json"]);
+/// assert!(graph["file:///name.json"].is_empty());
+/// ```
+pub fn ref_graph(sources: &[(&str, &Value)]) -> BTreeMap<String, BTreeSet<String>> {
+    let mut graph = BTreeMap::new();
+    for (url, document) in sources {
+        let mut targets = vec![];
+        collect_refs(document, url.to_string(), &mut targets);
+        let dependencies = targets
+            .into_iter()
+            .map(|target| target.split('#').next().unwrap_or_default().to_owned())
+            .filter(|target| target != url)
+            .collect();
+        graph.insert(url.to_string(), dependencies);
+    }
+    graph
+}
+
+/// Call `visit` on every subschema of `schema`, returning the deepest nesting below it.
+fn count_schemas<T>(schema: &Value, depth: usize, state: &mut T, visit: &mut dyn FnMut(&mut T, &Value)) -> usize {
+    visit(state, schema);
+    children(schema)
+        .into_iter()
+        .map(|(_, subschema)| count_schemas(subschema, depth + 1, state, visit))
+        .max()
+        .unwrap_or(depth)
+}
+
+/// The absolute URL of every `$ref` in `value`, resolved against `base`.
+fn collect_refs(value: &Value, base: String, targets: &mut Vec<String>) {
+    match value {
+        Value::Object(obj) => {
+            let base = join_id(value, base);
+            if let Some(reference) = obj.get("$ref").and_then(Value::as_str) {
+                let target = Url::parse(&base).and_then(|base| base.join(reference));
+                targets.push(target.map_or_else(|_| reference.to_owned(), |target| target.to_string()));
+            }
+            for child in obj.values() {
+                collect_refs(child, base.clone(), targets);
+            }
+        }
+        Value::Array(array) => {
+            for child in array {
+                collect_refs(child, base.clone(), targets);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The size of the subschemas of `schema` serialized the same as one in `seen`, or earlier in
+/// `schema`.
+fn repeated_size(schema: &Value, seen: &mut HashSet<String>) -> usize {
+    let serialized = serde_json::to_string(schema).unwrap_or_default();
+    let length = serialized.len();
+    if !seen.insert(serialized) {
+        return length;
+    }
+    children(schema)
+        .into_iter()
+        .map(|(_, subschema)| repeated_size(subschema, seen))
+        .sum()
+}
+
+fn size(value: &Value) -> usize {
+    serde_json::to_vec(value).map_or(0, |bytes| bytes.len())
+}
+
+struct Walker<'a> {
+    root: &'a Value,
+    markers: &'a [&'a str],
+    found: Vec<FieldInfo>,
+    /// The local `$ref`s being followed, to stop at recursion.
+    refs: Vec<String>,
+}
+
+impl<'a> Walker<'a> {
+    /// List the properties of `schema`, found at `pointer` and describing the field `path`.
+    fn walk(&mut self, schema: &'a Value, pointer: &str, path: &str, from_ref: bool) {
+        let reference = schema.get("$ref").and_then(Value::as_str);
+        if reference.is_some_and(|reference| self.refs.iter().any(|followed| followed == reference)) {
+            return;
+        }
+        let (schema, pointer, from_ref) = match self.target(schema, pointer, from_ref) {
+            Some(target) => target,
+            None => return,
+        };
+        if let Some(reference) = reference {
+            self.refs.push(reference.to_owned());
+        }
+
+        let required: Vec<&str> = required(schema);
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (name, property) in properties {
+                let property_pointer = format!("{}/properties/{}", pointer, escape_pointer(name));
+                let property_path = if path.is_empty() { name.clone() } else { format!("{}/{}", path, name) };
+                let (target, _, property_from_ref) = self
+                    .target(property, &property_pointer, from_ref)
+                    .unwrap_or((property, String::new(), from_ref));
+                self.found.push(FieldInfo {
+                    path: property_path.clone(),
+                    pointer: property_pointer.clone(),
+                    types: types(target),
+                    title: target.get("title").and_then(Value::as_str).map(str::to_owned),
+                    required: required.contains(&name.as_str()),
+                    from_ref: property_from_ref,
+                });
+                self.walk(property, &property_pointer, &property_path, from_ref);
+            }
+        }
+
+        for keyword in ["allOf", "anyOf", "oneOf"] {
+            if let Some(branches) = schema.get(keyword).and_then(Value::as_array) {
+                for (index, branch) in branches.iter().enumerate() {
+                    self.walk(branch, &format!("{}/{}/{}", pointer, keyword, index), path, from_ref);
+                }
+            }
+        }
+        if let Some(items) = schema.get("items") {
+            match items {
+                Value::Array(items) => {
+                    for (index, item) in items.iter().enumerate() {
+                        self.walk(item, &format!("{}/items/{}", pointer, index), path, from_ref);
+                    }
+                }
+                item => self.walk(item, &format!("{}/items", pointer), path, from_ref),
+            }
+        }
+
+        if reference.is_some() {
+            self.refs.pop();
+        }
+    }
+
+    /// The schema `schema` describes, following a local `$ref`, with its pointer and whether it
+    /// came from a ref. `None` for refs that can not be followed.
+    fn target(&self, schema: &'a Value, pointer: &str, from_ref: bool) -> Option<(&'a Value, String, bool)> {
+        let marked = self.markers.iter().any(|marker| schema.get(*marker).is_some());
+        match schema.get("$ref").and_then(Value::as_str) {
+            Some(reference) => {
+                let target_pointer = reference.strip_prefix('#')?;
+                Some((self.root.pointer(target_pointer)?, target_pointer.to_owned(), true))
+            }
+            None => Some((schema, pointer.to_owned(), from_ref || marked)),
+        }
+    }
+}
+
+fn required(schema: &Value) -> Vec<&str> {
+    let mut required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    if let Some(branches) = schema.get("allOf").and_then(Value::as_array) {
+        for branch in branches {
+            required.extend(self::required(branch));
+        }
+    }
+    required
+}
+
+fn types(schema: &Value) -> Vec<String> {
+    match schema.get("type") {
+        Some(Value::String(instance_type)) => vec![instance_type.clone()],
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).map(str::to_owned).collect(),
+        _ if schema.get("properties").is_some() => vec!["object".to_owned()],
+        _ if schema.get("items").is_some() => vec!["array".to_owned()],
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fields, fields_with_markers, write_tsv};
+    use crate::JsonRef;
+    use serde_json::json;
+
+    #[test]
+    fn inventory_of_dereferenced_schema() {
+        let mut input = json!(
+            {"$defs": {"node": {"properties": {"name": {"type": ["string", "null"]},
+                                               "children": {"type": "array", "items": {"$ref": "#/$defs/node"}}}}},
+             "properties": {"root": {"$ref": "#/$defs/node"},
+                            "meta": {"allOf": [{"required": ["id"], "properties": {"id": {"type": "integer"}}}]}}}
+        );
+
+        // Recursion is stopped in the bundled form.
+        let found = fields(&input);
+        let paths: Vec<&str> = found.iter().map(|field| field.path.as_str()).collect();
+        assert!(paths.contains(&"root/children"));
+        assert!(!paths.contains(&"root/children/name"));
+        let id = found.iter().find(|field| field.path == "meta/id").unwrap();
+        assert!(id.required && !id.from_ref);
+        assert_eq!(id.pointer, "/properties/meta/allOf/0/properties/id");
+
+        let mut jsonref = JsonRef::new();
+        jsonref.set_source_key("$source");
+        jsonref.deref_value(&mut input).unwrap();
+
+        let found = fields_with_markers(&input, &["$source"]);
+        let name = found.iter().find(|field| field.path == "root/name").unwrap();
+        assert_eq!(name.types, vec!["string", "null"]);
+        assert!(name.from_ref);
+        assert!(!found.iter().find(|field| field.path == "meta").unwrap().from_ref);
+    }
+
+    #[test]
+    fn tsv_export() {
+        let schema = json!({"properties": {"a\tb": {"title": "line\nbreak", "items": {}}}});
+
+        let mut tsv = vec![];
+        write_tsv(&mut tsv, &fields(&schema)).unwrap();
+
+        assert_eq!(
+            String::from_utf8(tsv).unwrap(),
+            "path\ttypes\ttitle\trequired\tfrom_ref\tpointer\na b\tarray\tline break\tfalse\tfalse\t/properties/a b\n"
+        );
+    }
+}
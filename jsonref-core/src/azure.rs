@@ -0,0 +1,192 @@
+//! A [`Layer`] retrieving documents from Azure Blob Storage, for schemas kept there as build
+//! artifacts, without downloading them and rewriting the refs first. Needs the `azure` feature.
+
+use crate::http::{token_response, Http, TokenCache, TokenProvider};
+use crate::resolve::{Layer, Next};
+use crate::{parse, Callback, Error, Result, SchemaNotJson, UrlParseError};
+use serde_json::Value;
+use snafu::ResultExt;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+
+/// The Blob service version sent with OAuth requests, which need one.
+const API_VERSION: &str = "2021-08-06";
+
+/// Retrieve `az://account/container/blob` URLs, and `https://account.blob.core.windows.net`
+/// ones, from Azure Blob Storage, passing on any other. Refs relative to a blob stay in its
+/// container.
+///
+/// Requests are authenticated with, in order, the SAS token or token provider set on the
+/// layer, the SAS token in `AZURE_STORAGE_SAS_TOKEN`, or a token of the managed identity of the
+/// VM, App Service or AKS pod, the user-assigned one named by `AZURE_CLIENT_ID` if set.
+/// Requests go through the HTTP settings of the [`JsonRef`](crate::JsonRef), such as its host
+/// options.
+///
+/// ```no_run
+/// use jsonref::azure::Azure;
+/// use jsonref::JsonRef;
+/// use serde_json::json;
+///
+/// let mut jsonref = JsonRef::new();
+/// jsonref.add_layer(Azure::new().sas_token("sv=2022-11-02&ss=b&srt=o&sp=r&sig=..."));
+///
+/// let mut input = json!({"properties": {"order": {"$ref": "az://acmeschemas/orders/v3/order.json"}}});
+/// jsonref.deref_value(&mut input).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Azure {
+    /// The base URL of every account, such as that of an Azurite emulator.
+    endpoint: Option<String>,
+    auth: Auth,
+}
+
+#[derive(Debug, Clone)]
+enum Auth {
+    Anonymous,
+    Sas(String),
+    Token(Callback<dyn TokenProvider>),
+    /// The managed identity, the user-assigned one with the client id if given, with tokens
+    /// from the instance metadata service.
+    ManagedIdentity(Option<String>, TokenCache),
+}
+
+impl Azure {
+    pub fn new() -> Azure {
+        let auth = match env::var("AZURE_STORAGE_SAS_TOKEN") {
+            Ok(sas_token) => Auth::Sas(sas_token.trim_start_matches('?').to_owned()),
+            Err(_) => Auth::ManagedIdentity(env::var("AZURE_CLIENT_ID").ok(), TokenCache::default()),
+        };
+        Azure { endpoint: None, auth }
+    }
+
+    /// Send requests for every account to `endpoint`, such as
+    /// `http://127.0.0.1:10000/devstoreaccount1` for Azurite, rather than to
+    /// `https://{account}.blob.core.windows.net`.
+    pub fn endpoint(mut self, endpoint: &str) -> Azure {
+        self.endpoint = Some(endpoint.trim_end_matches('/').to_owned());
+        self
+    }
+
+    /// Authenticate with a shared access signature, the query string it is given as.
+    pub fn sas_token(mut self, sas_token: &str) -> Azure {
+        self.auth = Auth::Sas(sas_token.trim_start_matches('?').to_owned());
+        self
+    }
+
+    /// Authenticate with the managed identity, the user-assigned one with `client_id` if given.
+    pub fn managed_identity(mut self, client_id: Option<&str>) -> Azure {
+        self.auth = Auth::ManagedIdentity(client_id.map(str::to_owned), TokenCache::default());
+        self
+    }
+
+    /// Authenticate with Microsoft Entra ID tokens for `https://storage.azure.com/` from
+    /// `provider`.
+    pub fn token_provider<P: TokenProvider + 'static>(mut self, provider: P) -> Azure {
+        self.auth = Auth::Token(Callback(Arc::new(provider)));
+        self
+    }
+
+    /// Send requests without authentication, as for public containers.
+    pub fn anonymous(mut self) -> Azure {
+        self.auth = Auth::Anonymous;
+        self
+    }
+
+    /// The URL of the blob at `url`, `None` if it is not a blob URL.
+    fn blob_url(&self, url: &Url) -> Result<Option<Url>> {
+        let account = match (url.scheme(), url.host_str()) {
+            ("az", Some(account)) if !account.is_empty() => account,
+            ("az", _) => {
+                return Err(Error::UnsupportedUrl {
+                    url: url.to_string(),
+                    reason: "az urls need an account, as in `az://account/container/blob`".to_owned(),
+                })
+            }
+            ("https", Some(host)) if host.ends_with(".blob.core.windows.net") => return Ok(Some(url.clone())),
+            _ => return Ok(None),
+        };
+        let endpoint = match &self.endpoint {
+            Some(endpoint) => endpoint.clone(),
+            None => format!("https://{}.blob.core.windows.net", account),
+        };
+        let blob_url = format!("{}{}", endpoint, url.path());
+        Url::parse(&blob_url).map(Some).context(UrlParseError {url: blob_url})
+    }
+}
+
+impl Default for Azure {
+    fn default() -> Azure {
+        Azure::new()
+    }
+}
+
+impl Layer for Azure {
+    fn retrieve(&self, url: &Url, next: Next<'_>) -> Result<Value> {
+        let mut blob_url = match self.blob_url(url)? {
+            Some(blob_url) => blob_url,
+            None => return next.run(url),
+        };
+        if let Auth::Sas(sas_token) = &self.auth {
+            let query = match blob_url.query() {
+                Some(query) => format!("{}&{}", query, sas_token),
+                None => sas_token.clone(),
+            };
+            blob_url.set_query(Some(&query));
+        }
+        let http = next.http();
+        let token = match &self.auth {
+            Auth::Anonymous | Auth::Sas(_) => None,
+            Auth::Token(provider) => provider.0.token(url)?,
+            Auth::ManagedIdentity(client_id, token) => Some(token.get(|| managed_identity_token(http, client_id.as_deref()))?),
+        };
+        let authorization = token.map(|token| format!("Bearer {}", token));
+        let headers: Vec<(&str, &str)> = match &authorization {
+            Some(authorization) => vec![("Authorization", authorization), ("x-ms-version", API_VERSION)],
+            None => vec![],
+        };
+        let response = http.get_with_headers(blob_url.as_str(), &headers)?;
+        parse(response.into_reader(), url.as_str())
+    }
+}
+
+/// A token of the managed identity, the user-assigned one with `client_id` if given, from the
+/// instance metadata service.
+fn managed_identity_token(http: &Http, client_id: Option<&str>) -> Result<(String, Duration)> {
+    let mut token_url = "http://169.254.169.254/metadata/identity/oauth2/token?api-version=2018-02-01&resource=https%3A%2F%2Fstorage.azure.com%2F".to_owned();
+    if let Some(client_id) = client_id {
+        token_url.push_str(&format!("&client_id={}", client_id));
+    }
+    let response: Value = http
+        .get_with_headers(&token_url, &[("Metadata", "true")])?
+        .into_json()
+        .context(SchemaNotJson {url: token_url.as_str()})?;
+    token_response(&response, &token_url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Azure;
+    use crate::http::tests::serve;
+    use crate::http::HostOptions;
+    use crate::JsonRef;
+    use serde_json::json;
+
+    #[test]
+    fn blobs_with_sas_token() {
+        let (base, requests) = serve(r#"{"properties": {"id": {"$ref": "id.json"}}, "type": "integer"}"#, 2);
+        let mut jsonref = JsonRef::new();
+        jsonref.add_layer(Azure::new().endpoint(&format!("{}/devstoreaccount1", base)).sas_token("?sv=2022-11-02&sig=abc"));
+        jsonref.set_host_options("127.0.0.1", HostOptions::new().header("X-Scope", "host"));
+
+        let mut input = json!({"$ref": "az://devstoreaccount1/schemas/orders/order.json"});
+        jsonref.deref_value(&mut input).unwrap();
+        assert_eq!(input["properties"]["id"]["type"], json!("integer"));
+
+        let first = requests.recv().unwrap();
+        assert_eq!(first[0], "GET /devstoreaccount1/schemas/orders/order.json?sv=2022-11-02&sig=abc HTTP/1.1");
+        assert!(first.iter().any(|line| line == "X-Scope: host"));
+        assert_eq!(requests.recv().unwrap()[0], "GET /devstoreaccount1/schemas/orders/id.json?sv=2022-11-02&sig=abc HTTP/1.1");
+    }
+}
@@ -0,0 +1,168 @@
+//! Dereferencing from build scripts.
+//!
+//! ```no_run
+//! // In build.rs
+//! jsonref::build::Build::new()
+//!     .schema("schemas/base.json")
+//!     .schema("schemas/release.json")
+//!     .run()
+//!     .unwrap();
+//! ```
+//!
+//! The dereferenced schemas can then be embedded with
+//! `include_str!(concat!(env!("OUT_DIR"), "/base.json"))`.
+
+use crate::{JSONRefError, JsonRef, Result, WriteOutput};
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Dereferences a set of schema files into a directory, by default `OUT_DIR`, and tells cargo to
+/// rerun the build script when any file read while dereferencing changes.
+#[derive(Debug)]
+pub struct Build {
+    jsonref: JsonRef,
+    schemas: Vec<PathBuf>,
+    out_dir: Option<PathBuf>,
+    pretty: bool,
+}
+
+impl Default for Build {
+    fn default() -> Build {
+        Build::new()
+    }
+}
+
+impl Build {
+    /// Create a build with the default [`JsonRef`] options.
+    pub fn new() -> Build {
+        Build {
+            jsonref: JsonRef::new(),
+            schemas: vec![],
+            out_dir: None,
+            pretty: false,
+        }
+    }
+
+    /// The [`JsonRef`] used to dereference, to set its options.
+    pub fn jsonref(&mut self) -> &mut JsonRef {
+        &mut self.jsonref
+    }
+
+    /// Add a schema file to dereference. Its output has the same file name, so two schemas with
+    /// the same file name make [`Build::run`] fail.
+    pub fn schema<P: AsRef<Path>>(&mut self, path: P) -> &mut Build {
+        self.schemas.push(path.as_ref().to_owned());
+        self
+    }
+
+    /// Write the outputs to `out_dir` instead of `OUT_DIR`.
+    pub fn out_dir<P: AsRef<Path>>(&mut self, out_dir: P) -> &mut Build {
+        self.out_dir = Some(out_dir.as_ref().to_owned());
+        self
+    }
+
+    /// Write indented rather than compact JSON.
+    pub fn pretty(&mut self, pretty: bool) -> &mut Build {
+        self.pretty = pretty;
+        self
+    }
+
+    /// Dereference every schema, write the outputs and print the `cargo:rerun-if-changed` lines.
+    /// Returns the paths written.
+    pub fn run(&mut self) -> Result<Vec<PathBuf>> {
+        self.run_with_output(&mut io::stdout())
+    }
+
+    fn run_with_output(&mut self, cargo_output: &mut dyn io::Write) -> Result<Vec<PathBuf>> {
+        let out_dir = match &self.out_dir {
+            Some(out_dir) => out_dir.clone(),
+            None => env::var_os("OUT_DIR")
+                .map(PathBuf::from)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "OUT_DIR is not set"))
+                .context(JSONRefError {})?,
+        };
+        let mut outputs: HashMap<PathBuf, &Path> = HashMap::new();
+        for schema in &self.schemas {
+            let path = out_dir.join(schema.file_name().unwrap_or(schema.as_os_str()));
+            if let Some(first) = outputs.insert(path.clone(), schema) {
+                let message = format!("both {} and {} would be written here", first.display(), schema.display());
+                let error = io::Error::new(io::ErrorKind::AlreadyExists, message);
+                return Err(error).context(WriteOutput { path: path.to_string_lossy() });
+            }
+        }
+        fs::create_dir_all(&out_dir).context(WriteOutput { path: out_dir.to_string_lossy() })?;
+
+        let mut written = vec![];
+        for schema in &self.schemas {
+            let output = self.jsonref.deref_file(&schema.to_string_lossy())?;
+            let path = out_dir.join(schema.file_name().unwrap_or(schema.as_os_str()));
+            let json = if self.pretty {
+                serde_json::to_vec_pretty(&output)
+            } else {
+                serde_json::to_vec(&output)
+            }
+            .map_err(io::Error::from)
+            .context(WriteOutput { path: path.to_string_lossy() })?;
+            fs::write(&path, json).context(WriteOutput { path: path.to_string_lossy() })?;
+            written.push(path);
+        }
+
+        for url in self.jsonref.documents() {
+            let file = Url::parse(&url).ok().and_then(|url| url.to_file_path().ok());
+            if let Some(file) = file.filter(|file| file.exists()) {
+                writeln!(cargo_output, "cargo:rerun-if-changed={}", file.display())
+                    .context(JSONRefError {})?;
+            }
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Build;
+    use std::fs;
+
+    #[test]
+    fn writes_outputs_and_rerun_lines() {
+        let out_dir = std::env::temp_dir().join(format!("jsonref-build-{}", std::process::id()));
+
+        let mut cargo_output = vec![];
+        let written = Build::new()
+            .schema("fixtures/nested_relative/base.json")
+            .out_dir(&out_dir)
+            .run_with_output(&mut cargo_output)
+            .unwrap();
+
+        assert_eq!(written, vec![out_dir.join("base.json")]);
+        let output: serde_json::Value = serde_json::from_slice(&fs::read(&written[0]).unwrap()).unwrap();
+        assert_eq!(output["properties"]["prop3"]["title"], "title from url");
+
+        let cargo_output = String::from_utf8(cargo_output).unwrap();
+        let lines: Vec<&str> = cargo_output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("cargo:rerun-if-changed=/") && lines[0].ends_with("base.json"));
+        assert!(lines[1].ends_with("other.json"));
+
+        fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[test]
+    fn duplicate_output_names() {
+        let out_dir = std::env::temp_dir().join(format!("jsonref-build-duplicate-{}", std::process::id()));
+
+        let result = Build::new()
+            .schema("fixtures/nested_relative/base.json")
+            .schema("fixtures/nested/base.json")
+            .out_dir(&out_dir)
+            .run_with_output(&mut vec![]);
+
+        assert!(result.unwrap_err().to_string().contains("fixtures/nested/base.json"));
+        assert!(!out_dir.exists());
+    }
+}
@@ -0,0 +1,393 @@
+//! Configuring a [`JsonRef`] in one expression.
+
+use crate::{BundleNaming, DefinitionsKeyword, IdPolicy, JsonRef, NameCollision, RecursionPolicy, RefScope, RefSite, TraversalPolicy, VocabularyPolicy};
+use crate::http::HostOptions;
+use crate::resolve::Layer;
+use crate::transform::Pass;
+use serde_json::Value;
+use std::path::Path;
+use std::time::Duration;
+
+/// Builds a [`JsonRef`] with chained calls, one for each of its `set_` methods and named the
+/// same without the prefix.
+///
+/// ```
+/// use jsonref::{JsonRef, RefScope};
+/// use serde_json::json;
+///
+/// let mut jsonref = JsonRef::builder()
+///     .ref_scope(RefScope::Internal)
+///     .strip_keywords(&["$comment"])
+///     .max_depth(5)
+///     .build();
+///
+/// let mut input = json!(
+///     {"$defs": {"a": {"type": "string", "$comment": "internal"}},
+///      "properties": {"a": {"$ref": "#/$defs/a"}, "b": {"$ref": "b.json"}}}
+/// );
+/// jsonref.deref_value(&mut input).unwrap();
+///
+/// assert_eq!(input["properties"]["a"], json!({"type": "string"}));
+/// assert_eq!(input["properties"]["b"], json!({"$ref": "b.json"}));
+/// ```
+#[derive(Debug)]
+pub struct JsonRefBuilder {
+    jsonref: JsonRef,
+}
+
+impl Default for JsonRefBuilder {
+    fn default() -> JsonRefBuilder {
+        JsonRefBuilder::new()
+    }
+}
+
+impl JsonRefBuilder {
+    /// Start from the default options, as [`JsonRef::new`].
+    pub fn new() -> JsonRefBuilder {
+        JsonRefBuilder { jsonref: JsonRef::new() }
+    }
+
+    /// The configured [`JsonRef`].
+    pub fn build(self) -> JsonRef {
+        self.jsonref
+    }
+
+    /// See [`JsonRef::set_reference_key`].
+    pub fn reference_key(mut self, reference_key: &str) -> JsonRefBuilder {
+        self.jsonref.set_reference_key(reference_key);
+        self
+    }
+
+    /// See [`JsonRef::set_max_depth`].
+    pub fn max_depth(mut self, max_depth: usize) -> JsonRefBuilder {
+        self.jsonref.set_max_depth(max_depth);
+        self
+    }
+
+    /// See [`JsonRef::set_absolute_refs`].
+    pub fn absolute_refs(mut self, absolute_refs: bool) -> JsonRefBuilder {
+        self.jsonref.set_absolute_refs(absolute_refs);
+        self
+    }
+
+    /// See [`JsonRef::set_bundle_threshold`].
+    pub fn bundle_threshold(mut self, bundle_threshold: usize) -> JsonRefBuilder {
+        self.jsonref.set_bundle_threshold(bundle_threshold);
+        self
+    }
+
+    /// See [`JsonRef::set_ref_filter`].
+    pub fn ref_filter<F>(mut self, filter: F) -> JsonRefBuilder
+    where
+        F: Fn(&RefSite) -> bool + Send + Sync + 'static,
+    {
+        self.jsonref.set_ref_filter(filter);
+        self
+    }
+
+    /// See [`JsonRef::set_url_rewriter`].
+    pub fn url_rewriter<F>(mut self, rewriter: F) -> JsonRefBuilder
+    where
+        F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+    {
+        self.jsonref.set_url_rewriter(rewriter);
+        self
+    }
+
+    /// See [`JsonRef::set_ref_resolver`].
+    pub fn ref_resolver<F>(mut self, resolver: F) -> JsonRefBuilder
+    where
+        F: Fn(&RefSite) -> Option<Value> + Send + Sync + 'static,
+    {
+        self.jsonref.set_ref_resolver(resolver);
+        self
+    }
+
+    /// See [`JsonRef::set_inline_hook`].
+    pub fn inline_hook<F>(mut self, hook: F) -> JsonRefBuilder
+    where
+        F: Fn(&mut Value, &RefSite) + Send + Sync + 'static,
+    {
+        self.jsonref.set_inline_hook(hook);
+        self
+    }
+
+    /// See [`JsonRef::add_layer`].
+    pub fn layer<L: Layer + 'static>(mut self, layer: L) -> JsonRefBuilder {
+        self.jsonref.add_layer(layer);
+        self
+    }
+
+    /// See [`JsonRef::add_pass`].
+    pub fn pass<P: Pass + 'static>(mut self, pass: P) -> JsonRefBuilder {
+        self.jsonref.add_pass(pass);
+        self
+    }
+
+    /// See [`JsonRef::set_codegen_profile`].
+    pub fn codegen_profile(mut self, codegen: bool) -> JsonRefBuilder {
+        self.jsonref.set_codegen_profile(codegen);
+        self
+    }
+
+    /// See [`JsonRef::set_ref_scope`].
+    pub fn ref_scope(mut self, ref_scope: RefScope) -> JsonRefBuilder {
+        self.jsonref.set_ref_scope(ref_scope);
+        self
+    }
+
+    /// See [`JsonRef::set_flatten_all_of`].
+    pub fn flatten_all_of(mut self, flatten_all_of: bool) -> JsonRefBuilder {
+        self.jsonref.set_flatten_all_of(flatten_all_of);
+        self
+    }
+
+    /// See [`JsonRef::set_source_key`].
+    pub fn source_key(mut self, source_key: &str) -> JsonRefBuilder {
+        self.jsonref.set_source_key(source_key);
+        self
+    }
+
+    /// See [`JsonRef::set_openapi`].
+    pub fn openapi(mut self, openapi: bool) -> JsonRefBuilder {
+        self.jsonref.set_openapi(openapi);
+        self
+    }
+
+    /// See [`JsonRef::set_openrpc`].
+    pub fn openrpc(mut self, openrpc: bool) -> JsonRefBuilder {
+        self.jsonref.set_openrpc(openrpc);
+        self
+    }
+
+    /// See [`JsonRef::set_keep_components`].
+    pub fn keep_components(mut self, keep_components: bool) -> JsonRefBuilder {
+        self.jsonref.set_keep_components(keep_components);
+        self
+    }
+
+    /// See [`JsonRef::set_crd_structural`].
+    pub fn crd_structural(mut self, crd_structural: bool) -> JsonRefBuilder {
+        self.jsonref.set_crd_structural(crd_structural);
+        self
+    }
+
+    /// See [`JsonRef::set_bundle_naming`].
+    pub fn bundle_naming(mut self, bundle_naming: BundleNaming) -> JsonRefBuilder {
+        self.jsonref.set_bundle_naming(bundle_naming);
+        self
+    }
+
+    /// See [`JsonRef::set_name_collision`].
+    pub fn name_collision(mut self, name_collision: NameCollision) -> JsonRefBuilder {
+        self.jsonref.set_name_collision(name_collision);
+        self
+    }
+
+    /// See [`JsonRef::set_deterministic`].
+    pub fn deterministic(mut self, deterministic: bool) -> JsonRefBuilder {
+        self.jsonref.set_deterministic(deterministic);
+        self
+    }
+
+    /// See [`JsonRef::set_cache_ttl`].
+    pub fn cache_ttl(mut self, ttl: Duration) -> JsonRefBuilder {
+        self.jsonref.set_cache_ttl(ttl);
+        self
+    }
+
+    /// See [`JsonRef::set_directive_key`].
+    pub fn directive_key(mut self, directive_key: &str) -> JsonRefBuilder {
+        self.jsonref.set_directive_key(directive_key);
+        self
+    }
+
+    /// See [`JsonRef::set_merge_extensions`].
+    pub fn merge_extensions(mut self, merge_extensions: bool) -> JsonRefBuilder {
+        self.jsonref.set_merge_extensions(merge_extensions);
+        self
+    }
+
+    /// See [`JsonRef::set_id_policy`].
+    pub fn id_policy(mut self, id_policy: IdPolicy) -> JsonRefBuilder {
+        self.jsonref.set_id_policy(id_policy);
+        self
+    }
+
+    /// See [`JsonRef::set_recursion_policy`].
+    pub fn recursion_policy(mut self, recursion_policy: RecursionPolicy) -> JsonRefBuilder {
+        self.jsonref.set_recursion_policy(recursion_policy);
+        self
+    }
+
+    /// See [`JsonRef::set_traversal_policy`].
+    pub fn traversal_policy(mut self, traversal_policy: TraversalPolicy) -> JsonRefBuilder {
+        self.jsonref.set_traversal_policy(traversal_policy);
+        self
+    }
+
+    /// See [`JsonRef::set_vocabulary_policy`].
+    pub fn vocabulary_policy(mut self, vocabulary_policy: VocabularyPolicy) -> JsonRefBuilder {
+        self.jsonref.set_vocabulary_policy(vocabulary_policy);
+        self
+    }
+
+    /// See [`JsonRef::set_bundle_meta_schema`].
+    pub fn bundle_meta_schema(mut self, bundle_meta_schema: bool) -> JsonRefBuilder {
+        self.jsonref.set_bundle_meta_schema(bundle_meta_schema);
+        self
+    }
+
+    /// See [`JsonRef::set_json_reference`].
+    pub fn json_reference(mut self, json_reference: bool) -> JsonRefBuilder {
+        self.jsonref.set_json_reference(json_reference);
+        self
+    }
+
+    /// See [`JsonRef::set_check_data_pointers`].
+    pub fn check_data_pointers(mut self, check_data_pointers: bool) -> JsonRefBuilder {
+        self.jsonref.set_check_data_pointers(check_data_pointers);
+        self
+    }
+
+    /// See [`JsonRef::set_embed_non_json`].
+    pub fn embed_non_json(mut self, embed_non_json: bool) -> JsonRefBuilder {
+        self.jsonref.set_embed_non_json(embed_non_json);
+        self
+    }
+
+    /// See [`JsonRef::set_offline_meta_schemas`].
+    pub fn offline_meta_schemas(mut self, offline_meta_schemas: bool) -> JsonRefBuilder {
+        self.jsonref.set_offline_meta_schemas(offline_meta_schemas);
+        self
+    }
+
+    /// See [`JsonRef::set_strict_refs`].
+    pub fn strict_refs(mut self, strict_refs: bool) -> JsonRefBuilder {
+        self.jsonref.set_strict_refs(strict_refs);
+        self
+    }
+
+    /// See [`JsonRef::set_schema_registry`].
+    pub fn schema_registry(mut self, url: &str) -> JsonRefBuilder {
+        self.jsonref.set_schema_registry(url);
+        self
+    }
+
+    /// See [`JsonRef::set_host_options`].
+    pub fn host_options(mut self, scope: &str, options: HostOptions) -> JsonRefBuilder {
+        self.jsonref.set_host_options(scope, options);
+        self
+    }
+
+    /// See [`JsonRef::set_ambient_credentials`].
+    pub fn ambient_credentials(mut self, ambient_credentials: bool) -> JsonRefBuilder {
+        self.jsonref.set_ambient_credentials(ambient_credentials);
+        self
+    }
+
+    /// See [`JsonRef::set_netrc_default`].
+    pub fn netrc_default(mut self, netrc_default: bool) -> JsonRefBuilder {
+        self.jsonref.set_netrc_default(netrc_default);
+        self
+    }
+
+    /// See [`JsonRef::add_unix_socket`].
+    pub fn unix_socket<P: AsRef<Path>>(mut self, socket: P) -> JsonRefBuilder {
+        self.jsonref.add_unix_socket(socket);
+        self
+    }
+
+    /// See [`JsonRef::set_connection_pool`].
+    pub fn connection_pool(mut self, max_idle: usize, max_idle_per_host: usize) -> JsonRefBuilder {
+        self.jsonref.set_connection_pool(max_idle, max_idle_per_host);
+        self
+    }
+
+    /// See [`JsonRef::set_host_address`].
+    pub fn host_address(mut self, host: &str, address: std::net::IpAddr) -> JsonRefBuilder {
+        self.jsonref.set_host_address(host, address);
+        self
+    }
+
+    /// See [`JsonRef::set_mirror`].
+    pub fn mirror(mut self, prefix: &str, base: &str) -> JsonRefBuilder {
+        self.jsonref.set_mirror(prefix, base);
+        self
+    }
+
+    /// See [`JsonRef::set_schema_catalog`].
+    pub fn schema_catalog(mut self, url: &str) -> JsonRefBuilder {
+        self.jsonref.set_schema_catalog(url);
+        self
+    }
+
+    /// See [`JsonRef::set_strip_keywords`].
+    pub fn strip_keywords(mut self, keywords: &[&str]) -> JsonRefBuilder {
+        self.jsonref.set_strip_keywords(keywords);
+        self
+    }
+
+    /// See [`JsonRef::set_dedupe_threshold`].
+    pub fn dedupe_threshold(mut self, threshold: usize) -> JsonRefBuilder {
+        self.jsonref.set_dedupe_threshold(threshold);
+        self
+    }
+
+    /// See [`JsonRef::set_normalize_definitions`].
+    pub fn normalize_definitions(mut self, keyword: DefinitionsKeyword) -> JsonRefBuilder {
+        self.jsonref.set_normalize_definitions(keyword);
+        self
+    }
+
+    /// See [`JsonRef::set_migrate_drafts`].
+    pub fn migrate_drafts(mut self, migrate_drafts: bool) -> JsonRefBuilder {
+        self.jsonref.set_migrate_drafts(migrate_drafts);
+        self
+    }
+
+    /// See [`JsonRef::set_record_patch`].
+    pub fn record_patch(mut self, record_patch: bool) -> JsonRefBuilder {
+        self.jsonref.set_record_patch(record_patch);
+        self
+    }
+
+    /// See [`JsonRef::set_validate_meta_schema`].
+    #[cfg(feature = "jsonschema")]
+    pub fn validate_meta_schema(mut self, validate_meta_schema: bool) -> JsonRefBuilder {
+        self.jsonref.set_validate_meta_schema(validate_meta_schema);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::JsonRef;
+    use serde_json::json;
+
+    #[test]
+    fn same_as_setters() {
+        let input = json!(
+            {"$defs": {"a": {"title": "a", "x-a": 1}},
+             "properties": {"a": {"$ref": "#/$defs/a"}, "b": {"$ref": "#/$defs/a", "x-b": 2}}}
+        );
+
+        let mut built = JsonRef::builder()
+            .merge_extensions(true)
+            .strip_keywords(&["title"])
+            .source_key("x-source")
+            .build();
+        let mut built_output = input.clone();
+        built.deref_value(&mut built_output).unwrap();
+
+        let mut set = JsonRef::new();
+        set.set_merge_extensions(true);
+        set.set_strip_keywords(&["title"]);
+        set.set_source_key("x-source");
+        let mut set_output = input;
+        set.deref_value(&mut set_output).unwrap();
+
+        assert_eq!(built_output, set_output);
+        assert_eq!(built_output["properties"]["b"]["x-b"], 2);
+    }
+}
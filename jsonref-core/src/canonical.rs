@@ -0,0 +1,149 @@
+//! Canonical serialization following the
+//! [JSON Canonicalization Scheme (RFC 8785)](https://www.rfc-editor.org/rfc/rfc8785), so the
+//! same dereferenced schema always serializes to the same bytes and can be hashed or signed.
+
+use serde_json::Value;
+use std::fmt::Write;
+
+/// Serialize `value` canonically: no whitespace, object keys sorted by their UTF-16 code units,
+/// numbers formatted as ECMAScript does and only the required characters escaped in strings.
+///
+/// ```
+/// use serde_json::json;
+///
+/// let value = json!({"b": [1.50, 1e30, "\u{20ac}"], "a": null});
+///
+/// assert_eq!(jsonref::canonical::to_string(&value), r#"{"a":null,"b":[1.5,1e+30,"€"]}"#);
+/// ```
+pub fn to_string(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(value, &mut out);
+    out
+}
+
+/// Serialize `value` canonically as UTF-8 bytes. See [`to_string`].
+pub fn to_vec(value: &Value) -> Vec<u8> {
+    to_string(value).into_bytes()
+}
+
+fn write_value(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(true) => out.push_str("true"),
+        Value::Bool(false) => out.push_str("false"),
+        Value::Number(number) => out.push_str(&format_number(number.as_f64().unwrap_or(0.0))),
+        Value::String(string) => write_string(string, out),
+        Value::Array(array) => {
+            out.push('[');
+            for (index, item) in array.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write_value(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(obj) => {
+            let mut entries: Vec<(&String, &Value)> = obj.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            out.push('{');
+            for (index, (key, item)) in entries.into_iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write_string(key, out);
+                out.push(':');
+                write_value(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_string(string: &str, out: &mut String) {
+    out.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{08}' => out.push_str("\\b"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\u{0c}' => out.push_str("\\f"),
+            '\r' => out.push_str("\\r"),
+            c if c < ' ' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Format a number the way ECMAScript's `Number.prototype.toString` does.
+fn format_number(number: f64) -> String {
+    if number == 0.0 {
+        return "0".to_owned();
+    }
+    if number < 0.0 {
+        return format!("-{}", format_number(-number));
+    }
+
+    // Rust gives the shortest digits that round trip, e.g. `1.2345e3`.
+    let scientific = format!("{:e}", number);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let k = digits.len() as i32;
+    let n = exponent + 1;
+
+    if k <= n && n <= 21 {
+        format!("{}{}", digits, "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        format!("{}.{}", &digits[..n as usize], &digits[n as usize..])
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{}", "0".repeat(-n as usize), digits)
+    } else {
+        let sign = if n >= 1 { "+" } else { "-" };
+        if k == 1 {
+            format!("{}e{}{}", digits, sign, (n - 1).abs())
+        } else {
+            format!("{}.{}e{}{}", &digits[..1], &digits[1..], sign, (n - 1).abs())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_number, to_string};
+    use serde_json::Value;
+
+    #[test]
+    fn rfc_8785_example() {
+        let input: Value = serde_json::from_str(
+            r#"{"numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001],
+                "string": "\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/",
+                "literals": [null, true, false]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            to_string(&input),
+            r#"{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/"}"#
+        );
+    }
+
+    #[test]
+    fn numbers_and_key_order() {
+        assert_eq!(format_number(100.0), "100");
+        assert_eq!(format_number(1e21), "1e+21");
+        assert_eq!(format_number(1e20), "100000000000000000000");
+        assert_eq!(format_number(0.000001), "0.000001");
+        assert_eq!(format_number(1e-7), "1e-7");
+        assert_eq!(format_number(-1.25), "-1.25");
+
+        // U+FB33 sorts before U+1F600 by UTF-16 code units but after it by code points.
+        let input: Value = serde_json::from_str(r#"{"😀": 1, "דּ": 2, "a": 3}"#).unwrap();
+        assert_eq!(to_string(&input), "{\"a\":3,\"\u{1f600}\":1,\"\u{fb33}\":2}");
+    }
+}
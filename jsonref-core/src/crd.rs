@@ -0,0 +1,180 @@
+//! Kubernetes CustomResourceDefinition structural schemas, see
+//! [`JsonRef::set_crd_structural`](crate::JsonRef::set_crd_structural).
+
+use crate::schema::pointer_keywords;
+use crate::walk::walk_mut;
+use crate::Warning;
+use serde_json::{json, Value};
+
+/// Keywords CRDs do not support that can be dropped without changing what is valid.
+const ANNOTATIONS: &[&str] = &[
+    "$schema", "$id", "id", "$anchor", "$dynamicAnchor", "$defs", "definitions", "$comment", "$vocabulary",
+    "deprecated", "readOnly", "writeOnly", "contentMediaType", "contentEncoding",
+];
+
+/// Keywords CRDs do not support whose validation is lost when they are dropped.
+const UNSUPPORTED: &[&str] = &[
+    "$ref", "$dynamicRef", "prefixItems", "additionalItems", "contains", "minContains", "maxContains", "if",
+    "then", "else", "propertyNames", "patternProperties", "dependencies", "dependentRequired",
+    "dependentSchemas", "unevaluatedItems", "unevaluatedProperties",
+];
+
+/// Rewrite the dereferenced `schema` as a structural schema, as the `openAPIV3Schema` of a
+/// CRD needs. Keywords with a CRD equivalent are converted: `const` to a one value `enum`,
+/// `examples` to `example`, numeric `exclusiveMinimum`/`exclusiveMaximum` to the boolean form
+/// (or dropped if `minimum`/`maximum` is already stricter), a `null` type to `nullable` and `integer` or `string` to `x-kubernetes-int-or-string`.
+/// Missing types are inferred from `properties` and `items`. Other keywords CRDs do not
+/// support are removed, and a [`Warning::NotStructural`] returned for each that validated
+/// something, and for each schema that still has no type.
+///
+/// ```
+/// use serde_json::json;
+///
+/// let mut schema = json!(
+///     {"properties": {"replicas": {"type": ["integer", "null"], "exclusiveMinimum": 0},
+///                     "port": {"type": ["integer", "string"]},
+///                     "mode": {"const": "fast", "$comment": "only one for now"},
+///                     "tags": {"items": {"type": "string"}, "contains": {"const": "main"}}}}
+/// );
+///
+/// let warnings = jsonref::crd::structural(&mut schema);
+///
+/// assert_eq!(schema, json!(
+///     {"type": "object",
+///      "properties": {"replicas": {"type": "integer", "nullable": true, "minimum": 0, "exclusiveMinimum": true},
+///                     "port": {"x-kubernetes-int-or-string": true},
+///                     "mode": {"type": "string", "enum": ["fast"]},
+///                     "tags": {"type": "array", "items": {"type": "string"}}}}
+/// ));
+/// assert_eq!(warnings[0].to_string(), "schema at /properties/tags can not be structural: contains is not supported");
+/// ```
+pub fn structural(schema: &mut Value) -> Vec<Warning> {
+    let mut warnings = vec![];
+    walk_mut(schema, |pointer, subschema| {
+        let obj = match subschema.as_object_mut() {
+            Some(obj) => obj,
+            None => return,
+        };
+        let mut not_structural = |reason: String| {
+            warnings.push(Warning::NotStructural {
+                pointer: pointer.to_owned(),
+                reason,
+            })
+        };
+
+        for keyword in ANNOTATIONS {
+            obj.remove(*keyword);
+        }
+        for keyword in UNSUPPORTED {
+            if obj.remove(*keyword).is_some() {
+                not_structural(format!("{} is not supported", keyword));
+            }
+        }
+        if obj.get("uniqueItems").is_some_and(|unique| unique != &Value::Bool(false)) {
+            not_structural("uniqueItems is not supported".to_owned());
+        }
+        obj.remove("uniqueItems");
+
+        if let Some(value) = obj.remove("const") {
+            if !obj.contains_key("type") {
+                if let Some(type_name) = type_of(&value) {
+                    obj.insert("type".to_owned(), json!(type_name));
+                }
+            }
+            obj.entry("enum").or_insert_with(|| json!([value]));
+        }
+        if let Some(Value::Array(mut examples)) = obj.remove("examples") {
+            if !examples.is_empty() && !obj.contains_key("example") {
+                obj.insert("example".to_owned(), examples.swap_remove(0));
+            }
+        }
+        // Of an exclusive and an inclusive bound, only the stricter is kept.
+        for (exclusive, inclusive, lower) in [("exclusiveMinimum", "minimum", true), ("exclusiveMaximum", "maximum", false)] {
+            if let Some(limit) = obj.get(exclusive).and_then(Value::as_f64) {
+                let bound = obj.get(inclusive).and_then(Value::as_f64);
+                if bound.is_some_and(|bound| if lower { bound > limit } else { bound < limit }) {
+                    obj.remove(exclusive);
+                } else {
+                    let limit = obj.insert(exclusive.to_owned(), Value::Bool(true));
+                    obj.insert(inclusive.to_owned(), limit.unwrap_or_default());
+                }
+            }
+        }
+
+        if let Some(Value::Array(types)) = obj.get("type").filter(|types| types.is_array()).cloned() {
+            obj.remove("type");
+            let mut types: Vec<&str> = types.iter().filter_map(Value::as_str).collect();
+            if types.contains(&"null") {
+                types.retain(|type_name| *type_name != "null");
+                obj.insert("nullable".to_owned(), Value::Bool(true));
+            }
+            types.sort_unstable();
+            match types[..] {
+                [type_name] => {
+                    obj.insert("type".to_owned(), json!(type_name));
+                }
+                ["integer", "string"] => {
+                    obj.insert("x-kubernetes-int-or-string".to_owned(), Value::Bool(true));
+                }
+                _ => not_structural(format!("type can not be one of {}", types.join(", "))),
+            }
+        }
+        if !obj.contains_key("type") {
+            if obj.contains_key("properties") || obj.contains_key("additionalProperties") {
+                obj.insert("type".to_owned(), json!("object"));
+            } else if obj.contains_key("items") {
+                obj.insert("type".to_owned(), json!("array"));
+            }
+        }
+
+        // Schemas under the logical keywords only add validation to the structure given
+        // outside them, so need no type.
+        let in_junctor = pointer_keywords(pointer)
+            .iter()
+            .any(|keyword| ["allOf", "anyOf", "oneOf", "not"].contains(keyword));
+        let untyped = ["x-kubernetes-int-or-string", "x-kubernetes-preserve-unknown-fields"]
+            .iter()
+            .any(|keyword| obj.get(*keyword) == Some(&Value::Bool(true)));
+        if !obj.contains_key("type") && !in_junctor && !untyped {
+            not_structural("it has no type".to_owned());
+        }
+    });
+    warnings
+}
+
+fn type_of(value: &Value) -> Option<&'static str> {
+    match value {
+        Value::String(_) => Some("string"),
+        Value::Bool(_) => Some("boolean"),
+        Value::Number(number) if number.is_f64() => Some("number"),
+        Value::Number(_) => Some("integer"),
+        Value::Object(_) => Some("object"),
+        Value::Array(_) => Some("array"),
+        Value::Null => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::structural;
+    use serde_json::json;
+
+    #[test]
+    fn keyword_tokens_and_stricter_bounds() {
+        let mut schema = json!(
+            {"type": "object",
+             "properties": {"notes": {"properties": {"text": {}}},
+                            "size": {"type": "number", "minimum": 5, "exclusiveMinimum": 0,
+                                     "maximum": 10, "exclusiveMaximum": 10}},
+             "allOf": [{"properties": {"text": {}}}]}
+        );
+
+        let warnings = structural(&mut schema);
+
+        assert_eq!(schema["properties"]["size"], json!(
+            {"type": "number", "minimum": 5, "maximum": 10, "exclusiveMaximum": true}
+        ));
+        let warnings: Vec<String> = warnings.iter().map(ToString::to_string).collect();
+        assert_eq!(warnings, ["schema at /properties/notes/properties/text can not be structural: it has no type"]);
+    }
+}
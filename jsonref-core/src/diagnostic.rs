@@ -0,0 +1,122 @@
+//! [`miette`] diagnostics for errors, showing the offending `$ref` in its source. Needs the
+//! `miette` feature.
+//!
+//! [`Error`] implements [`miette::Diagnostic`] with a code and, where there is something to say,
+//! help. Errors about a single `$ref`, such as those from [`JsonRef::check_file`], can be wrapped
+//! in a [`LocatedError`] with the text of their document to add a labelled snippet.
+//!
+//! [`JsonRef::check_file`]: crate::JsonRef::check_file
+
+use crate::{spans, BrokenRef, Error};
+use miette::{Diagnostic, LabeledSpan, NamedSource, SourceCode};
+use std::fmt;
+use std::ops::Range;
+
+impl Diagnostic for Error {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(format!("jsonref::{:?}", self.kind()).to_lowercase()))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        let help = match self {
+            Error::SchemaFromUrl { .. } if self.kind().is_retryable() => "the server could not be reached, trying again may work",
+            Error::SchemaFromFile { .. } => "relative refs are resolved against the document containing them, or its `$id`",
+            Error::JsonPointerNotFound { .. } => "the part of the ref after `#` must point at a value in the document",
+            Error::RefCycle { .. } => "break the cycle, or set RecursionPolicy::Truncate to stop at it",
+            Error::UnsupportedVocabulary { .. } => "set VocabularyPolicy::Warn to dereference it anyway",
+            Error::SchemaWithoutId => "add an `$id`, or give the URL with JsonRef::register_at",
+            _ => return None,
+        };
+        Some(Box::new(help))
+    }
+}
+
+/// An error about a `$ref` with the text of the document containing it, so that it is shown with
+/// the `$ref` labelled.
+///
+/// ```
+/// use jsonref::diagnostic::LocatedError;
+/// use jsonref::JsonRef;
+///
+/// let source = r##"{"properties": {"name": {"$ref": "#/$defs/name"}}}"##;
+/// let mut jsonref = JsonRef::new();
+/// let broken = jsonref.check_value(&serde_json::from_str(source).unwrap(), "file:///schema.json");
+///
+/// let error = LocatedError::from_broken_ref(broken.into_iter().next().unwrap(), source.to_owned());
+/// eprintln!("{:?}", miette::Report::new(error));
+/// ```
+#[derive(Debug)]
+pub struct LocatedError {
+    error: Error,
+    source_code: NamedSource<String>,
+    span: Option<Range<usize>>,
+}
+
+impl LocatedError {
+    /// Locate `error`, about the `$ref` in the object at `pointer`, in `source`, the text of the
+    /// document called `name`. If the `$ref` can not be found there is no label.
+    pub fn new(error: Error, name: &str, source: String, pointer: &str) -> LocatedError {
+        let span = spans::span(&source, &format!("{}/$ref", pointer));
+        LocatedError {
+            error,
+            source_code: NamedSource::new(name, source),
+            span,
+        }
+    }
+
+    /// Locate a [`BrokenRef`] in `source`, the text of its document.
+    pub fn from_broken_ref(broken: BrokenRef, source: String) -> LocatedError {
+        LocatedError::new(broken.error, &broken.document, source, &broken.pointer)
+    }
+
+    /// The error being located.
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+}
+
+impl fmt::Display for LocatedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for LocatedError {}
+
+impl Diagnostic for LocatedError {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.error.code()
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.error.help()
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.source_code)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let span = self.span.clone()?;
+        Some(Box::new(std::iter::once(LabeledSpan::at(span, "this ref"))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LocatedError;
+    use crate::JsonRef;
+    use miette::Diagnostic;
+
+    #[test]
+    fn labels_the_ref() {
+        let source = "{\n  \"properties\": {\n    \"a\": {\"$ref\": \"#/missing\"}\n  }\n}";
+        let mut jsonref = JsonRef::new();
+        let broken = jsonref.check_value(&serde_json::from_str(source).unwrap(), "file:///schema.json");
+        let error = LocatedError::from_broken_ref(broken.into_iter().next().unwrap(), source.to_owned());
+
+        assert_eq!(error.code().unwrap().to_string(), "jsonref::resolution");
+        let label = error.labels().unwrap().next().unwrap();
+        assert_eq!(&source[label.offset()..label.offset() + label.len()], "\"#/missing\"");
+    }
+}
@@ -0,0 +1,134 @@
+//! A C API for dereferencing from other languages. Needs the `ffi` feature.
+//!
+//! Build a library to link against with
+//! `cargo rustc --release --features ffi --crate-type cdylib` (or `staticlib`), and include
+//! `include/jsonref.h`. Every string returned must be freed with `jsonref_free`.
+
+use crate::JsonRef;
+use serde_json::Value;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+
+/// The schema was dereferenced and the output is the dereferenced JSON.
+pub const JSONREF_OK: c_int = 0;
+/// A pointer argument was null or a string was not UTF-8.
+pub const JSONREF_INVALID_ARGUMENT: c_int = 1;
+/// The input was not valid JSON.
+pub const JSONREF_INVALID_JSON: c_int = 2;
+/// Dereferencing failed, for example because a `$ref` could not be found.
+pub const JSONREF_DEREF_ERROR: c_int = 3;
+/// jsonref panicked, which is a bug in jsonref.
+pub const JSONREF_PANIC: c_int = 4;
+
+/// Dereference the UTF-8 JSON schema in `input`, `input_len` bytes long. Relative refs are
+/// resolved against the NUL terminated `base_url`, or the current directory if it is null.
+///
+/// Returns one of the `JSONREF_` codes. Unless the code is `JSONREF_INVALID_ARGUMENT` because
+/// `output` is null, `*output` is set to a NUL terminated string: the dereferenced JSON on
+/// success, otherwise an error message. It must be freed with [`jsonref_free`].
+///
+/// # Safety
+///
+/// `input` must point to `input_len` readable bytes, `base_url` must be null or a NUL
+/// terminated string, and `output` must be null or valid for writing a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn jsonref_deref(
+    input: *const u8,
+    input_len: usize,
+    base_url: *const c_char,
+    output: *mut *mut c_char,
+) -> c_int {
+    if output.is_null() {
+        return JSONREF_INVALID_ARGUMENT;
+    }
+    // Unwinding into the caller's frames is undefined behaviour, and aborting would take the
+    // host process down with it.
+    let (code, message) = match panic::catch_unwind(AssertUnwindSafe(|| deref(input, input_len, base_url))) {
+        Ok(result) => result,
+        Err(payload) => {
+            let reason = payload
+                .downcast_ref::<&str>()
+                .map(|reason| reason.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            (JSONREF_PANIC, format!("jsonref panicked: {}", reason))
+        }
+    };
+    // Strings from serde_json and error messages have no interior NULs, but be safe.
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    *output = message.into_raw();
+    code
+}
+
+/// Free a string returned by [`jsonref_deref`]. Does nothing if `string` is null.
+///
+/// # Safety
+///
+/// `string` must be null or a pointer returned by `jsonref_deref` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn jsonref_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+unsafe fn deref(input: *const u8, input_len: usize, base_url: *const c_char) -> (c_int, String) {
+    if input.is_null() {
+        return (JSONREF_INVALID_ARGUMENT, "input is null".to_owned());
+    }
+    let input = match std::str::from_utf8(slice::from_raw_parts(input, input_len)) {
+        Ok(input) => input,
+        Err(error) => return (JSONREF_INVALID_ARGUMENT, format!("input is not UTF-8: {}", error)),
+    };
+    let base_url = if base_url.is_null() {
+        None
+    } else {
+        match CStr::from_ptr(base_url).to_str() {
+            Ok(base_url) => Some(base_url),
+            Err(error) => return (JSONREF_INVALID_ARGUMENT, format!("base_url is not UTF-8: {}", error)),
+        }
+    };
+
+    let mut value: Value = match serde_json::from_str(input) {
+        Ok(value) => value,
+        Err(error) => return (JSONREF_INVALID_JSON, error.to_string()),
+    };
+    let mut jsonref = JsonRef::new();
+    let result = match base_url {
+        Some(base_url) => jsonref.deref_value_with_url(&mut value, base_url),
+        None => jsonref.deref_value(&mut value),
+    };
+    match result {
+        Ok(()) => (JSONREF_OK, value.to_string()),
+        Err(error) => (JSONREF_DEREF_ERROR, error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{jsonref_deref, jsonref_free, JSONREF_DEREF_ERROR, JSONREF_INVALID_JSON, JSONREF_OK};
+    use std::ffi::CStr;
+    use std::ptr;
+
+    fn call(input: &str) -> (i32, String) {
+        let mut output = ptr::null_mut();
+        unsafe {
+            let code = jsonref_deref(input.as_ptr(), input.len(), ptr::null(), &mut output);
+            let message = CStr::from_ptr(output).to_str().unwrap().to_owned();
+            jsonref_free(output);
+            (code, message)
+        }
+    }
+
+    #[test]
+    fn deref_buffer() {
+        assert_eq!(
+            call(r##"{"properties": {"a": {"type": "string"}, "b": {"$ref": "#/properties/a"}}}"##),
+            (JSONREF_OK, r#"{"properties":{"a":{"type":"string"},"b":{"type":"string"}}}"#.to_owned())
+        );
+        assert_eq!(call("{").0, JSONREF_INVALID_JSON);
+        assert_eq!(call(r##"{"$ref": "#/missing"}"##).0, JSONREF_DEREF_ERROR);
+    }
+}
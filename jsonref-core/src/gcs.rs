@@ -0,0 +1,194 @@
+//! A [`Layer`] retrieving documents from Google Cloud Storage, for schemas kept there as build
+//! artifacts, without downloading them and rewriting the refs first. Needs the `gcs` feature.
+
+use crate::http::{token_response, Http, TokenCache, TokenProvider};
+use crate::resolve::{Layer, Next};
+use crate::{parse, Callback, Error, Result, SchemaNotJson, UrlParseError};
+use serde_json::Value;
+use snafu::ResultExt;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+
+/// Retrieve `gs://bucket/object` URLs from Google Cloud Storage, passing on any other. Refs
+/// relative to a document in a bucket stay in the bucket.
+///
+/// Requests are authenticated with, in order, the token provider set with
+/// [`Gcs::token_provider`], the access token in `GOOGLE_OAUTH_ACCESS_TOKEN`, or a token of the
+/// service account of the Compute Engine, GKE or Cloud Run instance from its metadata server.
+/// `STORAGE_EMULATOR_HOST`, such as `localhost:4443`, points them at an emulator, without
+/// authentication. Requests go through the HTTP settings of the [`JsonRef`](crate::JsonRef),
+/// such as its host options.
+///
+/// ```no_run
+/// use jsonref::gcs::Gcs;
+/// use jsonref::JsonRef;
+/// use serde_json::json;
+///
+/// let mut jsonref = JsonRef::new();
+/// jsonref.add_layer(Gcs::new());
+///
+/// let mut input = json!({"properties": {"order": {"$ref": "gs://acme-schemas/orders/v3/order.json"}}});
+/// jsonref.deref_value(&mut input).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Gcs {
+    endpoint: String,
+    auth: Auth,
+}
+
+#[derive(Debug, Clone)]
+enum Auth {
+    Anonymous,
+    Token(Callback<dyn TokenProvider>),
+    /// The service account of the instance, with tokens from its metadata server.
+    MetadataServer(TokenCache),
+}
+
+impl Gcs {
+    pub fn new() -> Gcs {
+        let emulator = env::var("STORAGE_EMULATOR_HOST").ok();
+        let auth = match (emulator.is_some(), env::var("GOOGLE_OAUTH_ACCESS_TOKEN")) {
+            (true, _) => Auth::Anonymous,
+            (false, Ok(token)) => Auth::Token(Callback(Arc::new(move |_: &Url| Ok(Some(token.clone()))))),
+            (false, Err(_)) => Auth::MetadataServer(TokenCache::default()),
+        };
+        Gcs {
+            endpoint: emulator.map_or_else(|| "https://storage.googleapis.com".to_owned(), |host| with_scheme(&host)),
+            auth,
+        }
+    }
+
+    /// Send requests to `endpoint`, such as a local emulator, rather than
+    /// `https://storage.googleapis.com`. Without a scheme, as in `localhost:4443`, it is sent
+    /// plain `http`.
+    pub fn endpoint(mut self, endpoint: &str) -> Gcs {
+        self.endpoint = with_scheme(endpoint);
+        self
+    }
+
+    /// Authenticate with tokens from `provider`.
+    pub fn token_provider<P: TokenProvider + 'static>(mut self, provider: P) -> Gcs {
+        self.auth = Auth::Token(Callback(Arc::new(provider)));
+        self
+    }
+
+    /// Send requests without authentication, as for public buckets.
+    pub fn anonymous(mut self) -> Gcs {
+        self.auth = Auth::Anonymous;
+        self
+    }
+
+    /// The JSON API URL of the media of the object at the `gs` URL `url`.
+    fn media_url(&self, url: &Url) -> Result<Url> {
+        let bucket = url.host_str().filter(|bucket| !bucket.is_empty()).ok_or_else(|| Error::UnsupportedUrl {
+            url: url.to_string(),
+            reason: "gs urls need a bucket, as in `gs://bucket/object`".to_owned(),
+        })?;
+        let object = url.path().trim_start_matches('/');
+        let mut media_url = Url::parse(&format!("{}/storage/v1/b/", self.endpoint)).context(UrlParseError {url: self.endpoint.clone()})?;
+        media_url
+            .path_segments_mut()
+            .map_err(|_| Error::UnsupportedUrl {url: self.endpoint.clone(), reason: "not a base url".to_owned()})?
+            .pop_if_empty()
+            .extend([bucket, "o", &decode(object)]);
+        media_url.set_query(Some("alt=media"));
+        Ok(media_url)
+    }
+}
+
+impl Default for Gcs {
+    fn default() -> Gcs {
+        Gcs::new()
+    }
+}
+
+impl Layer for Gcs {
+    fn retrieve(&self, url: &Url, next: Next<'_>) -> Result<Value> {
+        if url.scheme() != "gs" {
+            return next.run(url);
+        }
+        let media_url = self.media_url(url)?;
+        let http = next.http();
+        let token = match &self.auth {
+            Auth::Anonymous => None,
+            Auth::Token(provider) => provider.0.token(url)?,
+            Auth::MetadataServer(token) => Some(token.get(|| metadata_server_token(http))?),
+        };
+        let authorization = token.map(|token| format!("Bearer {}", token));
+        let headers: Vec<(&str, &str)> = authorization.iter().map(|value| ("Authorization", value.as_str())).collect();
+        let response = http.get_with_headers(media_url.as_str(), &headers)?;
+        parse(response.into_reader(), url.as_str())
+    }
+}
+
+/// A token of the instance's service account from the metadata server.
+fn metadata_server_token(http: &Http) -> Result<(String, Duration)> {
+    const TOKEN_URL: &str = "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+    let response: Value = http
+        .get_with_headers(TOKEN_URL, &[("Metadata-Flavor", "Google")])?
+        .into_json()
+        .context(SchemaNotJson {url: TOKEN_URL})?;
+    token_response(&response, TOKEN_URL)
+}
+
+/// `endpoint` without a trailing `/`, and with `http://` in front if it has no scheme, as
+/// `STORAGE_EMULATOR_HOST` often has not.
+fn with_scheme(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.contains("://") {
+        endpoint.to_owned()
+    } else {
+        format!("http://{}", endpoint)
+    }
+}
+
+/// `path` with its percent escapes decoded, as object names are sent encoded again.
+fn decode(path: &str) -> String {
+    let mut decoded = vec![];
+    let mut bytes = path.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let hex: Vec<u8> = bytes.clone().take(2).collect();
+            if let Some(escaped) = std::str::from_utf8(&hex).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(escaped);
+                bytes.nth(1);
+                continue;
+            }
+        }
+        decoded.push(byte);
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Gcs;
+    use crate::http::tests::serve;
+    use crate::http::HostOptions;
+    use crate::JsonRef;
+    use serde_json::json;
+    use std::env;
+
+    #[test]
+    fn gcs_objects() {
+        let (base, requests) = serve(r#"{"properties": {"id": {"$ref": "id.json"}}, "type": "integer"}"#, 2);
+        // Emulators are usually given as a bare host and port, and need no authentication.
+        env::set_var("STORAGE_EMULATOR_HOST", base.trim_start_matches("http://"));
+        let gcs = Gcs::new();
+        env::remove_var("STORAGE_EMULATOR_HOST");
+        let mut jsonref = JsonRef::new();
+        jsonref.add_layer(gcs);
+        jsonref.set_host_options("127.0.0.1", HostOptions::new().header("X-Scope", "host"));
+
+        let mut input = json!({"$ref": "gs://acme-schemas/orders/order%20v3.json"});
+        jsonref.deref_value(&mut input).unwrap();
+        assert_eq!(input["properties"]["id"]["type"], json!("integer"));
+
+        let first = requests.recv().unwrap();
+        assert_eq!(first[0], "GET /storage/v1/b/acme-schemas/o/orders%2Forder%20v3.json?alt=media HTTP/1.1");
+        assert!(first.iter().any(|line| line == "X-Scope: host"));
+        assert_eq!(requests.recv().unwrap()[0], "GET /storage/v1/b/acme-schemas/o/orders%2Fid.json?alt=media HTTP/1.1");
+    }
+}
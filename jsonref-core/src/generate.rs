@@ -0,0 +1,374 @@
+//! Generation of sample instances from dereferenced schemas, for filling test-data pipelines.
+//! Needs the `generate` feature.
+//!
+//! Honours `const`, `enum`, `type`, the common `format`s, length, range and item count limits,
+//! `required`, `allOf`, `anyOf` and `oneOf`. `pattern` and `$ref` are not followed, so schemas
+//! should be dereferenced first.
+
+use serde_json::{Map, Number, Value};
+
+/// Nesting beyond which only required properties and the minimum number of items are generated.
+const MAX_DEPTH: usize = 8;
+
+const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+/// Generates instances from schemas. The same seed always gives the same instances.
+///
+/// ```
+/// use jsonref::generate::Generator;
+/// use serde_json::json;
+///
+/// let schema = json!(
+///     {"type": "object",
+///      "required": ["id", "status"],
+///      "properties": {"id": {"type": "integer", "minimum": 1, "maximum": 9},
+///                     "status": {"enum": ["open", "closed"]},
+///                     "email": {"type": "string", "format": "email"}}}
+/// );
+///
+/// let instance = Generator::new(7).generate(&schema);
+///
+/// assert!((1..=9).contains(&instance["id"].as_i64().unwrap()));
+/// assert!(["open", "closed"].contains(&instance["status"].as_str().unwrap()));
+/// assert_eq!(instance, Generator::new(7).generate(&schema));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Generator {
+    state: u64,
+}
+
+impl Generator {
+    /// Create a generator from `seed`.
+    pub fn new(seed: u64) -> Generator {
+        Generator {
+            state: seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1,
+        }
+    }
+
+    /// Generate one instance of `schema`.
+    pub fn generate(&mut self, schema: &Value) -> Value {
+        self.instance(schema, 0)
+    }
+
+    fn next(&mut self) -> u64 {
+        // xorshift64*
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A number in `min..=max`.
+    fn between(&mut self, min: i64, max: i64) -> i64 {
+        if max <= min {
+            return min;
+        }
+        // The span of the whole `i64` range only fits in a wider type.
+        let span = (max as i128 - min as i128) as u128 + 1;
+        (min as i128 + (self.next() as u128 % span) as i128) as i64
+    }
+
+    fn choose<'a>(&mut self, values: &'a [Value]) -> Option<&'a Value> {
+        if values.is_empty() {
+            return None;
+        }
+        let index = self.between(0, values.len() as i64 - 1) as usize;
+        values.get(index)
+    }
+
+    fn instance(&mut self, schema: &Value, depth: usize) -> Value {
+        let obj = match schema {
+            Value::Object(obj) => obj,
+            _ => return Value::Null,
+        };
+
+        if let Some(value) = obj.get("const") {
+            return value.clone();
+        }
+        if let Some(value) = obj.get("enum").and_then(Value::as_array).and_then(|values| self.choose(values)) {
+            return value.clone();
+        }
+        if let Some(branches) = obj.get("allOf").and_then(Value::as_array) {
+            let mut merged = obj.clone();
+            merged.remove("allOf");
+            for branch in branches {
+                merge_into(&mut merged, branch);
+            }
+            return self.instance(&Value::Object(merged), depth);
+        }
+        for keyword in ["oneOf", "anyOf"] {
+            if let Some(branch) = obj.get(keyword).and_then(Value::as_array).and_then(|branches| self.choose(branches)) {
+                let mut merged = obj.clone();
+                merged.remove(keyword);
+                merge_into(&mut merged, branch);
+                return self.instance(&Value::Object(merged), depth);
+            }
+        }
+
+        match self.instance_type(obj).as_str() {
+            "object" => self.object(obj, depth),
+            "array" => self.array(obj, depth),
+            "integer" => Value::from(self.integer(obj)),
+            "number" => self.number(obj),
+            "boolean" => Value::Bool(self.next() & 1 == 0),
+            "null" => Value::Null,
+            _ => Value::String(self.string(obj)),
+        }
+    }
+
+    fn instance_type(&mut self, obj: &Map<String, Value>) -> String {
+        match obj.get("type") {
+            Some(Value::String(instance_type)) => return instance_type.clone(),
+            Some(Value::Array(types)) => {
+                // Prefer a type with content over `null`.
+                let non_null: Vec<Value> = types.iter().filter(|t| t.as_str() != Some("null")).cloned().collect();
+                let types = if non_null.is_empty() { types.clone() } else { non_null };
+                if let Some(Value::String(instance_type)) = self.choose(&types) {
+                    return instance_type.clone();
+                }
+            }
+            _ => {}
+        }
+        let has = |keywords: &[&str]| keywords.iter().any(|keyword| obj.contains_key(*keyword));
+        if has(&["properties", "required", "additionalProperties", "patternProperties"]) {
+            "object"
+        } else if has(&["items", "prefixItems", "minItems", "maxItems"]) {
+            "array"
+        } else if has(&["minimum", "maximum", "exclusiveMinimum", "exclusiveMaximum", "multipleOf"]) {
+            "number"
+        } else {
+            "string"
+        }
+        .to_owned()
+    }
+
+    fn object(&mut self, obj: &Map<String, Value>, depth: usize) -> Value {
+        let required: Vec<&str> = obj
+            .get("required")
+            .and_then(Value::as_array)
+            .map(|required| required.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+
+        let mut instance = Map::new();
+        if let Some(properties) = obj.get("properties").and_then(Value::as_object) {
+            for (name, property) in properties {
+                let include = required.contains(&name.as_str()) || (depth < MAX_DEPTH && self.next() & 1 == 0);
+                if include {
+                    instance.insert(name.clone(), self.instance(property, depth + 1));
+                }
+            }
+        }
+        for name in required {
+            if !instance.contains_key(name) {
+                instance.insert(name.to_owned(), self.instance(&Value::Object(Map::new()), depth + 1));
+            }
+        }
+        Value::Object(instance)
+    }
+
+    fn array(&mut self, obj: &Map<String, Value>, depth: usize) -> Value {
+        let min_items = limit(obj, "minItems").unwrap_or(0);
+        let max_items = if depth < MAX_DEPTH {
+            limit(obj, "maxItems").unwrap_or(min_items.max(1) + 2)
+        } else {
+            min_items
+        };
+        let count = self.between(min_items, max_items.max(min_items)) as usize;
+
+        let prefix = obj
+            .get("prefixItems")
+            .or_else(|| obj.get("items").filter(|items| items.is_array()))
+            .and_then(Value::as_array);
+        let rest = obj.get("items").filter(|items| items.is_object());
+
+        let mut instance = vec![];
+        for index in 0..count {
+            let item_schema = match prefix.and_then(|prefix| prefix.get(index)) {
+                Some(item_schema) => item_schema,
+                None if prefix.is_some() && rest.is_none() => break,
+                None => rest.unwrap_or(&Value::Null),
+            };
+            instance.push(match item_schema {
+                Value::Null => Value::String(self.string(&Map::new())),
+                item_schema => self.instance(item_schema, depth + 1),
+            });
+        }
+        Value::Array(instance)
+    }
+
+    fn integer(&mut self, obj: &Map<String, Value>) -> i64 {
+        let ((low, low_exclusive), (high, high_exclusive)) = bounds(obj);
+        let low = if low_exclusive { (low.floor() as i64).saturating_add(1) } else { low.ceil() as i64 };
+        let high = if high_exclusive { (high.ceil() as i64).saturating_sub(1) } else { high.floor() as i64 };
+        match obj.get("multipleOf").and_then(Value::as_i64).filter(|multiple| *multiple > 0) {
+            Some(multiple) => {
+                let low = (low as f64 / multiple as f64).ceil() as i64;
+                let high = (high as f64 / multiple as f64).floor() as i64;
+                // Bounds beyond `i64` are saturated, so the product may not fit; the largest
+                // multiple that does is used instead.
+                let value = self.between(low, high) as i128 * multiple as i128;
+                i64::try_from(value).unwrap_or(value.signum() as i64 * (i64::MAX / multiple) * multiple)
+            }
+            None => self.between(low, high),
+        }
+    }
+
+    fn number(&mut self, obj: &Map<String, Value>) -> Value {
+        if obj.get("multipleOf").is_some_and(Value::is_i64) {
+            return Value::from(self.integer(obj));
+        }
+        let ((low, _), (high, _)) = bounds(obj);
+        // Strictly between the bounds, so exclusive bounds need no special case.
+        let fraction = (self.next() % 999 + 1) as f64 / 1000.0;
+        Number::from_f64(low + (high - low) * fraction).map_or(Value::Null, Value::Number)
+    }
+
+    fn string(&mut self, obj: &Map<String, Value>) -> String {
+        let n = self.between(1, 9999);
+        match obj.get("format").and_then(Value::as_str) {
+            Some("date-time") => return format!("2024-{:02}-{:02}T{:02}:00:00Z", n % 12 + 1, n % 28 + 1, n % 24),
+            Some("date") => return format!("2024-{:02}-{:02}", n % 12 + 1, n % 28 + 1),
+            Some("time") => return format!("{:02}:{:02}:00Z", n % 24, n % 60),
+            Some("email") | Some("idn-email") => return format!("user{}@example.com", n),
+            Some("hostname") | Some("idn-hostname") => return format!("host{}.example.com", n),
+            Some("uri") | Some("iri") | Some("uri-reference") | Some("iri-reference") | Some("url") => {
+                return format!("https://example.com/{}", n)
+            }
+            Some("uuid") => {
+                let (high, low) = (self.next(), self.next());
+                return format!(
+                    "{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}",
+                    high >> 32,
+                    (high >> 16) & 0xffff,
+                    high & 0x0fff,
+                    (low >> 48) & 0x3fff | 0x8000,
+                    low & 0xffff_ffff_ffff
+                );
+            }
+            Some("ipv4") => return format!("192.0.2.{}", n % 255),
+            Some("ipv6") => return format!("2001:db8::{:x}", n),
+            _ => {}
+        }
+
+        let min_length = limit(obj, "minLength").unwrap_or(1);
+        let max_length = limit(obj, "maxLength").unwrap_or(min_length.max(1) + 9);
+        let length = self.between(min_length, max_length.max(min_length));
+        (0..length)
+            .map(|_| ALPHABET[self.between(0, ALPHABET.len() as i64 - 1) as usize] as char)
+            .collect()
+    }
+}
+
+/// The lower and upper bounds of a number schema, and whether each is exclusive. Handles both the
+/// draft-04 boolean and the later numeric `exclusiveMinimum`/`exclusiveMaximum`.
+fn bounds(obj: &Map<String, Value>) -> ((f64, bool), (f64, bool)) {
+    let bound = |inclusive: &str, exclusive: &str| match obj.get(exclusive) {
+        Some(Value::Number(number)) => number.as_f64().map(|number| (number, true)),
+        Some(Value::Bool(exclusive)) => obj.get(inclusive).and_then(Value::as_f64).map(|number| (number, *exclusive)),
+        _ => obj.get(inclusive).and_then(Value::as_f64).map(|number| (number, false)),
+    };
+    let low = bound("minimum", "exclusiveMinimum");
+    let high = bound("maximum", "exclusiveMaximum");
+    match (low, high) {
+        (Some(low), Some(high)) => (low, high),
+        (Some(low), None) => (low, (low.0.max(0.0) + 100.0, false)),
+        (None, Some(high)) => ((high.0.min(0.0) - 100.0, false), high),
+        (None, None) => ((0.0, false), (100.0, false)),
+    }
+}
+
+/// A non-negative integer keyword of `obj`.
+fn limit(obj: &Map<String, Value>, keyword: &str) -> Option<i64> {
+    obj.get(keyword).and_then(Value::as_i64).map(|limit| limit.max(0))
+}
+
+/// Combine the keywords of `branch` into `merged`, joining `properties` and `required`.
+fn merge_into(merged: &mut Map<String, Value>, branch: &Value) {
+    let branch = match branch.as_object() {
+        Some(branch) => branch,
+        None => return,
+    };
+    for (key, value) in branch {
+        match (key.as_str(), merged.get_mut(key), value) {
+            ("properties", Some(Value::Object(existing)), Value::Object(properties)) => {
+                for (name, property) in properties {
+                    existing.entry(name.clone()).or_insert_with(|| property.clone());
+                }
+            }
+            ("required", Some(Value::Array(existing)), Value::Array(required)) => {
+                for name in required {
+                    if !existing.contains(name) {
+                        existing.push(name.clone());
+                    }
+                }
+            }
+            (_, Some(_), _) => {}
+            (_, None, _) => {
+                merged.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Generator;
+    use serde_json::json;
+
+    #[test]
+    fn honours_constraints() {
+        let schema = json!(
+            {"allOf": [{"required": ["tags"], "properties": {"tags": {"type": "array", "minItems": 2, "maxItems": 2,
+                                                                       "items": {"type": "string", "maxLength": 3}}}},
+                       {"required": ["kind"], "properties": {"kind": {"const": "pet"}}}],
+             "properties": {"count": {"type": "integer", "multipleOf": 5, "minimum": 1, "maximum": 20},
+                            "price": {"type": "number", "exclusiveMinimum": 0, "maximum": 1},
+                            "owner": {"type": ["null", "object"], "required": ["id"],
+                                      "properties": {"id": {"type": "string", "format": "uuid"}}},
+                            "tuple": {"prefixItems": [{"type": "boolean"}, {"type": "null"}]}}}
+        );
+
+        for seed in 0..50 {
+            let instance = Generator::new(seed).generate(&schema);
+
+            assert_eq!(instance["kind"], json!("pet"));
+            let tags = instance["tags"].as_array().unwrap();
+            assert_eq!(tags.len(), 2);
+            assert!(tags.iter().all(|tag| (1..=3).contains(&tag.as_str().unwrap().len())));
+            if let Some(count) = instance.get("count") {
+                let count = count.as_i64().unwrap();
+                assert!(count % 5 == 0 && (5..=20).contains(&count));
+            }
+            if let Some(price) = instance.get("price") {
+                let price = price.as_f64().unwrap();
+                assert!(price > 0.0 && price <= 1.0);
+            }
+            if let Some(owner) = instance.get("owner") {
+                assert_eq!(owner["id"].as_str().unwrap().len(), 36);
+            }
+            if let Some(tuple) = instance.get("tuple") {
+                let tuple = tuple.as_array().unwrap();
+                assert!(tuple.len() <= 2);
+                assert!(tuple.first().is_none_or(|first| first.is_boolean()));
+            }
+        }
+    }
+
+    #[test]
+    fn huge_bounds() {
+        let schema = json!(
+            {"properties": {"any": {"type": "integer", "minimum": -1e19, "maximum": 1e19},
+                            "multiple": {"type": "integer", "multipleOf": 3, "minimum": -1e19, "maximum": 1e19},
+                            "above": {"type": "integer", "exclusiveMinimum": 1e19}},
+             "required": ["any", "multiple", "above"]}
+        );
+
+        for seed in 0..50 {
+            let instance = Generator::new(seed).generate(&schema);
+
+            assert!(instance["any"].is_i64());
+            assert_eq!(instance["multiple"].as_i64().unwrap() % 3, 0);
+            assert!(instance["above"].is_i64());
+        }
+    }
+}
@@ -0,0 +1,889 @@
+//! Options for fetching `http` and `https` documents, scoped to a host or URL prefix with
+//! [`JsonRef::set_host_options`](crate::JsonRef::set_host_options), so one document can refer
+//! to both a public host and an authenticated internal registry.
+//!
+//! With [`JsonRef::set_ambient_credentials`](crate::JsonRef::set_ambient_credentials) turned
+//! on, `https` requests to a host without an `Authorization` header in its options use the
+//! first credentials found in
+//!
+//! 1. `JSONREF_TOKEN_<HOST>`, a bearer token,
+//! 2. `JSONREF_USERNAME_<HOST>` and `JSONREF_PASSWORD_<HOST>`, for basic auth,
+//! 3. the `machine` entry for the host in the netrc file named by `NETRC`, or `~/.netrc`, or
+//!    its `default` entry if [`JsonRef::set_netrc_default`](crate::JsonRef::set_netrc_default)
+//!    is turned on too,
+//!
+//! where `<HOST>` is the host name in upper case with anything but letters and digits replaced
+//! by `_`, as in `JSONREF_TOKEN_REGISTRY_EXAMPLE_COM`. CI can then authenticate to a private
+//! registry without changes to the code using jsonref.
+//!
+//! Requests share one client, and so its pool of kept-alive connections, across every
+//! document a [`JsonRef`](crate::JsonRef) and its clones fetch, tuned with
+//! [`JsonRef::set_connection_pool`](crate::JsonRef::set_connection_pool).
+
+use crate::resolve::under_prefix;
+use crate::{Callback, Error, Result, SchemaFromUrl, UrlParseError};
+#[cfg(any(feature = "gcs", feature = "azure"))]
+use serde_json::Value;
+use base64::Engine;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use url::Url;
+
+pub use rustls;
+
+/// The headers, timeout and TLS settings for requests to a host or URL prefix.
+///
+/// ```
+/// use jsonref::http::HostOptions;
+/// use std::time::Duration;
+///
+/// let options = HostOptions::new()
+///     .bearer_token("s3cr3t")
+///     .header("X-Team", "schemas")
+///     .timeout(Duration::from_secs(5));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HostOptions {
+    headers: Vec<(String, String)>,
+    timeout: Option<Duration>,
+    tls_config: Option<Arc<rustls::ClientConfig>>,
+    /// Certificate authorities trusted besides the usual web ones.
+    roots: Vec<CertificateDer<'static>>,
+    /// The certificate chain and private key to authenticate with.
+    client_auth: Option<(Vec<CertificateDer<'static>>, Arc<PrivateKeyDer<'static>>)>,
+    token_provider: Option<Callback<dyn TokenProvider>>,
+    signer: Option<Callback<dyn RequestSigner>>,
+}
+
+impl HostOptions {
+    pub fn new() -> HostOptions {
+        HostOptions::default()
+    }
+
+    /// Send the header `name` with every request.
+    pub fn header(mut self, name: &str, value: &str) -> HostOptions {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Authenticate with HTTP basic auth.
+    pub fn basic_auth(self, username: &str, password: &str) -> HostOptions {
+        self.header("Authorization", &basic_auth(username, password))
+    }
+
+    /// Authenticate with a bearer token.
+    pub fn bearer_token(self, token: &str) -> HostOptions {
+        self.header("Authorization", &format!("Bearer {}", token))
+    }
+
+    /// Give up on a request after `timeout`, connecting included.
+    pub fn timeout(mut self, timeout: Duration) -> HostOptions {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Connect with `config`, replacing the TLS settings from
+    /// [`HostOptions::root_certificates`] and [`HostOptions::client_certificate`].
+    pub fn tls_config(mut self, config: Arc<rustls::ClientConfig>) -> HostOptions {
+        self.tls_config = Some(config);
+        self
+    }
+
+    /// Trust the certificate authorities in the PEM file `pem`, such as that of an internal
+    /// registry, besides the usual web ones.
+    pub fn root_certificates(mut self, pem: &[u8]) -> Result<HostOptions> {
+        self.roots.extend(certificates(pem)?);
+        self.tls_config = Some(self.build_tls_config()?);
+        Ok(self)
+    }
+
+    /// Authenticate with the client certificate chain and private key in the PEM files
+    /// `certificates_pem` and `key_pem`, for servers requiring mutual TLS.
+    ///
+    /// ```
+    /// use jsonref::http::HostOptions;
+    /// use std::fs;
+    ///
+    /// let options = HostOptions::new()
+    ///     .root_certificates(&fs::read("fixtures/tls/ca.pem").unwrap())
+    ///     .unwrap()
+    ///     .client_certificate(&fs::read("fixtures/tls/client.pem").unwrap(), &fs::read("fixtures/tls/client.key").unwrap())
+    ///     .unwrap();
+    /// ```
+    pub fn client_certificate(mut self, certificates_pem: &[u8], key_pem: &[u8]) -> Result<HostOptions> {
+        let key = PrivateKeyDer::from_pem_slice(key_pem).map_err(|error| Error::InvalidTls {
+            reason: format!("no private key: {}", error),
+        })?;
+        self.client_auth = Some((certificates(certificates_pem)?, Arc::new(key)));
+        self.tls_config = Some(self.build_tls_config()?);
+        Ok(self)
+    }
+
+    fn build_tls_config(&self) -> Result<Arc<rustls::ClientConfig>> {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        for root in &self.roots {
+            roots.add(root.clone()).map_err(|error| Error::InvalidTls {
+                reason: format!("bad root certificate: {}", error),
+            })?;
+        }
+        let invalid = |error: rustls::Error| Error::InvalidTls { reason: error.to_string() };
+        let builder = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(invalid)?
+            .with_root_certificates(roots);
+        let config = match &self.client_auth {
+            Some((chain, key)) => builder.with_client_auth_cert(chain.clone(), key.clone_key()).map_err(invalid)?,
+            None => builder.with_no_client_auth(),
+        };
+        Ok(Arc::new(config))
+    }
+
+    /// Authenticate each request with a bearer token from `provider`, asking for a new one
+    /// and trying again once when a request is rejected with `401`.
+    pub fn token_provider<P: TokenProvider + 'static>(mut self, provider: P) -> HostOptions {
+        self.token_provider = Some(Callback(Arc::new(provider)));
+        self
+    }
+
+    /// Sign each request with `signer`, such as [`SigV4`](crate::sigv4::SigV4) with the
+    /// `ring` feature, adding the headers it returns.
+    pub fn signer<S: RequestSigner + 'static>(mut self, signer: S) -> HostOptions {
+        self.signer = Some(Callback(Arc::new(signer)));
+        self
+    }
+
+    fn has_authorization(&self) -> bool {
+        self.token_provider.is_some() || self.signer.is_some() || self.headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("Authorization"))
+    }
+}
+
+/// Supplies bearer tokens for requests, see [`HostOptions::token_provider`]. Closures taking
+/// the URL are token providers too.
+pub trait TokenProvider: Send + Sync {
+    /// The token for a request to `url`, `None` to send it without one.
+    fn token(&self, url: &Url) -> Result<Option<String>>;
+
+    /// Forget the token given for `url`, which was rejected.
+    fn invalidate(&self, _url: &Url) {}
+}
+
+impl<F> TokenProvider for F
+where
+    F: Fn(&Url) -> Result<Option<String>> + Send + Sync,
+{
+    fn token(&self, url: &Url) -> Result<Option<String>> {
+        self(url)
+    }
+}
+
+/// Signs requests, see [`HostOptions::signer`]. Closures taking the URL are signers too.
+pub trait RequestSigner: Send + Sync {
+    /// The headers that sign a `GET` request to `url`.
+    fn sign(&self, url: &Url) -> Result<Vec<(String, String)>>;
+}
+
+impl<F> RequestSigner for F
+where
+    F: Fn(&Url) -> Result<Vec<(String, String)>> + Send + Sync,
+{
+    fn sign(&self, url: &Url) -> Result<Vec<(String, String)>> {
+        self(url)
+    }
+}
+
+/// A token from an OAuth2 token endpoint or the like, fetched on first use and again shortly
+/// before it expires, or when it is rejected, for long-running services. Clones share the
+/// token.
+///
+/// ```no_run
+/// use jsonref::http::{HostOptions, RefreshingToken};
+/// use jsonref::JsonRef;
+/// use std::time::Duration;
+///
+/// let token = RefreshingToken::new(|| {
+///     // Exchange client credentials at the token endpoint of the identity provider.
+///     Ok(("eyJhbGciOi...".to_owned(), Duration::from_secs(3600)))
+/// });
+/// let mut jsonref = JsonRef::new();
+/// jsonref.set_host_options("api.example.com", HostOptions::new().token_provider(token));
+/// ```
+#[derive(Clone)]
+pub struct RefreshingToken {
+    fetch: Arc<dyn Fn() -> Result<(String, Duration)> + Send + Sync>,
+    token: TokenCache,
+}
+
+impl fmt::Debug for RefreshingToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RefreshingToken")
+    }
+}
+
+/// How long before it expires a [`RefreshingToken`] is refreshed.
+const REFRESH_MARGIN: Duration = Duration::from_secs(30);
+
+impl RefreshingToken {
+    /// `fetch` returns a new token and how long it is valid for.
+    pub fn new<F>(fetch: F) -> RefreshingToken
+    where
+        F: Fn() -> Result<(String, Duration)> + Send + Sync + 'static,
+    {
+        RefreshingToken {
+            fetch: Arc::new(fetch),
+            token: TokenCache::default(),
+        }
+    }
+}
+
+impl TokenProvider for RefreshingToken {
+    fn token(&self, _url: &Url) -> Result<Option<String>> {
+        self.token.get(|| (self.fetch)()).map(Some)
+    }
+
+    fn invalidate(&self, _url: &Url) {
+        self.token.clear();
+    }
+}
+
+/// The token of a [`RefreshingToken`], for tokens fetched with the [`Http`] of a layer.
+#[derive(Clone, Default)]
+pub(crate) struct TokenCache(Arc<Mutex<Option<(String, Instant)>>>);
+
+impl fmt::Debug for TokenCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TokenCache")
+    }
+}
+
+impl TokenCache {
+    /// The token, from `fetch` if there is none yet or it is about to expire.
+    pub(crate) fn get<F>(&self, fetch: F) -> Result<String>
+    where
+        F: FnOnce() -> Result<(String, Duration)>,
+    {
+        let mut token = self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some((value, expires_at)) = token.as_ref() {
+            if Instant::now() + REFRESH_MARGIN < *expires_at {
+                return Ok(value.clone());
+            }
+        }
+        let (value, valid_for) = fetch()?;
+        *token = Some((value.clone(), Instant::now() + valid_for));
+        Ok(value)
+    }
+
+    pub(crate) fn clear(&self) {
+        *self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+    }
+}
+
+/// The certificates in the PEM file `pem`, failing if there are none.
+fn certificates(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>> {
+    let certificates = CertificateDer::pem_slice_iter(pem)
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|error| Error::InvalidTls {
+            reason: format!("bad certificate: {}", error),
+        })?;
+    if certificates.is_empty() {
+        return Err(Error::InvalidTls {
+            reason: "no certificates".to_owned(),
+        });
+    }
+    Ok(certificates)
+}
+
+fn basic_auth(username: &str, password: &str) -> String {
+    let credentials = base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, password));
+    format!("Basic {}", credentials)
+}
+
+/// The host options of a [`JsonRef`](crate::JsonRef), and the agents built for them.
+#[derive(Debug, Clone)]
+pub(crate) struct Http {
+    hosts: Vec<Scoped>,
+    pub(crate) ambient_credentials: bool,
+    /// Whether the `default` entry of the netrc file is used for hosts without their own.
+    pub(crate) netrc_default: bool,
+    /// Looks up environment variables.
+    env: Callback<Env>,
+    /// The netrc file, read on first use.
+    netrc: Arc<OnceLock<Netrc>>,
+    /// The idle connections kept in all and to each host.
+    pool: (usize, usize),
+    /// Fixed addresses for host names, used instead of looking them up.
+    addresses: Arc<HashMap<String, IpAddr>>,
+    /// The Unix sockets `http+unix` urls may connect to.
+    pub(crate) unix_sockets: Vec<PathBuf>,
+    /// The agent for requests without TLS settings of their own.
+    agent: ureq::Agent,
+}
+
+type Env = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+/// The idle connections ureq keeps by default, in all and to each host.
+const DEFAULT_POOL: (usize, usize) = (100, 1);
+
+impl Default for Http {
+    fn default() -> Http {
+        let mut http = Http {
+            hosts: vec![],
+            ambient_credentials: false,
+            netrc_default: false,
+            env: Callback(Arc::new(|name: &str| env::var(name).ok())),
+            netrc: Arc::new(OnceLock::new()),
+            pool: DEFAULT_POOL,
+            addresses: Arc::new(HashMap::new()),
+            unix_sockets: vec![],
+            agent: ureq::agent(),
+        };
+        http.agent = http.build_agent(None);
+        http
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Scoped {
+    /// A host name, or a URL prefix if it has a scheme.
+    scope: String,
+    options: HostOptions,
+    /// The agent for options with their own TLS settings.
+    agent: Option<ureq::Agent>,
+}
+
+impl Scoped {
+    /// How closely the scope matches `url`, `None` if it does not: a URL prefix by its
+    /// length, above a host name. A prefix matches URLs with its scheme, host and port, and a
+    /// path under its path, as for an [`Allowlist`](crate::resolve::Allowlist).
+    fn specificity(&self, url: &Url) -> Option<usize> {
+        if self.scope.contains("://") {
+            under_prefix(url, &self.scope).then(|| self.scope.len() + 1)
+        } else {
+            (url.host_str() == Some(self.scope.as_str())).then_some(0)
+        }
+    }
+}
+
+impl Http {
+    /// An agent keeping the pool of idle connections and resolving the fixed addresses, with
+    /// `tls_config` if given.
+    fn build_agent(&self, tls_config: Option<Arc<rustls::ClientConfig>>) -> ureq::Agent {
+        let addresses = self.addresses.clone();
+        let mut builder = ureq::AgentBuilder::new()
+            .max_idle_connections(self.pool.0)
+            .max_idle_connections_per_host(self.pool.1)
+            .resolver(move |netloc: &str| {
+                let fixed = netloc.rsplit_once(':').and_then(|(host, port)| {
+                    let address = addresses.get(host.trim_start_matches('[').trim_end_matches(']'))?;
+                    Some(SocketAddr::new(*address, port.parse().ok()?))
+                });
+                match fixed {
+                    Some(address) => Ok(vec![address]),
+                    None => netloc.to_socket_addrs().map(Iterator::collect),
+                }
+            });
+        if let Some(tls_config) = tls_config {
+            builder = builder.tls_config(tls_config);
+        }
+        builder.build()
+    }
+
+    /// Build the agents again after a change to the settings they are built with.
+    fn rebuild_agents(&mut self) {
+        self.agent = self.build_agent(None);
+        for index in 0..self.hosts.len() {
+            let tls_config = self.hosts[index].options.tls_config.clone();
+            self.hosts[index].agent = tls_config.map(|tls_config| self.build_agent(Some(tls_config)));
+        }
+    }
+
+    pub(crate) fn set_connection_pool(&mut self, max_idle: usize, max_idle_per_host: usize) {
+        self.pool = (max_idle, max_idle_per_host);
+        self.rebuild_agents();
+    }
+
+    pub(crate) fn set_host_address(&mut self, host: &str, address: IpAddr) {
+        Arc::make_mut(&mut self.addresses).insert(host.to_owned(), address);
+        self.rebuild_agents();
+    }
+
+    pub(crate) fn set_host_options(&mut self, scope: &str, options: HostOptions) {
+        let agent = options.tls_config.clone().map(|config| self.build_agent(Some(config)));
+        self.hosts.retain(|scoped| scoped.scope != scope);
+        self.hosts.push(Scoped {
+            scope: scope.to_owned(),
+            options,
+            agent,
+        });
+    }
+
+    /// The options scoped most closely to `url`.
+    fn scoped(&self, url: &Url) -> Option<&Scoped> {
+        self.hosts
+            .iter()
+            .filter_map(|scoped| scoped.specificity(url).map(|specificity| (specificity, scoped)))
+            .max_by_key(|(specificity, _)| *specificity)
+            .map(|(_, scoped)| scoped)
+    }
+
+    /// `GET` the `http`, `https` or, on Unix, `http+unix` url `url`.
+    pub(crate) fn get(&self, url: &str) -> Result<ureq::Response> {
+        self.get_with_headers(url, &[])
+    }
+
+    /// `GET` `url` as [`Http::get`] does, with `headers` set over any from the host options.
+    pub(crate) fn get_with_headers(&self, url: &str, headers: &[(&str, &str)]) -> Result<ureq::Response> {
+        let parsed = Url::parse(url).context(UrlParseError {url})?;
+        if parsed.scheme() == "http+unix" {
+            let socket = PathBuf::from(percent_decode(parsed.host_str().unwrap_or_default()));
+            if !self.unix_sockets.contains(&socket) {
+                return Err(Error::UnsupportedUrl {
+                    url: url.to_owned(),
+                    reason: format!("the socket {} has not been added with JsonRef::add_unix_socket", socket.display()),
+                });
+            }
+        }
+        let scoped = self.scoped(&parsed);
+        let provider = scoped.and_then(|scoped| scoped.options.token_provider.as_ref());
+        let call = |request: ureq::Request| {
+            #[cfg(unix)]
+            if parsed.scheme() == "http+unix" {
+                return crate::unix::call(&parsed, &request, scoped.and_then(|scoped| scoped.options.timeout));
+            }
+            request.call()
+        };
+        let response = call(self.request(url, &parsed, scoped, headers)?);
+        match (response, provider) {
+            (Err(ureq::Error::Status(401, _)), Some(provider)) => {
+                provider.0.invalidate(&parsed);
+                call(self.request(url, &parsed, scoped, headers)?).context(SchemaFromUrl {url})
+            }
+            (response, _) => response.context(SchemaFromUrl {url}),
+        }
+    }
+
+    fn request(&self, url: &str, parsed: &Url, scoped: Option<&Scoped>, headers: &[(&str, &str)]) -> Result<ureq::Request> {
+        let mut request = match scoped.and_then(|scoped| scoped.agent.as_ref()) {
+            Some(agent) => agent.get(url),
+            None => self.agent.get(url),
+        };
+        // Credentials from the environment are only ever sent encrypted.
+        let ambient = self.ambient_credentials && parsed.scheme() == "https";
+        if ambient && !scoped.is_some_and(|scoped| scoped.options.has_authorization()) {
+            if let Some(authorization) = parsed.host_str().and_then(|host| self.ambient_authorization(host)) {
+                request = request.set("Authorization", &authorization);
+            }
+        }
+        if let Some(scoped) = scoped {
+            for (name, value) in &scoped.options.headers {
+                request = request.set(name, value);
+            }
+            if let Some(timeout) = scoped.options.timeout {
+                request = request.timeout(timeout);
+            }
+            if let Some(provider) = &scoped.options.token_provider {
+                if let Some(token) = provider.0.token(parsed)? {
+                    request = request.set("Authorization", &format!("Bearer {}", token));
+                }
+            }
+            if let Some(signer) = &scoped.options.signer {
+                for (name, value) in signer.0.sign(parsed)? {
+                    request = request.set(&name, &value);
+                }
+            }
+        }
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+        Ok(request)
+    }
+
+    /// The `Authorization` header for `host` from the environment or the netrc file.
+    fn ambient_authorization(&self, host: &str) -> Option<String> {
+        let suffix: String = host
+            .chars()
+            .map(|char| if char.is_ascii_alphanumeric() { char.to_ascii_uppercase() } else { '_' })
+            .collect();
+        let env = &*self.env.0;
+        if let Some(token) = env(&format!("JSONREF_TOKEN_{}", suffix)) {
+            return Some(format!("Bearer {}", token));
+        }
+        if let (Some(username), Some(password)) = (
+            env(&format!("JSONREF_USERNAME_{}", suffix)),
+            env(&format!("JSONREF_PASSWORD_{}", suffix)),
+        ) {
+            return Some(basic_auth(&username, &password));
+        }
+        let netrc = self.netrc.get_or_init(|| Netrc::read(env));
+        netrc.login(host, self.netrc_default).map(|(login, password)| basic_auth(login, password))
+    }
+}
+
+/// `text` with percent-encoded bytes decoded, leaving a `%` that does not start one alone.
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes.get(index + 1..index + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[index], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The entries of a netrc file.
+#[derive(Debug, Clone, Default)]
+struct Netrc {
+    /// The login and password of each machine, `None` for the `default` entry.
+    entries: Vec<(Option<String>, String, String)>,
+}
+
+impl Netrc {
+    /// Read the file named by `NETRC`, or `~/.netrc`, empty if there is none, looking the
+    /// variables up with `env`.
+    fn read(env: &Env) -> Netrc {
+        let path = env("NETRC")
+            .map(PathBuf::from)
+            .or_else(|| env("HOME").map(|home| PathBuf::from(home).join(".netrc")));
+        path.and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| Netrc::parse(&text))
+            .unwrap_or_default()
+    }
+
+    fn parse(text: &str) -> Netrc {
+        let mut entries = vec![];
+        let mut tokens = text.split_whitespace();
+        let mut current: Option<(Option<String>, String, String)> = None;
+        while let Some(token) = tokens.next() {
+            match token {
+                "machine" | "default" => {
+                    entries.extend(current.take());
+                    let machine = if token == "machine" { tokens.next().map(str::to_owned) } else { None };
+                    current = Some((machine, String::new(), String::new()));
+                }
+                "login" | "password" => {
+                    let value = tokens.next().unwrap_or_default().to_owned();
+                    if let Some((_, login, password)) = current.as_mut() {
+                        *(if token == "login" { login } else { password }) = value;
+                    }
+                }
+                "account" => {
+                    tokens.next();
+                }
+                // Macros run until a blank line, which splitting on whitespace loses, so
+                // nothing after one is read.
+                "macdef" => break,
+                _ => {}
+            }
+        }
+        entries.extend(current);
+        Netrc { entries }
+    }
+
+    /// The login and password for `host`, from its `machine` entry or else, if `use_default`,
+    /// the `default` one.
+    fn login(&self, host: &str, use_default: bool) -> Option<(&str, &str)> {
+        let machine = self.entries.iter().find(|(machine, _, _)| machine.as_deref() == Some(host));
+        machine
+            .or_else(|| self.entries.iter().filter(|_| use_default).find(|(machine, _, _)| machine.is_none()))
+            .map(|(_, login, password)| (login.as_str(), password.as_str()))
+    }
+}
+
+/// The `access_token` and `expires_in` of an OAuth2 token response.
+#[cfg(any(feature = "gcs", feature = "azure"))]
+pub(crate) fn token_response(response: &Value, url: &str) -> Result<(String, Duration)> {
+    let token = response.get("access_token").and_then(Value::as_str).ok_or_else(|| Error::UnsupportedUrl {
+        url: url.to_owned(),
+        reason: "no `access_token` in the token response".to_owned(),
+    })?;
+    let expires_in = response
+        .get("expires_in")
+        .and_then(|expires_in| expires_in.as_u64().or_else(|| expires_in.as_str()?.parse().ok()))
+        .unwrap_or(300);
+    Ok((token.to_owned(), Duration::from_secs(expires_in)))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{HostOptions, Http, Netrc, RefreshingToken, Scoped};
+    use crate::{Callback, JsonRef};
+    use serde_json::json;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+    use url::Url;
+
+    /// Serve `body` to `requests` requests on a local port, sending the lines of each request
+    /// down the channel. Returns the base URL.
+    pub(crate) fn serve(body: &'static str, requests: usize) -> (String, mpsc::Receiver<Vec<String>>) {
+        serve_statuses(body, vec![200; requests])
+    }
+
+    /// Serve `body` with each of `statuses` in turn, as [`serve`].
+    pub(crate) fn serve_statuses(body: &'static str, statuses: Vec<u16>) -> (String, mpsc::Receiver<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for (stream, status) in listener.incoming().zip(statuses) {
+                let mut stream = stream.unwrap();
+                let lines: Vec<String> = BufReader::new(stream.try_clone().unwrap())
+                    .lines()
+                    .map(|line| line.unwrap())
+                    .take_while(|line| !line.is_empty())
+                    .collect();
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+                sender.send(lines).unwrap();
+            }
+        });
+        (format!("http://{}", address), receiver)
+    }
+
+    #[test]
+    fn options_by_host_and_prefix() {
+        let (base, requests) = serve(r#"{"type": "string"}"#, 2);
+        let mut jsonref = JsonRef::new();
+        jsonref.set_host_options("127.0.0.1", HostOptions::new().header("X-Scope", "host"));
+        jsonref.set_host_options(&format!("{}/private/", base), HostOptions::new().bearer_token("s3cr3t"));
+
+        let mut input = json!({"properties": {"public": {"$ref": format!("{}/public/name.json", base)},
+                                              "private": {"$ref": format!("{}/private/name.json", base)}}});
+        jsonref.deref_value(&mut input).unwrap();
+        assert_eq!(input["properties"]["private"], json!({"type": "string"}));
+
+        let (public, private): (Vec<_>, Vec<_>) =
+            requests.iter().take(2).partition(|lines| lines[0].starts_with("GET /public/"));
+        let (public, private) = (&public[0], &private[0]);
+        assert!(public.iter().any(|line| line == "X-Scope: host"));
+        assert!(private.iter().any(|line| line == "Authorization: Bearer s3cr3t"));
+        assert!(!private.iter().any(|line| line.starts_with("X-Scope")));
+    }
+
+    #[test]
+    fn prefix_scopes() {
+        let scoped = |scope: &str| Scoped {
+            scope: scope.to_owned(),
+            options: HostOptions::new(),
+            agent: None,
+        };
+        let url = Url::parse("https://example.com/private/name.json").unwrap();
+        assert_eq!(scoped("https://example.com/private/").specificity(&url), Some(29));
+        assert_eq!(scoped("https://example.com:443/private").specificity(&url), Some(32));
+        assert_eq!(scoped("example.com").specificity(&url), Some(0));
+        assert_eq!(scoped("https://example.com/priv").specificity(&url), None);
+        assert_eq!(scoped("https://example.com.evil.net/private/").specificity(&url), None);
+        assert_eq!(scoped("http://example.com/private/").specificity(&url), None);
+    }
+
+    #[test]
+    fn ambient_credentials() {
+        let netrc = Netrc::parse(
+            "machine registry.example.com login ci password s3cr3t\n\
+             default login anonymous password guest\n\
+             macdef init\ncd /pub\n",
+        );
+        assert_eq!(netrc.login("registry.example.com", false), Some(("ci", "s3cr3t")));
+        assert_eq!(netrc.login("example.org", false), None);
+        assert_eq!(netrc.login("example.org", true), Some(("anonymous", "guest")));
+
+        let mut http = Http {
+            env: Callback(Arc::new(|name: &str| {
+                (name == "JSONREF_TOKEN_REGISTRY_EXAMPLE_COM").then(|| "from-env".to_owned())
+            })),
+            ..Http::default()
+        };
+        let authorization = |http: &Http, url: &str| {
+            let request = http.request(url, &Url::parse(url).unwrap(), None, &[]).unwrap();
+            request.header("Authorization").map(str::to_owned)
+        };
+        assert_eq!(authorization(&http, "https://registry.example.com/name.json"), None);
+
+        http.ambient_credentials = true;
+        assert_eq!(
+            authorization(&http, "https://registry.example.com/name.json").as_deref(),
+            Some("Bearer from-env")
+        );
+        assert_eq!(authorization(&http, "http://registry.example.com/name.json"), None);
+        assert_eq!(authorization(&http, "https://example.org/name.json"), None);
+    }
+
+    #[test]
+    fn refreshes_rejected_tokens() {
+        let (base, requests) = serve_statuses(r#"{"type": "string"}"#, vec![401, 200, 200]);
+        let issued = Arc::new(AtomicUsize::new(0));
+        let counted = issued.clone();
+        let token = RefreshingToken::new(move || {
+            Ok((format!("token-{}", counted.fetch_add(1, Ordering::SeqCst)), Duration::from_secs(3600)))
+        });
+        let mut jsonref = JsonRef::new();
+        jsonref.set_host_options("127.0.0.1", HostOptions::new().token_provider(token));
+
+        let mut input = json!({"properties": {"a": {"$ref": format!("{}/a.json", base)},
+                                              "b": {"$ref": format!("{}/b.json", base)}}});
+        jsonref.deref_value(&mut input).unwrap();
+        assert_eq!(input["properties"]["b"], json!({"type": "string"}));
+
+        let authorizations: Vec<String> = requests
+            .iter()
+            .take(3)
+            .map(|lines| lines.into_iter().find(|line| line.starts_with("Authorization")).unwrap())
+            .collect();
+        assert_eq!(
+            authorizations,
+            ["Authorization: Bearer token-0", "Authorization: Bearer token-1", "Authorization: Bearer token-1"]
+        );
+    }
+
+    /// Serve `body` over kept-alive connections, returning the base URL and the number of
+    /// connections made.
+    fn serve_kept_alive(body: &'static str) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counted = connections.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                counted.fetch_add(1, Ordering::SeqCst);
+                let mut stream = stream.unwrap();
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    loop {
+                        let mut request = String::new();
+                        while !request.ends_with("\r\n\r\n") {
+                            let mut byte = [0];
+                            if reader.read(&mut byte).unwrap_or(0) == 0 {
+                                return;
+                            }
+                            request.push(byte[0] as char);
+                        }
+                        write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+                    }
+                });
+            }
+        });
+        (format!("http://{}", address), connections)
+    }
+
+    #[test]
+    fn connections_are_reused() {
+        let schema = |base: &str| {
+            json!({"properties": {"a": {"$ref": format!("{}/a.json", base)},
+                                  "b": {"$ref": format!("{}/b.json", base)},
+                                  "c": {"$ref": format!("{}/c.json", base)}}})
+        };
+
+        let (base, connections) = serve_kept_alive(r#"{"type": "string"}"#);
+        let mut jsonref = JsonRef::new();
+        jsonref.deref_value(&mut schema(&base)).unwrap();
+        jsonref.clone().deref_value(&mut schema(&base)).unwrap();
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+        let (base, connections) = serve_kept_alive(r#"{"type": "string"}"#);
+        let mut jsonref = JsonRef::new();
+        jsonref.set_connection_pool(0, 0);
+        jsonref.deref_value(&mut schema(&base)).unwrap();
+        assert_eq!(connections.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn mutual_tls() {
+        use rustls::pki_types::pem::PemObject;
+        use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+        use rustls::server::WebPkiClientVerifier;
+
+        let fixture = |name: &str| std::fs::read(format!("fixtures/tls/{}", name)).unwrap();
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(CertificateDer::from_pem_slice(&fixture("ca.pem")).unwrap()).unwrap();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone()).build().unwrap();
+        let server_config = Arc::new(
+            rustls::ServerConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_client_cert_verifier(verifier)
+                .with_single_cert(
+                    vec![CertificateDer::from_pem_slice(&fixture("server.pem")).unwrap()],
+                    PrivateKeyDer::from_pem_slice(&fixture("server.key")).unwrap(),
+                )
+                .unwrap(),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let connection = rustls::ServerConnection::new(server_config.clone()).unwrap();
+                let mut tls = rustls::StreamOwned::new(connection, stream.unwrap());
+                let mut request = vec![];
+                let mut byte = [0];
+                while !request.ends_with(b"\r\n\r\n") && tls.read(&mut byte).unwrap_or(0) == 1 {
+                    request.push(byte[0]);
+                }
+                let body = r#"{"type": "string"}"#;
+                let _ = write!(tls, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+                tls.conn.send_close_notify();
+                let _ = tls.flush();
+            }
+        });
+
+        let url = format!("https://localhost:{}/name.json", port);
+        let trusting = HostOptions::new().root_certificates(&fixture("ca.pem")).unwrap();
+
+        let mut jsonref = JsonRef::new();
+        jsonref.set_host_options("localhost", trusting.clone());
+        assert!(jsonref.deref_value(&mut json!({"$ref": url})).is_err());
+
+        let mut jsonref = JsonRef::new();
+        jsonref.set_host_options(
+            "localhost",
+            trusting.client_certificate(&fixture("client.pem"), &fixture("client.key")).unwrap(),
+        );
+        let mut input = json!({"$ref": url});
+        jsonref.deref_value(&mut input).unwrap();
+        assert_eq!(input, json!({"type": "string"}));
+
+        assert!(HostOptions::new().client_certificate(b"not pem", &fixture("client.key")).is_err());
+    }
+
+    #[test]
+    fn fixed_host_addresses() {
+        let (base, requests) = serve(r#"{"type": "string"}"#, 1);
+        let port = base.rsplit(':').next().unwrap();
+        let mut jsonref = JsonRef::new();
+        jsonref.set_host_address("schemas.example.com", "127.0.0.1".parse().unwrap());
+
+        let mut input = json!({"$ref": format!("http://schemas.example.com:{}/name.json", port)});
+        jsonref.deref_value(&mut input).unwrap();
+        assert_eq!(input, json!({"type": "string"}));
+        assert!(requests.recv().unwrap().contains(&format!("Host: schemas.example.com:{}", port)));
+    }
+}
@@ -0,0 +1,163 @@
+//! A small JSONPath evaluator used to pick which parts of a document to dereference.
+//!
+//! Supports the root `$`, child names (`.name`, `['name']`), array indexes (`[0]`), wildcards
+//! (`.*`, `[*]`) and recursive descent (`..name`, `..*`).
+
+use crate::{escape_pointer, Error, Result};
+use serde_json::Value;
+use std::collections::HashSet;
+
+#[derive(Debug, PartialEq)]
+enum Selector {
+    Name(String),
+    Index(usize),
+    Wildcard,
+}
+
+#[derive(Debug, PartialEq)]
+struct Step {
+    recursive: bool,
+    selector: Selector,
+}
+
+/// JSON pointers to every node in `value` matched by the JSONPath expression `path`, in document
+/// order and without duplicates.
+pub(crate) fn pointers(value: &Value, path: &str) -> Result<Vec<String>> {
+    let steps = parse(path)?;
+
+    let mut current = vec![(String::new(), value)];
+    for step in steps {
+        if step.recursive {
+            let mut expanded = vec![];
+            for (pointer, node) in current {
+                descendants(pointer, node, &mut expanded);
+            }
+            current = expanded;
+        }
+        let mut next = vec![];
+        for (pointer, node) in current {
+            select(&step.selector, pointer, node, &mut next);
+        }
+        current = next;
+    }
+
+    let mut seen = HashSet::new();
+    Ok(current
+        .into_iter()
+        .map(|(pointer, _)| pointer)
+        .filter(|pointer| seen.insert(pointer.clone()))
+        .collect())
+}
+
+fn descendants<'a>(pointer: String, node: &'a Value, out: &mut Vec<(String, &'a Value)>) {
+    out.push((pointer.clone(), node));
+    let mut children = vec![];
+    select(&Selector::Wildcard, pointer, node, &mut children);
+    for (child_pointer, child) in children {
+        descendants(child_pointer, child, out);
+    }
+}
+
+fn select<'a>(selector: &Selector, pointer: String, node: &'a Value, out: &mut Vec<(String, &'a Value)>) {
+    match (selector, node) {
+        (Selector::Name(name), Value::Object(obj)) => {
+            if let Some(child) = obj.get(name) {
+                out.push((format!("{}/{}", pointer, escape_pointer(name)), child));
+            }
+        }
+        (Selector::Index(index), Value::Array(array)) => {
+            if let Some(child) = array.get(*index) {
+                out.push((format!("{}/{}", pointer, index), child));
+            }
+        }
+        (Selector::Wildcard, Value::Object(obj)) => {
+            for (key, child) in obj {
+                out.push((format!("{}/{}", pointer, escape_pointer(key)), child));
+            }
+        }
+        (Selector::Wildcard, Value::Array(array)) => {
+            for (index, child) in array.iter().enumerate() {
+                out.push((format!("{}/{}", pointer, index), child));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn parse(path: &str) -> Result<Vec<Step>> {
+    let invalid = |reason: &str| Error::InvalidJsonPath {
+        path: path.to_owned(),
+        reason: reason.to_owned(),
+    };
+
+    let mut rest = path
+        .trim()
+        .strip_prefix('$')
+        .ok_or_else(|| invalid("path must start with `$`"))?;
+    let mut steps = vec![];
+
+    while !rest.is_empty() {
+        let recursive = rest.starts_with("..");
+        if recursive {
+            rest = &rest[2..];
+        } else if rest.starts_with('.') {
+            rest = &rest[1..];
+        } else if !rest.starts_with('[') {
+            return Err(invalid("expected `.` or `[`"));
+        }
+
+        let selector = if let Some(bracketed) = rest.strip_prefix('[') {
+            let end = bracketed.find(']').ok_or_else(|| invalid("unclosed `[`"))?;
+            let inner = bracketed[..end].trim();
+            rest = &bracketed[end + 1..];
+            if inner == "*" {
+                Selector::Wildcard
+            } else if let Some(name) = quoted(inner, '\'').or_else(|| quoted(inner, '"')) {
+                Selector::Name(name.to_owned())
+            } else {
+                Selector::Index(inner.parse().map_err(|_| invalid("expected a name, index or `*` in brackets"))?)
+            }
+        } else {
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            let name = &rest[..end];
+            rest = &rest[end..];
+            match name {
+                "" => return Err(invalid("expected a name after `.`")),
+                "*" => Selector::Wildcard,
+                name => Selector::Name(name.to_owned()),
+            }
+        };
+        steps.push(Step { recursive, selector });
+    }
+    Ok(steps)
+}
+
+fn quoted(inner: &str, quote: char) -> Option<&str> {
+    inner.strip_prefix(quote)?.strip_suffix(quote)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pointers;
+    use serde_json::json;
+
+    #[test]
+    fn select_pointers() {
+        let value = json!(
+            {"paths": {"/pets": {"post": {"requestBody": {"$ref": "#/components/requestBodies/pet"}},
+                                 "get": {"responses": {}}}},
+             "list": [{"a": 1}, {"a": 2}]}
+        );
+
+        assert_eq!(
+            pointers(&value, "$.paths..requestBody").unwrap(),
+            vec!["/paths/~1pets/post/requestBody"]
+        );
+        assert_eq!(pointers(&value, "$['paths']['/pets'].*").unwrap().len(), 2);
+        assert_eq!(pointers(&value, "$.list[1].a").unwrap(), vec!["/list/1/a"]);
+        assert_eq!(pointers(&value, "$..a").unwrap(), vec!["/list/0/a", "/list/1/a"]);
+        assert_eq!(pointers(&value, "$").unwrap(), vec![""]);
+        assert!(pointers(&value, "paths").is_err());
+        assert!(pointers(&value, "$.list[x]").is_err());
+    }
+}
@@ -0,0 +1,4767 @@
+//! jsonref dereferences JSONSchema `$ref` attributes and creates a new dereferenced schema.
+//!
+//! Dereferencing is normally done by a JSONSchema validator in the process of validation, but
+//! it is sometimes useful to do this independent of the validator for tasks like:
+//!
+//! * Analysing a schema programatically to see what field there are.
+//! * Programatically modifying a schema.
+//! * Passing to tools that create fake JSON data from the schema.
+//! * Passing the schema to form generation tools.
+//!
+//!
+//! Example:
+//! ```
+//! use serde_json::json;
+//! use jsonref::JsonRef;
+//!
+//! let mut simple_example = json!(
+//!           {"properties": {"prop1": {"title": "name"},
+//!                           "prop2": {"$ref": "#/properties/prop1"}}
+//!           }
+//!        );
+//!
+//! let mut jsonref = JsonRef::new();
+//!
+//! jsonref.deref_value(&mut simple_example).unwrap();
+//!
+//! let dereffed_expected = json!(
+//!     {"properties":
+//!         {"prop1": {"title": "name"},
+//!          "prop2": {"title": "name"}}
+//!     }
+//! );
+//! assert_eq!(simple_example, dereffed_expected)
+//! ```
+//!
+//! **Note**:  If the JSONSchema has recursive `$ref` only the first recursion will happen.
+//! This is to stop an infinate loop.
+
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+use snafu::{Snafu, ResultExt};
+
+pub mod analyze;
+#[cfg(feature = "azure")]
+pub mod azure;
+pub mod build;
+mod builder;
+pub mod canonical;
+pub mod crd;
+#[cfg(feature = "miette")]
+pub mod diagnostic;
+pub mod http;
+mod jsonpath;
+mod metaschema;
+pub mod patch;
+pub mod resolve;
+mod openapi;
+mod openrpc;
+mod schema;
+mod store;
+pub mod share;
+pub mod spans;
+pub mod transform;
+#[cfg(unix)]
+mod unix;
+pub mod walk;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "gcs")]
+pub mod gcs;
+#[cfg(feature = "generate")]
+pub mod generate;
+#[cfg(feature = "ring")]
+pub mod sigv4;
+#[cfg(feature = "stream")]
+pub mod stream;
+#[cfg(feature = "jsonschema")]
+pub mod validate;
+
+use patch::PatchOperation;
+
+pub use builder::JsonRefBuilder;
+pub use schema::DefinitionsKeyword;
+
+/// Register schema files embedded in the binary with `include_str!` by their `$id`, so `$ref`s
+/// to them need no filesystem or network access. Paths are relative to the calling file.
+/// Evaluates to a `Result` that fails on the first schema that can not be registered.
+///
+/// ```
+/// use jsonref::{register_embedded, JsonRef};
+/// use serde_json::json;
+///
+/// let mut jsonref = JsonRef::new();
+/// register_embedded!(jsonref, "../fixtures/registry/name.json", "../fixtures/registry/person.json").unwrap();
+///
+/// let mut input = json!({"$ref": "https://example.com/schemas/person.json"});
+/// jsonref.deref_value(&mut input).unwrap();
+///
+/// assert_eq!(input["properties"]["name"]["type"], json!("string"));
+/// ```
+#[macro_export]
+macro_rules! register_embedded {
+    ($jsonref:expr, $($path:expr),+ $(,)?) => {
+        (|| -> ::std::result::Result<(), $crate::Error> {
+            $( $jsonref.register_str(include_str!($path))?; )+
+            Ok(())
+        })()
+    };
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not open schema from {}: {}", filename, source))]
+    SchemaFromFile {
+        filename: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not open schema from url {}: {}", url, source))]
+    SchemaFromUrl {
+        url: String,
+        source: ureq::Error,
+    },
+    #[snafu(display("Parse error for url {}: {}", url, source))]
+    UrlParseError {
+        url: String,
+        source: url::ParseError,
+    },
+    #[snafu(display("schema from {} not valid JSON: {}", url, source))]
+    SchemaNotJson {
+        url: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("schema from {} not valid JSON: {}", url, source))]
+    SchemaNotJsonSerde {
+        url: String,
+        source: serde_json::Error,
+    },
+    #[cfg(feature = "serde_yaml")]
+    #[snafu(display("schema from {} not valid YAML: {}", url, source))]
+    SchemaNotYaml {
+        url: String,
+        source: serde_yaml::Error,
+    },
+    #[snafu(display("json pointer {} not found", pointer))]
+    JsonPointerNotFound {
+        pointer: String,
+    },
+    #[snafu(display("Invalid JSONPath {}: {}", path, reason))]
+    InvalidJsonPath {
+        path: String,
+        reason: String,
+    },
+    #[snafu(display("Invalid $ref `{}`: {}", reference, reason))]
+    InvalidRef {
+        reference: String,
+        reason: String,
+    },
+    #[snafu(display("Invalid $data `{}` at {}: {}", value, pointer, reason))]
+    InvalidDataPointer {
+        /// JSON pointer to the object holding the `$data`.
+        pointer: String,
+        value: String,
+        reason: String,
+    },
+    #[snafu(display("Reference cycle {}", cycle.join(" → ")))]
+    RefCycle {
+        /// The ref URLs followed, starting and ending with the same one.
+        cycle: Vec<String>,
+    },
+    #[snafu(display("Meta-schema {} requires vocabulary {}, which is not supported", meta_schema, vocabulary))]
+    UnsupportedVocabulary {
+        meta_schema: String,
+        vocabulary: String,
+    },
+    #[snafu(display("Can not retrieve {}: {}", url, reason))]
+    UnsupportedUrl {
+        url: String,
+        reason: String,
+    },
+    #[snafu(display("Can not retrieve {}, which failed recently: {}", url, reason))]
+    RecentlyFailed {
+        url: String,
+        reason: String,
+        /// The kind of the original error.
+        kind: ErrorKind,
+    },
+    #[snafu(display("Can not retrieve {}, as {} failed {} times in a row: {}", url, host, failures, reason))]
+    HostUnavailable {
+        url: String,
+        host: String,
+        failures: usize,
+        /// The last error from the host.
+        reason: String,
+    },
+    #[snafu(display("Unexpected response from schema registry for {}: {}", url, reason))]
+    RegistryResponse {
+        url: String,
+        reason: String,
+    },
+    #[snafu(display("Unexpected schema catalog at {}: {}", url, reason))]
+    CatalogResponse {
+        url: String,
+        reason: String,
+    },
+    #[snafu(display("No schema named {} in the schema catalog at {}", name, catalog))]
+    UnknownSchema {
+        name: String,
+        catalog: String,
+    },
+    #[snafu(display("Invalid TLS settings: {}", reason))]
+    InvalidTls {
+        reason: String,
+    },
+    #[snafu(display("Snapshot {} is not valid: {}", path, reason))]
+    InvalidSnapshot {
+        path: String,
+        reason: String,
+    },
+    #[snafu(display("Can not bundle {} as {}, which is taken by a different definition", url, name))]
+    BundleNameCollision {
+        name: String,
+        url: String,
+    },
+    #[snafu(display("Schema can not be registered as it has no $id"))]
+    SchemaWithoutId,
+    #[snafu(display("Could not write {}: {}", path, source))]
+    WriteOutput {
+        path: String,
+        source: std::io::Error,
+    },
+    #[cfg(feature = "jsonschema")]
+    #[snafu(display("Could not build validator: {}", reason))]
+    Validator {
+        reason: String,
+    },
+    #[cfg(feature = "jsonschema")]
+    #[snafu(display("Schema has {} meta-schema violations, first at {}: {}",
+                    violations.len(), violations[0].pointer, violations[0].message))]
+    MetaSchemaViolations {
+        violations: Vec<validate::Violation>,
+    },
+    #[snafu(display("{}", "Json Ref Error"))]
+    JSONRefError {
+        source: std::io::Error,
+    }
+}
+
+/// The result of every fallible `jsonref` operation.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Broad categories of [`Error`], from [`Error::kind`], to decide what to do about one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Fetching a document over http failed in a way that may succeed if tried again: the
+    /// connection failed or timed out, or the server answered 429 or 5xx.
+    Network,
+    /// A local file could not be read or written.
+    Io,
+    /// A document is not valid JSON, or YAML.
+    Parse,
+    /// A `$ref`, URL, pointer or path is malformed or points at nothing, including an http
+    /// document that does not exist.
+    Resolution,
+    /// The schema does not validate against its meta-schema.
+    Validation,
+    /// The options given are not usable, such as a client certificate or root certificate that
+    /// can not be loaded.
+    Configuration,
+}
+
+impl ErrorKind {
+    /// Whether the same operation may succeed if tried again, which is only so for
+    /// [`ErrorKind::Network`].
+    pub fn is_retryable(self) -> bool {
+        self == ErrorKind::Network
+    }
+}
+
+impl Error {
+    /// The category of the error.
+    ///
+    /// ```
+    /// # use jsonref::{ErrorKind, JsonRef};
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!({"properties": {"a": {"$ref": "#/$defs/missing"}}});
+    /// let error = JsonRef::new().deref_value(&mut input).unwrap_err();
+    ///
+    /// assert_eq!(error.kind(), ErrorKind::Resolution);
+    /// assert!(!error.kind().is_retryable());
+    /// ```
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::SchemaFromUrl { source, .. } => match source {
+                ureq::Error::Status(status, _) if *status == 429 || *status >= 500 => ErrorKind::Network,
+                ureq::Error::Status(..) => ErrorKind::Resolution,
+                ureq::Error::Transport(_) => ErrorKind::Network,
+            },
+            // Reading the body of a response failed part way, or it is not JSON.
+            Error::SchemaNotJson { source, .. } => match source.kind() {
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => ErrorKind::Parse,
+                _ => ErrorKind::Network,
+            },
+            Error::SchemaFromFile { .. } | Error::WriteOutput { .. } | Error::JSONRefError { .. } => ErrorKind::Io,
+            Error::RecentlyFailed { kind, .. } => *kind,
+            Error::HostUnavailable { .. } => ErrorKind::Network,
+            Error::SchemaNotJsonSerde { .. }
+            | Error::RegistryResponse { .. }
+            | Error::CatalogResponse { .. }
+            | Error::InvalidSnapshot { .. } => ErrorKind::Parse,
+            Error::InvalidTls { .. } => ErrorKind::Configuration,
+            #[cfg(feature = "serde_yaml")]
+            Error::SchemaNotYaml { .. } => ErrorKind::Parse,
+            Error::UrlParseError { .. }
+            | Error::JsonPointerNotFound { .. }
+            | Error::InvalidRef { .. }
+            | Error::UnsupportedUrl { .. }
+            | Error::InvalidDataPointer { .. }
+            | Error::UnsupportedVocabulary { .. }
+            | Error::RefCycle { .. }
+            | Error::BundleNameCollision { .. }
+            | Error::UnknownSchema { .. }
+            | Error::InvalidJsonPath { .. }
+            | Error::SchemaWithoutId => ErrorKind::Resolution,
+            #[cfg(feature = "jsonschema")]
+            Error::Validator { .. } | Error::MetaSchemaViolations { .. } => ErrorKind::Validation,
+        }
+    }
+}
+
+/// A `$ref` found while dereferencing, passed to user supplied callbacks.
+#[derive(Debug, Clone, Copy)]
+pub struct RefSite<'a> {
+    /// The `$ref` string as written in the schema.
+    pub reference: &'a str,
+    /// The absolute URL the `$ref` resolves to.
+    pub url: &'a str,
+    /// JSON pointer to the object containing the `$ref` in the output document.
+    pub pointer: &'a str,
+}
+
+/// A `$ref` that was replaced during the last run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replacement {
+    /// JSON pointer to where the `$ref` was in the output document.
+    pub pointer: String,
+    /// The `$ref` string as written in the schema.
+    pub reference: String,
+    /// The absolute URL, including the fragment, of the schema that replaced it.
+    pub url: String,
+    /// The object containing the `$ref`, as it was before being replaced.
+    pub original: Value,
+    /// Whether the schema was bundled into `$defs` and the `$ref` rewritten to point there,
+    /// rather than inlined.
+    pub bundled: bool,
+}
+
+/// A `$ref` that could not be resolved, found by [`JsonRef::check_value`].
+#[derive(Debug)]
+pub struct BrokenRef {
+    /// The URL of the document containing the `$ref`.
+    pub document: String,
+    /// JSON pointer to the object containing the `$ref` in that document.
+    pub pointer: String,
+    /// The `$ref` string as written in the schema.
+    pub reference: String,
+    /// Why it could not be resolved.
+    pub error: Error,
+}
+
+/// A lint-style issue met during the last run that did not stop it, from [`JsonRef::warnings`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// Keys next to a `$ref` were dropped when its target was inlined in its place. Keep them
+    /// with [`JsonRef::set_reference_key`], or `x-` keys with [`JsonRef::set_merge_extensions`].
+    SiblingKeysDiscarded {
+        /// JSON pointer to where the `$ref` was in the output document.
+        pointer: String,
+        keys: Vec<String>,
+    },
+    /// A `$ref` met again while its target was being inlined was left out, see
+    /// [`RecursionPolicy::Truncate`].
+    RecursionTruncated {
+        /// JSON pointer to where the `$ref` was in the output document.
+        pointer: String,
+        /// The absolute URL, including the fragment, the `$ref` points to.
+        url: String,
+    },
+    /// Two schemas in the input declare the same `$id`, so which one refs to it find is a guess.
+    DuplicateId {
+        /// The absolute `$id`.
+        id: String,
+        /// JSON pointer to the first schema with it.
+        first: String,
+        /// JSON pointer to the other.
+        pointer: String,
+    },
+    /// The meta-schema of the document requires a vocabulary other than the standard ones, see
+    /// [`VocabularyPolicy::Warn`].
+    UnsupportedVocabulary {
+        /// The `$schema` of the document.
+        meta_schema: String,
+        vocabulary: String,
+    },
+    /// A `$ref` whose value is not a string, such as a property named `$ref`, was left as it is.
+    RefNotString {
+        /// JSON pointer to the object holding the `$ref`.
+        pointer: String,
+    },
+    /// A schema can not be represented in a Kubernetes structural schema, see
+    /// [`JsonRef::set_crd_structural`].
+    NotStructural {
+        /// JSON pointer to the schema in the output document.
+        pointer: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::SiblingKeysDiscarded { pointer, keys } => {
+                write!(f, "keys {} next to the $ref at {} were discarded", keys.join(", "), pointer)
+            }
+            Warning::RecursionTruncated { pointer, url } => {
+                write!(f, "recursive $ref to {} at {} was left out", url, pointer)
+            }
+            Warning::DuplicateId { id, first, pointer } => {
+                write!(f, "$id {} at {} is already declared at {}", id, pointer, first)
+            }
+            Warning::UnsupportedVocabulary { meta_schema, vocabulary } => {
+                write!(f, "meta-schema {} requires vocabulary {}, which is not supported", meta_schema, vocabulary)
+            }
+            Warning::RefNotString { pointer } => write!(f, "$ref at {} is not a string and was skipped", pointer),
+            Warning::NotStructural { pointer, reason } => {
+                write!(f, "schema at {} can not be structural: {}", if pointer.is_empty() { "the root" } else { pointer }, reason)
+            }
+        }
+    }
+}
+
+/// Which `$ref`s are inlined, based on whether they point into the document being dereferenced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefScope {
+    /// Inline every `$ref`. This is the default.
+    All,
+    /// Only inline refs within the same document (`#/...`) and leave every external ref
+    /// untouched, so no files or urls are ever read.
+    Internal,
+    /// Only inline refs to other documents and keep refs within the document (`#/$defs/...`),
+    /// producing a single file schema that keeps its internal structure.
+    External,
+}
+
+/// What to do with the `$id` and `$schema` keywords of schemas that are inlined, which would
+/// otherwise change the base URI of everything inside them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdPolicy {
+    /// Keep `$id` and `$schema` as they are. This is the default.
+    Keep,
+    /// Remove `$id` and `$schema`.
+    Strip,
+    /// Rewrite `$id` to a plain name fragment (`#address` for `.../address.json`) and remove
+    /// `$schema`.
+    Fragment,
+}
+
+/// What to do on meeting a `$ref` that is already being inlined, which would otherwise repeat
+/// forever.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecursionPolicy {
+    /// Stop there, leaving the object that held the `$ref` without it. This is the default.
+    Truncate,
+    /// Fail with [`Error::RefCycle`], listing the refs that form the cycle.
+    Error,
+}
+
+/// Progress while dereferencing, passed as it happens to the handler set with
+/// [`JsonRef::set_event_handler`].
+#[derive(Debug, Clone, Copy)]
+pub enum Event<'a> {
+    /// A document is about to be retrieved, as it was not already loaded.
+    Fetching { url: &'a str },
+    /// A `$ref` is being replaced. Its target is dereferenced next.
+    Replacing(&'a Replacement),
+    /// An issue was met, as also listed by [`JsonRef::warnings`].
+    Warning(&'a Warning),
+}
+
+/// What to do when the meta-schema named by a document's `$schema` requires, in its
+/// `$vocabulary`, a vocabulary other than the standard ones of drafts 2019-09 and 2020-12. Such a
+/// vocabulary may give keywords meaning that changes what refs resolve to, so the output may
+/// be subtly wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VocabularyPolicy {
+    /// Do not look at the meta-schema. This is the default.
+    Ignore,
+    /// Add a [`Warning::UnsupportedVocabulary`] for each such vocabulary.
+    Warn,
+    /// Fail with [`Error::UnsupportedVocabulary`].
+    Error,
+}
+
+/// Where to look for `$ref`s. Only keywords such as `properties`, `items` and `allOf` hold
+/// schemas; others, such as `examples`, `const` and vendor extensions, hold data or something
+/// else entirely, where an object with a `$ref` may not be a reference at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraversalPolicy {
+    /// Look everywhere and resolve every `$ref` found. This is the default.
+    All,
+    /// Only look inside keywords known to hold schemas, leaving anything under other keywords as
+    /// it is.
+    SchemaKeywords,
+    /// Resolve `$ref`s inside keywords known to hold schemas, and leave those under other
+    /// keywords in place, only rewritten as refs that are not followed are (see
+    /// [`JsonRef::set_absolute_refs`]) so that they still point at the same schema.
+    KeepUnknown,
+}
+
+/// What to name the definitions that targets are bundled into in `$defs`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BundleNaming {
+    /// The name of the definition in the ref's document that the target is, or is identical to,
+    /// else the last token of the ref's fragment, or the file name of its document without the
+    /// extension if it has no fragment. A target that is only a ref to a definition is bundled
+    /// as that definition. This is the default.
+    Ref,
+    /// The file name of the ref's document without the extension.
+    FileName,
+    /// The last path segment of the target's `$id`, without the extension, falling back to
+    /// [`BundleNaming::Ref`] for targets without one.
+    Id,
+    /// The target's `title`, falling back to [`BundleNaming::Ref`] for targets without one.
+    Title,
+}
+
+/// What to do when the name for a bundled definition is already taken by a different one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameCollision {
+    /// Add `_2`, `_3` and so on to the name until it is free. This is the default.
+    Suffix,
+    /// Fail with [`Error::BundleNameCollision`].
+    Error,
+    /// Use the definition already there if it is identical, otherwise add a suffix.
+    MergeIdentical,
+}
+
+/// Wrapper for user supplied callbacks so that `JsonRef` can still derive `Debug` and `Clone`.
+struct Callback<F: ?Sized>(Arc<F>);
+
+impl<F: ?Sized> Clone for Callback<F> {
+    fn clone(&self) -> Self {
+        Callback(self.0.clone())
+    }
+}
+
+impl<F: ?Sized> fmt::Debug for Callback<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Callback")
+    }
+}
+
+type RefFilter = dyn Fn(&RefSite) -> bool + Send + Sync;
+type UrlRewriter = dyn Fn(&str) -> Option<String> + Send + Sync;
+type RefResolver = dyn Fn(&RefSite) -> Option<Value> + Send + Sync;
+type InlineHook = dyn Fn(&mut Value, &RefSite) + Send + Sync;
+type EventHandler = dyn Fn(&Event) + Send + Sync;
+
+/// Main struct that holds configuration for a JSONScheama derefferencing.
+///
+/// Instantiate with
+/// ```
+/// use jsonref::JsonRef;
+/// let jsonref = JsonRef::new();
+/// ```
+///
+/// Configuration is done through the `set_` methods on the struct, or in one expression with
+/// [`JsonRef::builder`].
+///
+/// `JsonRef::default()` is the same as `JsonRef::new()`. A clone has the same options and a copy
+/// of the cache of documents read or registered so far, so cloning a configured instance for each
+/// worker saves every worker fetching the same documents again.
+#[derive(Debug, Clone)]
+pub struct JsonRef {
+    schema_cache: store::Store,
+    cache_ttl: Option<Duration>,
+    reference_key: Option<String>,
+    max_depth: Option<usize>,
+    absolute_refs: bool,
+    bundle_threshold: Option<usize>,
+    bundle_naming: BundleNaming,
+    name_collision: NameCollision,
+    ref_filter: Option<Callback<RefFilter>>,
+    url_rewriter: Option<Callback<UrlRewriter>>,
+    mirrors: BTreeMap<String, String>,
+    ref_resolver: Option<Callback<RefResolver>>,
+    inline_hook: Option<Callback<InlineHook>>,
+    layers: Vec<Callback<dyn resolve::Layer>>,
+    passes: Vec<Callback<dyn transform::Pass>>,
+    event_handler: Option<Callback<EventHandler>>,
+    ref_scope: RefScope,
+    flatten_all_of: bool,
+    normalize_definitions: Option<DefinitionsKeyword>,
+    migrate_drafts: bool,
+    strip_keywords: Vec<String>,
+    id_policy: IdPolicy,
+    recursion_policy: RecursionPolicy,
+    traversal_policy: TraversalPolicy,
+    vocabulary_policy: VocabularyPolicy,
+    bundle_meta_schema: bool,
+    json_reference: bool,
+    openapi: bool,
+    openrpc: bool,
+    keep_components: bool,
+    crd_structural: bool,
+    check_data_pointers: bool,
+    embed_non_json: bool,
+    offline_meta_schemas: bool,
+    strict_refs: bool,
+    schema_registry: Option<String>,
+    http: http::Http,
+    schema_catalog: String,
+    merge_extensions: bool,
+    source_key: Option<String>,
+    directive_key: String,
+    dedupe_threshold: Option<usize>,
+    record_patch: bool,
+    deterministic: bool,
+    #[cfg(feature = "jsonschema")]
+    validate_meta_schema: bool,
+    patch: Vec<PatchOperation>,
+    replacements: Vec<Replacement>,
+    warnings: Vec<Warning>,
+    root_url: String,
+    bundled: BTreeMap<String, Value>,
+    bundle_names: HashMap<String, String>,
+}
+
+impl Default for JsonRef {
+    fn default() -> JsonRef {
+        JsonRef::new()
+    }
+}
+
+impl JsonRef {
+    /// Create a new instance of JsonRef.
+    pub fn new() -> JsonRef {
+        return JsonRef {
+            schema_cache: store::Store::default(),
+            cache_ttl: None,
+            reference_key: None,
+            max_depth: None,
+            absolute_refs: false,
+            bundle_threshold: None,
+            bundle_naming: BundleNaming::Ref,
+            name_collision: NameCollision::Suffix,
+            ref_filter: None,
+            url_rewriter: None,
+            mirrors: BTreeMap::new(),
+            ref_resolver: None,
+            inline_hook: None,
+            layers: vec![],
+            passes: vec![],
+            event_handler: None,
+            ref_scope: RefScope::All,
+            flatten_all_of: false,
+            normalize_definitions: None,
+            migrate_drafts: false,
+            strip_keywords: vec![],
+            id_policy: IdPolicy::Keep,
+            recursion_policy: RecursionPolicy::Truncate,
+            traversal_policy: TraversalPolicy::All,
+            vocabulary_policy: VocabularyPolicy::Ignore,
+            bundle_meta_schema: false,
+            json_reference: false,
+            openapi: false,
+            openrpc: false,
+            keep_components: false,
+            crd_structural: false,
+            check_data_pointers: false,
+            embed_non_json: false,
+            offline_meta_schemas: true,
+            strict_refs: false,
+            schema_registry: None,
+            http: http::Http::default(),
+            schema_catalog: SCHEMASTORE_CATALOG.to_owned(),
+            merge_extensions: false,
+            source_key: None,
+            directive_key: "$comment".to_owned(),
+            dedupe_threshold: None,
+            record_patch: false,
+            deterministic: false,
+            #[cfg(feature = "jsonschema")]
+            validate_meta_schema: false,
+            patch: vec![],
+            replacements: vec![],
+            warnings: vec![],
+            root_url: String::new(),
+            bundled: BTreeMap::new(),
+            bundle_names: HashMap::new(),
+        };
+    }
+
+    /// A [`JsonRefBuilder`] to configure a new instance with chained calls.
+    pub fn builder() -> JsonRefBuilder {
+        JsonRefBuilder::new()
+    }
+
+    /// Set a key to store the data that the `$ref` replaced. 
+    ///
+    /// When the `$ref` resolves to a boolean schema it is replaced by the equivalent object
+    /// schema (`{}` for `true`, `{"not": {}}` for `false`) so the key can be added. Targets that
+    /// are not schemas, such as arrays or strings, are inlined without the key, and any keys that
+    /// were next to the `$ref` are reported with a [`Warning::SiblingKeysDiscarded`].
+    ///
+    /// This example uses `__reference__` as the key.
+    /// 
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// # let jsonref = JsonRef::new();
+    /// use serde_json::json;
+    ///
+    /// let mut input  = json!(
+    ///     {"properties": {"prop1": {"title": "name"},
+    ///                     "prop2": {"$ref": "#/properties/prop1", "title": "old_title"}}
+    ///     }
+    /// );
+    ///
+    /// let expected = json!(
+    ///     {"properties": {"prop1": {"title": "name"},
+    ///                     "prop2": {"title": "name", "__reference__": {"title": "old_title"}}}
+    ///     }
+    /// );
+    ///                                                                                          
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_reference_key("__reference__");
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///                                                                                          
+    /// assert_eq!(input, expected)
+    /// ```
+
+    pub fn set_reference_key(&mut self, reference_key: &str) {
+        self.reference_key = Some(reference_key.to_owned());
+    }
+
+    /// Only inline `$ref`s up to `max_depth` levels of nesting. Any `$ref` found inside content
+    /// that is already `max_depth` references deep is left in place.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"definitions": {"a": {"properties": {"b": {"$ref": "#/definitions/b"}}},
+    ///                      "b": {"title": "b"}},
+    ///      "properties": {"prop1": {"$ref": "#/definitions/a"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_max_depth(1);
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// assert_eq!(input["properties"]["prop1"], json!(
+    ///     {"properties": {"b": {"$ref": "#/definitions/b"}}}
+    /// ));
+    /// ```
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = Some(max_depth);
+    }
+
+    /// Rewrite any `$ref` that is left in place to the absolute URI it resolves to, so it stays
+    /// valid after being moved out of the document it was written in and the output no longer
+    /// depends on the original file layout or base URI. This covers refs kept because of
+    /// [`JsonRef::set_max_depth`], [`JsonRef::set_ref_filter`], [`JsonRef::set_ref_scope`] or
+    /// [`TraversalPolicy::KeepUnknown`]. Refs to bundled targets point into the output itself and
+    /// stay local.
+    ///
+    /// ```
+    /// # use jsonref::{JsonRef, RefScope};
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"$defs": {"name": {"type": "string"}},
+    ///      "properties": {"name": {"$ref": "#/$defs/name"}, "address": {"$ref": "address.json"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_ref_scope(RefScope::Internal);
+    /// jsonref.set_absolute_refs(true);
+    ///
+    /// jsonref.deref_value_with_url(&mut input, "https://example.com/schemas/person.json").unwrap();
+    ///
+    /// assert_eq!(input["properties"]["name"], json!({"type": "string"}));
+    /// assert_eq!(input["properties"]["address"], json!({"$ref": "https://example.com/schemas/address.json"}));
+    /// ```
+    pub fn set_absolute_refs(&mut self, absolute_refs: bool) {
+        self.absolute_refs = absolute_refs;
+    }
+
+    /// Only inline targets whose serialized JSON is at most `bundle_threshold` bytes. Larger
+    /// targets are moved into the `$defs` of the root schema once and every `$ref` to them is
+    /// rewritten to point there.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"definitions": {"small": {"type": "string"},
+    ///                      "large": {"type": "object", "title": "a large definition"}},
+    ///      "properties": {"prop1": {"$ref": "#/definitions/small"},
+    ///                     "prop2": {"$ref": "#/definitions/large"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_bundle_threshold(20);
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// assert_eq!(input["properties"]["prop1"], json!({"type": "string"}));
+    /// assert_eq!(input["properties"]["prop2"], json!({"$ref": "#/$defs/large"}));
+    /// assert_eq!(input["$defs"]["large"], json!({"type": "object", "title": "a large definition"}));
+    /// ```
+    pub fn set_bundle_threshold(&mut self, bundle_threshold: usize) {
+        self.bundle_threshold = Some(bundle_threshold);
+    }
+
+    /// Choose what bundled definitions are named after, see [`BundleNaming`].
+    ///
+    /// ```
+    /// # use jsonref::{BundleNaming, JsonRef};
+    /// use serde_json::json;
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// jsonref.register_at("https://example.com/definitions.json", json!(
+    ///     {"$defs": {"person": {"title": "Person", "properties": {"name": {"type": "string"}}}}}
+    /// ));
+    /// let mut input = json!({"properties": {"owner": {"$ref": "https://example.com/definitions.json#/$defs/person"}}});
+    ///
+    /// jsonref.set_codegen_profile(true);
+    /// jsonref.set_bundle_naming(BundleNaming::Title);
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    /// assert_eq!(input["properties"]["owner"], json!({"$ref": "#/$defs/Person"}));
+    /// ```
+    pub fn set_bundle_naming(&mut self, bundle_naming: BundleNaming) {
+        self.bundle_naming = bundle_naming;
+    }
+
+    /// Choose what happens when two different targets would be bundled under the same name,
+    /// see [`NameCollision`].
+    ///
+    /// ```
+    /// # use jsonref::{BundleNaming, JsonRef, NameCollision};
+    /// use serde_json::json;
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// jsonref.register_at("https://example.com/a/definitions.json", json!({"type": "string"}));
+    /// jsonref.register_at("https://example.com/b/definitions.json", json!({"type": "string"}));
+    /// jsonref.register_at("https://example.com/c/definitions.json", json!({"type": "integer"}));
+    /// let input = json!({"properties": {"a": {"$ref": "https://example.com/a/definitions.json"},
+    ///                                   "b": {"$ref": "https://example.com/b/definitions.json"},
+    ///                                   "c": {"$ref": "https://example.com/c/definitions.json"}}});
+    ///
+    /// jsonref.set_codegen_profile(true);
+    /// jsonref.set_name_collision(NameCollision::MergeIdentical);
+    /// let mut output = input.clone();
+    /// jsonref.deref_value(&mut output).unwrap();
+    /// assert_eq!(output["properties"]["b"], json!({"$ref": "#/$defs/definitions"}));
+    /// assert_eq!(output["properties"]["c"], json!({"$ref": "#/$defs/definitions_2"}));
+    ///
+    /// jsonref.set_name_collision(NameCollision::Error);
+    /// assert!(jsonref.deref_value(&mut input.clone()).is_err());
+    /// ```
+    pub fn set_name_collision(&mut self, name_collision: NameCollision) {
+        self.name_collision = name_collision;
+    }
+
+    /// Only inline the `$ref`s for which `filter` returns `true`. Any other `$ref` is left in
+    /// place.
+    ///
+    /// This example only expands refs into the `common/` folder.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"properties": {"prop1": {"$ref": "https://example.com/third_party.json"}}}
+    /// );
+    /// let expected = input.clone();
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_ref_filter(|site| site.reference.starts_with("common/"));
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// assert_eq!(input, expected)
+    /// ```
+    pub fn set_ref_filter<F>(&mut self, filter: F)
+    where
+        F: Fn(&RefSite) -> bool + Send + Sync + 'static,
+    {
+        self.ref_filter = Some(Callback(Arc::new(filter)));
+    }
+
+    /// Call `rewriter` with the absolute URL of each document before it is fetched, to fetch it
+    /// from the URL returned instead, or as it is for `None`. The document is still known by
+    /// its original URL, so refs and `$id`s are unaffected. Use this to fetch from a mirror or
+    /// pin versions.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mirror = format!("file://{}/fixtures/", env!("CARGO_MANIFEST_DIR"));
+    /// let mut input = json!({"$ref": "https://schemas.example.com/nested_relative/other.json#/properties/prop1"});
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_url_rewriter(move |url| {
+    ///     url.strip_prefix("https://schemas.example.com/").map(|path| format!("{}{}", mirror, path))
+    /// });
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    /// assert_eq!(input, json!({"title": "sub property title in other.json"}));
+    /// ```
+    pub fn set_url_rewriter<F>(&mut self, rewriter: F)
+    where
+        F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+    {
+        self.url_rewriter = Some(Callback(Arc::new(rewriter)));
+    }
+
+    /// Call `resolver` for each `$ref` about to be inlined, to supply its target directly instead
+    /// of it being fetched and looked up, or `None` to resolve it as usual. Refs in a supplied
+    /// target are resolved against the URL of the `$ref`. Use this to inject synthetic schemas,
+    /// such as for deprecated or feature-flagged definitions.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"$defs": {"legacy": {"type": "string"}},
+    ///      "properties": {"id": {"$ref": "#/$defs/legacy"}, "name": {"$ref": "#/$defs/legacy"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_ref_resolver(|site| {
+    ///     (site.pointer == "/properties/id").then(|| json!({"type": "string", "format": "uuid"}))
+    /// });
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    /// assert_eq!(input["properties"]["id"], json!({"type": "string", "format": "uuid"}));
+    /// assert_eq!(input["properties"]["name"], json!({"type": "string"}));
+    /// ```
+    pub fn set_ref_resolver<F>(&mut self, resolver: F)
+    where
+        F: Fn(&RefSite) -> Option<Value> + Send + Sync + 'static,
+    {
+        self.ref_resolver = Some(Callback(Arc::new(resolver)));
+    }
+
+    /// Call `hook` with each target about to be inlined, or bundled into `$defs`, once its own
+    /// refs are resolved, so it can be changed first. The [`RefSite`] gives the URL the target
+    /// came from and the pointer it is inserted at.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"$defs": {"name": {"type": "string", "x-internal": true}},
+    ///      "properties": {"name": {"$ref": "#/$defs/name"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_inline_hook(|schema, site| {
+    ///     if let Some(obj) = schema.as_object_mut() {
+    ///         obj.remove("x-internal");
+    ///         obj.insert("x-from".to_owned(), json!(site.url.split_once('#').map(|(_, fragment)| fragment)));
+    ///     }
+    /// });
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    /// assert_eq!(input["properties"]["name"], json!({"type": "string", "x-from": "/$defs/name"}));
+    /// ```
+    pub fn set_inline_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut Value, &RefSite) + Send + Sync + 'static,
+    {
+        self.inline_hook = Some(Callback(Arc::new(hook)));
+    }
+
+    /// Retrieve documents through `layer`, after any layers added before it. See [`resolve`]
+    /// for the order documents are looked for in and an example.
+    pub fn add_layer<L: resolve::Layer + 'static>(&mut self, layer: L) {
+        self.layers.push(Callback(Arc::new(layer)));
+    }
+
+    /// Run `pass` over the output of every run, after the transforms set by other options and
+    /// any passes added before it.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use jsonref::transform::{FlattenAllOf, StripKeywords};
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"$defs": {"named": {"properties": {"name": {"type": "string", "description": "a name"}}}},
+    ///      "allOf": [{"$ref": "#/$defs/named"}, {"required": ["name"]}]}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.add_pass(FlattenAllOf);
+    /// jsonref.add_pass(StripKeywords(vec!["description".to_owned()]));
+    /// jsonref.add_pass(|schema: &mut serde_json::Value| {
+    ///     schema.as_object_mut().unwrap().remove("$defs");
+    /// });
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    /// assert_eq!(input, json!({"properties": {"name": {"type": "string"}}, "required": ["name"]}));
+    /// ```
+    pub fn add_pass<P: transform::Pass + 'static>(&mut self, pass: P) {
+        self.passes.push(Callback(Arc::new(pass)));
+    }
+
+    /// Call `handler` with each [`Event`] as dereferencing progresses, such as to report progress
+    /// of long runs. With the `stream` feature, [`JsonRef::event_stream`] gives them as an async
+    /// `Stream` instead.
+    ///
+    /// ```
+    /// # use jsonref::{Event, JsonRef};
+    /// use serde_json::json;
+    /// use std::sync::mpsc;
+    ///
+    /// let (sender, receiver) = mpsc::channel();
+    /// let sender = std::sync::Mutex::new(sender);
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// jsonref.set_event_handler(move |event| {
+    ///     if let Event::Replacing(replacement) = event {
+    ///         sender.lock().unwrap().send(replacement.pointer.clone()).unwrap();
+    ///     }
+    /// });
+    ///
+    /// let mut input = json!(
+    ///     {"$defs": {"name": {"type": "string"}},
+    ///      "properties": {"first": {"$ref": "#/$defs/name"}, "last": {"$ref": "#/$defs/name"}}}
+    /// );
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// let replaced: Vec<String> = receiver.try_iter().collect();
+    /// assert_eq!(replaced, ["/properties/first", "/properties/last"]);
+    /// ```
+    pub fn set_event_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&Event) + Send + Sync + 'static,
+    {
+        self.event_handler = Some(Callback(Arc::new(handler)));
+    }
+
+    /// Produce output for code generators such as typify, which need every type to keep its
+    /// identity. Refs into other documents are moved into named `$defs` entries, named as set by
+    /// [`JsonRef::set_bundle_naming`], which keeps the names of definitions in the documents by
+    /// default, and refs within the document are kept.
+    /// Turning this off restores the default scope and threshold.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_codegen_profile(true);
+    ///
+    /// let output = jsonref.deref_file("fixtures/nested_relative/base.json").unwrap();
+    ///
+    /// assert_eq!(output["properties"]["prop2"], json!({"$ref": "#/properties/prop1"}));
+    /// assert_eq!(output["properties"]["prop4"], json!({"$ref": "#/$defs/prop1"}));
+    /// assert_eq!(output["$defs"]["prop1"], json!({"title": "sub property title in other.json"}));
+    /// ```
+    pub fn set_codegen_profile(&mut self, codegen: bool) {
+        if codegen {
+            self.ref_scope = RefScope::External;
+            self.bundle_threshold = Some(0);
+        } else {
+            self.ref_scope = RefScope::All;
+            self.bundle_threshold = None;
+        }
+    }
+
+    /// Choose which `$ref`s are inlined depending on whether they are internal to the document.
+    ///
+    /// ```
+    /// # use jsonref::{JsonRef, RefScope};
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"properties": {"prop1": {"title": "name"},
+    ///                     "prop2": {"$ref": "#/properties/prop1"},
+    ///                     "prop3": {"$ref": "other.json"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_ref_scope(RefScope::Internal);
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// assert_eq!(input["properties"]["prop2"], json!({"title": "name"}));
+    /// assert_eq!(input["properties"]["prop3"], json!({"$ref": "other.json"}));
+    /// ```
+    pub fn set_ref_scope(&mut self, ref_scope: RefScope) {
+        self.ref_scope = ref_scope;
+    }
+
+    /// Merge `allOf`s of plain object schemas once dereferencing is done. See
+    /// [`transform::flatten_all_of`] for how schemas are merged.
+    pub fn set_flatten_all_of(&mut self, flatten_all_of: bool) {
+        self.flatten_all_of = flatten_all_of;
+    }
+
+    /// Set a key to store the absolute URL, including the fragment, that each inlined or bundled
+    /// schema came from.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"properties": {"prop1": {"title": "name"},
+    ///                     "prop2": {"$ref": "#/properties/prop1"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_source_key("$deref_from");
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// let source = input["properties"]["prop2"]["$deref_from"].as_str().unwrap();
+    /// assert!(source.ends_with("anon.json#/properties/prop1"));
+    /// ```
+    pub fn set_source_key(&mut self, source_key: &str) {
+        self.source_key = Some(source_key.to_owned());
+    }
+
+    /// Move vendor extension keys (`x-...`) found next to a `$ref` onto the schema that replaces
+    /// it, overriding any the schema already has, instead of dropping them or keeping them under
+    /// the reference key.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"properties": {"prop1": {"title": "name", "x-order": 1},
+    ///                     "prop2": {"$ref": "#/properties/prop1", "x-order": 2, "x-internal": true}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_merge_extensions(true);
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// assert_eq!(input["properties"]["prop2"], json!(
+    ///     {"title": "name", "x-order": 2, "x-internal": true}
+    /// ));
+    /// ```
+    pub fn set_merge_extensions(&mut self, merge_extensions: bool) {
+        self.merge_extensions = merge_extensions;
+    }
+
+    /// Choose what happens when a `$ref` is met again while its target is being inlined.
+    ///
+    /// ```
+    /// # use jsonref::{Error, JsonRef, RecursionPolicy};
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"$defs": {"a": {"properties": {"b": {"$ref": "#/$defs/b"}}},
+    ///                "b": {"properties": {"a": {"$ref": "#/$defs/a"}}}},
+    ///      "properties": {"a": {"$ref": "#/$defs/a"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_recursion_policy(RecursionPolicy::Error);
+    ///
+    /// match jsonref.deref_value(&mut input).unwrap_err() {
+    ///     Error::RefCycle { cycle } => {
+    ///         let fragments: Vec<&str> = cycle.iter().map(|url| url.split('#').nth(1).unwrap()).collect();
+    ///         // `$defs` comes before `properties`, so the cycle is first met from `$defs/a`.
+    ///         assert_eq!(fragments, ["/$defs/b", "/$defs/a", "/$defs/b"]);
+    ///     }
+    ///     error => panic!("unexpected error {}", error),
+    /// }
+    /// ```
+    pub fn set_recursion_policy(&mut self, recursion_policy: RecursionPolicy) {
+        self.recursion_policy = recursion_policy;
+    }
+
+    /// Fetch documents again once they have been cached for `ttl`, for long running processes
+    /// whose documents change. Expired documents are dropped at the start of each deref or check,
+    /// so one run always sees the same version of a document. Registered documents never expire.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use std::time::Duration;
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// jsonref.set_cache_ttl(Duration::from_secs(300));
+    /// ```
+    pub fn set_cache_ttl(&mut self, ttl: Duration) {
+        self.cache_ttl = Some(ttl);
+    }
+
+    /// Set the key read for directives on a ref site, `$comment` by default. A ref whose object
+    /// has `"jsonref:keep"` under the key is left in place, as it would be beyond
+    /// [`JsonRef::set_max_depth`], so schema authors can choose refs to keep in the schema itself.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let input = json!(
+    ///     {"$defs": {"name": {"type": "string"}},
+    ///      "properties": {"name": {"$ref": "#/$defs/name"},
+    ///                     "alias": {"$ref": "#/$defs/name", "$comment": "jsonref:keep"},
+    ///                     "nickname": {"$ref": "#/$defs/name", "x-jsonref": "jsonref:keep"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// let mut output = input.clone();
+    /// jsonref.deref_value(&mut output).unwrap();
+    /// assert_eq!(output["properties"]["name"], json!({"type": "string"}));
+    /// assert_eq!(output["properties"]["alias"], json!({"$ref": "#/$defs/name", "$comment": "jsonref:keep"}));
+    ///
+    /// jsonref.set_directive_key("x-jsonref");
+    /// let mut output = input.clone();
+    /// jsonref.deref_value(&mut output).unwrap();
+    /// assert_eq!(output["properties"]["nickname"], json!({"$ref": "#/$defs/name", "x-jsonref": "jsonref:keep"}));
+    /// assert_eq!(output["properties"]["alias"]["type"], json!("string"));
+    /// ```
+    pub fn set_directive_key(&mut self, directive_key: &str) {
+        self.directive_key = directive_key.to_owned();
+    }
+
+    /// Choose whether to look for `$ref`s under keywords that do not hold schemas.
+    ///
+    /// ```
+    /// # use jsonref::{JsonRef, TraversalPolicy};
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"$defs": {"name": {"type": "string"}},
+    ///      "properties": {"name": {"$ref": "#/$defs/name"}},
+    ///      "examples": [{"$ref": "#/$defs/name"}]}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_traversal_policy(TraversalPolicy::SchemaKeywords);
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    /// assert_eq!(input["properties"]["name"], json!({"type": "string"}));
+    /// assert_eq!(input["examples"], json!([{"$ref": "#/$defs/name"}]));
+    /// ```
+    pub fn set_traversal_policy(&mut self, traversal_policy: TraversalPolicy) {
+        self.traversal_policy = traversal_policy;
+    }
+
+    /// Choose whether to check the vocabularies required by the document's meta-schema. Unless
+    /// the `$schema` is a standard dialect the meta-schema is read, or fetched, like any other
+    /// document.
+    ///
+    /// ```
+    /// # use jsonref::{JsonRef, VocabularyPolicy, Warning};
+    /// use serde_json::json;
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// jsonref.register_at("https://example.com/dialect", json!(
+    ///     {"$vocabulary": {"https://json-schema.org/draft/2020-12/vocab/core": true,
+    ///                      "https://example.com/vocab/imports": true}}
+    /// ));
+    ///
+    /// jsonref.set_vocabulary_policy(VocabularyPolicy::Warn);
+    ///
+    /// let mut input = json!({"$schema": "https://example.com/dialect", "type": "string"});
+    /// jsonref.deref_value(&mut input).unwrap();
+    /// assert!(matches!(
+    ///     &jsonref.warnings()[0],
+    ///     Warning::UnsupportedVocabulary { vocabulary, .. } if vocabulary == "https://example.com/vocab/imports"
+    /// ));
+    /// ```
+    pub fn set_vocabulary_policy(&mut self, vocabulary_policy: VocabularyPolicy) {
+        self.vocabulary_policy = vocabulary_policy;
+    }
+
+    /// Also fetch the meta-schema named by the document's `$schema`, dereferenced, into its
+    /// `$defs`. It keeps its `$id`, so validators reading the output find the dialect there
+    /// instead of fetching it.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// jsonref.register_at("https://example.com/dialect.json", json!(
+    ///     {"$id": "https://example.com/dialect.json", "allOf": [{"$ref": "core.json"}]}
+    /// ));
+    /// jsonref.register_at("https://example.com/core.json", json!({"type": ["object", "boolean"]}));
+    ///
+    /// jsonref.set_bundle_meta_schema(true);
+    ///
+    /// let mut input = json!({"$schema": "https://example.com/dialect.json", "type": "string"});
+    /// jsonref.deref_value(&mut input).unwrap();
+    /// assert_eq!(input["$defs"]["dialect"], json!(
+    ///     {"$id": "https://example.com/dialect.json", "allOf": [{"type": ["object", "boolean"]}]}
+    /// ));
+    /// ```
+    pub fn set_bundle_meta_schema(&mut self, bundle_meta_schema: bool) {
+        self.bundle_meta_schema = bundle_meta_schema;
+    }
+
+    /// Dereference any JSON document, such as configuration files composed from fragments, with
+    /// the semantics of the JSON Reference draft instead of JSON Schema: `$id` and `$schema`
+    /// have no meaning, so refs are always resolved against the document's URL, and an object
+    /// with a `$ref` is replaced as a whole, without a warning about the keys next to it. Options
+    /// about schemas still apply if set.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut config = json!(
+    ///     {"defaults": {"timeout": 30, "retries": [1, 2, 4]},
+    ///      "services": {"$id": "billing", "api": {"$ref": "#/defaults"}, "retries": {"$ref": "#/defaults/retries/2"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_json_reference(true);
+    ///
+    /// jsonref.deref_value(&mut config).unwrap();
+    /// assert_eq!(config["services"]["api"], json!({"timeout": 30, "retries": [1, 2, 4]}));
+    /// assert_eq!(config["services"]["retries"], json!(4));
+    /// assert!(jsonref.warnings().is_empty());
+    /// ```
+    pub fn set_json_reference(&mut self, json_reference: bool) {
+        self.json_reference = json_reference;
+    }
+
+    /// Dereference an OpenAPI document. Refs are resolved everywhere in it, as with
+    /// [`TraversalPolicy::All`] whatever the policy, including in `callbacks`, `links` and
+    /// `webhooks`, except in example data: `example`, the `value` of Example Objects and the
+    /// `examples` of schemas are left as they are. The `operationRef` of a Link Object inlined
+    /// from another document is made absolute, or local if it points into the root document,
+    /// so it still points at the same operation.
+    ///
+    /// For OpenAPI 3.1 and later, a `summary` or `description` next to a `$ref` replaces the
+    /// one of the target, as the specification says, instead of being discarded with a
+    /// [`Warning::SiblingKeysDiscarded`]. Earlier versions ignore them.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut spec = json!(
+    ///     {"openapi": "3.1.0",
+    ///      "paths": {"/pets": {"get": {"responses": {"200": {"$ref": "#/components/responses/Pets"}}}}},
+    ///      "components": {"responses": {"Pets": {"description": "pets",
+    ///                                            "content": {"application/json": {"example": {"$ref": "data"}}}}}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_openapi(true);
+    ///
+    /// jsonref.deref_value(&mut spec).unwrap();
+    /// assert_eq!(spec["paths"]["/pets"]["get"]["responses"]["200"]["description"], json!("pets"));
+    /// assert_eq!(spec["components"]["responses"]["Pets"]["content"]["application/json"]["example"], json!({"$ref": "data"}));
+    /// ```
+    pub fn set_openapi(&mut self, openapi: bool) {
+        self.openapi = openapi;
+    }
+
+    /// Dereference an OpenRPC document. As with [`JsonRef::set_openapi`], refs are resolved
+    /// everywhere in it, in `methods` and in `components` such as `schemas`,
+    /// `contentDescriptors` and `examplePairingObjects`, except in example data: the `value` of
+    /// Example Objects and the `examples` of schemas are left as they are.
+    /// [`JsonRef::set_keep_components`] applies to OpenRPC documents too.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut spec = json!(
+    ///     {"openrpc": "1.2.6",
+    ///      "methods": [{"name": "get_pet", "params": [{"$ref": "#/components/contentDescriptors/PetId"}],
+    ///                   "result": {"name": "pet", "schema": {"type": "object"}}}],
+    ///      "components": {"contentDescriptors": {"PetId": {"name": "id", "schema": {"type": "integer"}}}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_openrpc(true);
+    ///
+    /// jsonref.deref_value(&mut spec).unwrap();
+    /// assert_eq!(spec["methods"][0]["params"][0], json!({"name": "id", "schema": {"type": "integer"}}));
+    /// ```
+    pub fn set_openrpc(&mut self, openrpc: bool) {
+        self.openrpc = openrpc;
+    }
+
+    /// In OpenAPI or OpenRPC mode, leave the `components` of the document as they are, refs between them
+    /// included, and only inline the refs elsewhere, such as in `paths`. Docs renderers can then
+    /// still show each component once.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut spec = json!(
+    ///     {"openapi": "3.1.0",
+    ///      "paths": {"/pets": {"get": {"responses": {"200": {"$ref": "#/components/responses/Pets"}}}}},
+    ///      "components": {"responses": {"Pets": {"description": "pets",
+    ///                                            "content": {"application/json": {"schema": {"$ref": "#/components/schemas/Pet"}}}}},
+    ///                     "schemas": {"Pet": {"type": "object"}}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_openapi(true);
+    /// jsonref.set_keep_components(true);
+    ///
+    /// jsonref.deref_value(&mut spec).unwrap();
+    /// let response = &spec["paths"]["/pets"]["get"]["responses"]["200"];
+    /// assert_eq!(response["content"]["application/json"]["schema"], json!({"type": "object"}));
+    /// assert_eq!(
+    ///     spec["components"]["responses"]["Pets"]["content"]["application/json"]["schema"],
+    ///     json!({"$ref": "#/components/schemas/Pet"})
+    /// );
+    /// ```
+    pub fn set_keep_components(&mut self, keep_components: bool) {
+        self.keep_components = keep_components;
+    }
+
+    /// Produce the structural schema a Kubernetes CustomResourceDefinition needs as its
+    /// `openAPIV3Schema`: after every ref is inlined, the output is rewritten with
+    /// [`crd::structural`], and what can not be represented, including recursive refs, is
+    /// reported in [`JsonRef::warnings`].
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"$schema": "https://json-schema.org/draft/2020-12/schema",
+    ///      "$defs": {"port": {"type": "integer", "maximum": 65535}},
+    ///      "properties": {"port": {"$ref": "#/$defs/port"}, "labels": {"propertyNames": {"maxLength": 63}}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_crd_structural(true);
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    /// assert_eq!(input, json!({"type": "object",
+    ///                          "properties": {"port": {"type": "integer", "maximum": 65535}, "labels": {}}}));
+    /// assert_eq!(jsonref.warnings().len(), 2);
+    /// ```
+    pub fn set_crd_structural(&mut self, crd_structural: bool) {
+        self.crd_structural = crd_structural;
+    }
+
+    /// Check the pointers of the `$data` extension, as used by ajv in `{"const": {"$data":
+    /// "1/password"}}`, failing with [`Error::InvalidDataPointer`] unless each is a JSON pointer
+    /// or a relative JSON pointer. `$data` refers to the instance being validated, not to a
+    /// schema, so it is always left as it is.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// jsonref.set_check_data_pointers(true);
+    ///
+    /// let mut input = json!({"properties": {"confirm": {"const": {"$data": "1/password"}}}});
+    /// jsonref.deref_value(&mut input).unwrap();
+    /// assert_eq!(input["properties"]["confirm"]["const"], json!({"$data": "1/password"}));
+    ///
+    /// let mut input = json!({"properties": {"confirm": {"const": {"$data": "password"}}}});
+    /// let error = jsonref.deref_value(&mut input).unwrap_err();
+    /// assert_eq!(
+    ///     error.to_string(),
+    ///     "Invalid $data `password` at /properties/confirm/const: not a JSON pointer or relative JSON pointer"
+    /// );
+    /// ```
+    pub fn set_check_data_pointers(&mut self, check_data_pointers: bool) {
+        self.check_data_pointers = check_data_pointers;
+    }
+
+    /// Embed the text of `file` and `http` documents that are not JSON (or YAML, for `.yaml`
+    /// files with the `serde_yaml` feature) as a string, such as a Markdown description or an
+    /// example payload, instead of failing to parse them. Refs to such documents can not have a
+    /// fragment.
+    ///
+    /// A document is taken to be JSON, and still fails if it does not parse, when its
+    /// `Content-Type` or file extension says it is JSON or YAML, or when it has neither.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"type": "object", "description": {"$ref": "fixtures/embed/person.md"}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_embed_non_json(true);
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    /// assert_eq!(input["description"], json!("# Person\n\nSomeone with a *name*.\n"));
+    /// ```
+    pub fn set_embed_non_json(&mut self, embed_non_json: bool) {
+        self.embed_non_json = embed_non_json;
+    }
+
+    /// Resolve refs to the official draft-04, -06, -07, 2019-09 and 2020-12 meta-schemas and
+    /// their vocabulary meta-schemas on `json-schema.org` from copies embedded in the crate,
+    /// without network access. On by default; turn it off to always fetch them.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"properties": {"schema": {"$ref": "http://json-schema.org/draft-07/schema#"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    /// assert_eq!(input["properties"]["schema"]["title"], json!("Core schema meta-schema"));
+    /// ```
+    pub fn set_offline_meta_schemas(&mut self, offline_meta_schemas: bool) {
+        self.offline_meta_schemas = offline_meta_schemas;
+    }
+
+    /// Check that every `$ref` is a valid URI-reference (RFC 3986) before resolving it, failing
+    /// with [`Error::InvalidRef`] on spaces, backslashes, bad `%` escapes and other characters
+    /// that must be escaped. Otherwise these are resolved as well as they can be, which may fail
+    /// later in a confusing way or find the wrong document.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!({"properties": {"a": {"$ref": "my schema.json"}}});
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_strict_refs(true);
+    ///
+    /// let error = jsonref.deref_value(&mut input).unwrap_err();
+    /// assert_eq!(error.to_string(), "Invalid $ref `my schema.json`: contains a space");
+    /// ```
+    pub fn set_strict_refs(&mut self, strict_refs: bool) {
+        self.strict_refs = strict_refs;
+    }
+
+    /// Resolve refs such as `registry://orders-value/3` from the schema registry at `url`, by
+    /// subject and version, with `latest` used when there is no version. The registry must
+    /// provide the Confluent API, `GET {url}/subjects/{subject}/versions/{version}`, as Apicurio
+    /// does under `/apis/ccompat/v7`. Without a registry such refs fail with
+    /// [`Error::UnsupportedUrl`].
+    ///
+    /// ```no_run
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!({"properties": {"order": {"$ref": "registry://orders-value/3"}}});
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_schema_registry("http://localhost:8081");
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    /// ```
+    pub fn set_schema_registry(&mut self, url: &str) {
+        self.schema_registry = Some(url.trim_end_matches('/').to_owned());
+    }
+
+    /// Use the schema catalog at `url`, or at the path `url` relative to the current directory,
+    /// for [`JsonRef::deref_known`], rather than the one of
+    /// [SchemaStore](https://www.schemastore.org). The catalog has SchemaStore's layout, a
+    /// `schemas` array of entries with a `name`, `fileMatch` globs and a `url`.
+    pub fn set_schema_catalog(&mut self, url: &str) {
+        self.schema_catalog = url.to_owned();
+    }
+
+    /// Send requests for `http` and `https` documents with `options` when they are to the host
+    /// `scope`, such as `registry.internal.example.com`, or, if `scope` has a scheme, to URLs
+    /// under it, such as `https://example.com/private/`: with the same scheme, host and port,
+    /// and a path below its path, `/private` not matching `/private-keys`. The most specific
+    /// scope applies: the longest matching URL prefix, then the host. Setting a scope again
+    /// replaces its options.
+    ///
+    /// ```no_run
+    /// # use jsonref::JsonRef;
+    /// use jsonref::http::HostOptions;
+    /// use serde_json::json;
+    /// use std::time::Duration;
+    ///
+    /// let mut input = json!({"properties": {"order": {"$ref": "https://registry.internal.example.com/order.json"},
+    ///                                       "address": {"$ref": "https://schemas.example.com/address.json"}}});
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_host_options(
+    ///     "registry.internal.example.com",
+    ///     HostOptions::new().bearer_token("s3cr3t").timeout(Duration::from_secs(5)),
+    /// );
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    /// ```
+    pub fn set_host_options(&mut self, scope: &str, options: http::HostOptions) {
+        self.http.set_host_options(scope, options);
+    }
+
+    /// Authenticate `https` requests to hosts without an `Authorization` header in their
+    /// [`JsonRef::set_host_options`] with credentials from the environment or the netrc file,
+    /// as described in the [`http`] module. Off by default.
+    pub fn set_ambient_credentials(&mut self, ambient_credentials: bool) {
+        self.http.ambient_credentials = ambient_credentials;
+    }
+
+    /// With [`JsonRef::set_ambient_credentials`], also send the login of the `default` entry of
+    /// the netrc file to hosts without a `machine` entry of their own. Off by default, as that
+    /// sends it to every host.
+    pub fn set_netrc_default(&mut self, netrc_default: bool) {
+        self.http.netrc_default = netrc_default;
+    }
+
+    /// Allow `http+unix` urls to fetch from the Unix domain socket `socket`, such as a schema
+    /// service run as a sidecar. The socket path is the percent-encoded host of the url, as in
+    /// `http+unix://%2Fvar%2Frun%2Fregistry.sock/schemas/order.json`. No socket is allowed by
+    /// default, so that documents can not reach local services unasked.
+    pub fn add_unix_socket<P: AsRef<Path>>(&mut self, socket: P) {
+        self.http.unix_sockets.push(socket.as_ref().to_owned());
+    }
+
+    /// Keep up to `max_idle` idle connections for reuse, and up to `max_idle_per_host` to any
+    /// one host, rather than 100 and 1. Every document is fetched with the same client, so
+    /// connections, and their TLS sessions, are reused across documents and by clones of this
+    /// `JsonRef`. Zero turns keep-alive off.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// // Fetching many documents from a few registries.
+    /// jsonref.set_connection_pool(32, 8);
+    /// ```
+    pub fn set_connection_pool(&mut self, max_idle: usize, max_idle_per_host: usize) {
+        self.http.set_connection_pool(max_idle, max_idle_per_host);
+    }
+
+    /// Connect to `address` for requests to `host`, rather than looking it up, as curl's
+    /// `--resolve` does, for air-gapped environments where the hosts of canonical `$id`s do not
+    /// resolve but mirrors are at known addresses. The port, `Host` header and TLS server name
+    /// are still those of the URL. To fetch from a mirror with different URLs, see
+    /// [`JsonRef::set_mirror`].
+    ///
+    /// ```no_run
+    /// # use jsonref::JsonRef;
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_host_address("schemas.example.com", "10.0.12.7".parse().unwrap());
+    /// ```
+    pub fn set_host_address(&mut self, host: &str, address: std::net::IpAddr) {
+        self.http.set_host_address(host, address);
+    }
+
+    /// Fetch documents with URLs starting with `prefix` from `base` instead, with the rest
+    /// of the URL appended, keeping them known by their original URL as with
+    /// [`JsonRef::set_url_rewriter`], which takes precedence. The longest matching prefix
+    /// applies.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mirror = format!("file://{}/fixtures/", env!("CARGO_MANIFEST_DIR"));
+    /// let mut input = json!({"$ref": "https://schemas.example.com/nested_relative/other.json#/properties/prop1"});
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_mirror("https://schemas.example.com/", &mirror);
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    /// assert_eq!(input, json!({"title": "sub property title in other.json"}));
+    /// ```
+    pub fn set_mirror(&mut self, prefix: &str, base: &str) {
+        self.mirrors.insert(prefix.to_owned(), base.to_owned());
+    }
+
+    /// Choose what happens to the `$id` and `$schema` of inlined and bundled schemas.
+    ///
+    /// ```
+    /// # use jsonref::{IdPolicy, JsonRef};
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"$defs": {"address": {"$id": "https://example.com/address.json", "type": "object"}},
+    ///      "properties": {"home": {"$ref": "#/$defs/address"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_id_policy(IdPolicy::Fragment);
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// assert_eq!(input["properties"]["home"], json!({"$id": "#address", "type": "object"}));
+    /// ```
+    pub fn set_id_policy(&mut self, id_policy: IdPolicy) {
+        self.id_policy = id_policy;
+    }
+
+    /// Remove the given annotation keywords, such as `description` or `examples`, from the
+    /// dereferenced schema. See [`transform::strip_keywords`].
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"properties": {"prop1": {"title": "name", "description": "a name", "type": "string"},
+    ///                     "prop2": {"$ref": "#/properties/prop1"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_strip_keywords(&["title", "description"]);
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// assert_eq!(input["properties"]["prop2"], json!({"type": "string"}));
+    /// ```
+    pub fn set_strip_keywords(&mut self, keywords: &[&str]) {
+        self.strip_keywords = keywords.iter().map(|keyword| keyword.to_string()).collect();
+    }
+
+    /// When the same target is inlined identically at `threshold` or more places, put it in the
+    /// `$defs` of the root schema once and replace each of those places with a `$ref` to it.
+    /// Targets that are already definitions of the root schema are pointed at where they are. A
+    /// threshold of 0 counts as 1.
+    /// The output is a plain [`Value`], which can not share one subtree between places, so this
+    /// is the way to keep the output small when a large definition is used in many places;
+    /// [`analyze::SchemaStats::size_repeated`] tells how much it would save.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"properties": {"prop1": {"$ref": "#/properties/prop3"},
+    ///                     "prop2": {"$ref": "#/properties/prop3"},
+    ///                     "prop3": {"title": "name"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_dedupe_threshold(2);
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// assert_eq!(input["properties"]["prop1"], json!({"$ref": "#/$defs/prop3"}));
+    /// assert_eq!(input["properties"]["prop2"], json!({"$ref": "#/$defs/prop3"}));
+    /// assert_eq!(input["$defs"]["prop3"], json!({"title": "name"}));
+    /// ```
+    pub fn set_dedupe_threshold(&mut self, threshold: usize) {
+        self.dedupe_threshold = Some(threshold.max(1));
+    }
+
+    /// Rename every `definitions` or `$defs` keyword to `keyword` in all schemas read, rewriting
+    /// the pointers of `$ref`s to match. Bundled targets are also put under `keyword`.
+    ///
+    /// ```
+    /// # use jsonref::{DefinitionsKeyword, JsonRef};
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"definitions": {"name": {"type": "string"}},
+    ///      "properties": {"prop1": {"$ref": "#/definitions/name"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_normalize_definitions(DefinitionsKeyword::Defs);
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// assert_eq!(input, json!(
+    ///     {"$defs": {"name": {"type": "string"}},
+    ///      "properties": {"prop1": {"type": "string"}}}
+    /// ));
+    /// ```
+    pub fn set_normalize_definitions(&mut self, keyword: DefinitionsKeyword) {
+        self.normalize_definitions = Some(keyword);
+    }
+
+    /// Migrate every document from draft-04, -06 or -07 to draft 2020-12 as it is read, with
+    /// [`transform::migrate_to_2020_12`], so that legacy schemas spread over several files come
+    /// out as a single modern one. Migrating first also makes draft-04 `id`s count when refs
+    /// are resolved.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"$schema": "http://json-schema.org/draft-07/schema#",
+    ///      "definitions": {"pair": {"items": [{"type": "string"}, {"type": "number"}]}},
+    ///      "properties": {"pair": {"$ref": "#/definitions/pair"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_migrate_drafts(true);
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// assert_eq!(input["$schema"], "https://json-schema.org/draft/2020-12/schema");
+    /// assert_eq!(input["properties"]["pair"], json!(
+    ///     {"prefixItems": [{"type": "string"}, {"type": "number"}]}
+    /// ));
+    /// ```
+    pub fn set_migrate_drafts(&mut self, migrate_drafts: bool) {
+        self.migrate_drafts = migrate_drafts;
+    }
+
+    /// Guarantee the same output bytes for the same inputs on any platform and build, for
+    /// outputs used as cache keys. Without this option the output is already the same from run
+    /// to run: bundled and deduplicated `$defs` are named from ref URLs in the order refs are
+    /// met, and refs are met in key order. This option also sorts the keys of every object in
+    /// the output, which `serde_json` otherwise leaves in insertion order when built with its
+    /// `preserve_order` feature.
+    ///
+    /// File URLs in the output, from [`JsonRef::set_source_key`] or
+    /// [`JsonRef::set_absolute_refs`], depend on where the files are, so leave those options
+    /// off, or map the URLs with [`JsonRef::set_url_rewriter`], to compare outputs made on
+    /// different machines.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!({"properties": {"b": {"$ref": "#/$defs/b"}, "a": {}}, "$defs": {"b": {"type": "string"}}});
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// jsonref.set_deterministic(true);
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// assert_eq!(input.to_string(), r#"{"$defs":{"b":{"type":"string"}},"properties":{"a":{},"b":{"type":"string"}}}"#);
+    /// ```
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    /// Record the JSON Patch operations that turn each document into its dereferenced form,
+    /// available from [`JsonRef::patch`] after each run.
+    pub fn set_record_patch(&mut self, record_patch: bool) {
+        self.record_patch = record_patch;
+    }
+
+    /// Validate each dereferenced document against the meta-schema named by its `$schema`
+    /// (draft 2020-12 when missing) and fail with [`Error::MetaSchemaViolations`] if inlining
+    /// produced an invalid schema. Needs the `jsonschema` feature.
+    #[cfg(feature = "jsonschema")]
+    pub fn set_validate_meta_schema(&mut self, validate_meta_schema: bool) {
+        self.validate_meta_schema = validate_meta_schema;
+    }
+
+    /// A retriever for the `jsonschema` crate sharing the documents of this instance, so a
+    /// validator built after dereferencing does not fetch them again, and documents the validator
+    /// fetches are not fetched again for later refs. Documents neither has loaded are fetched the
+    /// way refs are. Needs the `jsonschema` feature.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// let mut jsonref = JsonRef::new();
+    /// let schema = jsonref.deref_file("fixtures/nested_relative/base.json").unwrap();
+    ///
+    /// let validator = jsonschema::options()
+    ///     .with_retriever(jsonref.retriever())
+    ///     .build(&schema)
+    ///     .unwrap();
+    /// assert!(validator.is_valid(&serde_json::json!({"prop3": {}})));
+    /// ```
+    #[cfg(feature = "jsonschema")]
+    pub fn retriever(&self) -> validate::CacheRetriever {
+        let mut loader = self.clone();
+        loader.schema_cache = self.schema_cache.share();
+        validate::CacheRetriever::new(loader)
+    }
+
+    /// The JSON Patch operations recorded by the last run when [`JsonRef::set_record_patch`] is
+    /// on.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use jsonref::patch::PatchOperation;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"properties": {"prop1": {"title": "name"},
+    ///                     "prop2": {"$ref": "#/properties/prop1"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_record_patch(true);
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// assert_eq!(jsonref.patch(), &[
+    ///     PatchOperation::Remove { path: "/properties/prop2/$ref".into() },
+    ///     PatchOperation::Add { path: "/properties/prop2/title".into(), value: json!("name") },
+    /// ]);
+    /// ```
+    pub fn patch(&self) -> &[PatchOperation] {
+        &self.patch
+    }
+
+    /// Every `$ref` replaced during the last run, in the order they were replaced.
+    pub fn replacements(&self) -> &[Replacement] {
+        &self.replacements
+    }
+
+    /// Iterate over every subschema of `output`, the output of the last run, as
+    /// [`walk::subschemas`] does, with the URL each was inlined or bundled from as its source.
+    /// Subschemas from the input document itself have the URL it was read from.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"$defs": {"name": {"type": "string"}},
+    ///      "properties": {"name": {"$ref": "#/$defs/name"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// jsonref.deref_value_with_url(&mut input, "https://example.com/person.json").unwrap();
+    ///
+    /// for subschema in jsonref.subschemas(&input) {
+    ///     println!("{} from {:?}", subschema.pointer, subschema.source);
+    /// }
+    /// let name = jsonref.subschemas(&input).find(|subschema| subschema.pointer == "/properties/name").unwrap();
+    /// assert_eq!(name.source, Some("https://example.com/person.json#/$defs/name"));
+    /// ```
+    pub fn subschemas<'a>(&'a self, output: &'a Value) -> impl Iterator<Item = walk::Subschema<'a>> + 'a {
+        let defs_keyword = self.defs_keyword();
+        // Where targets were inserted, and the URL each came from.
+        let mut origins: Vec<(String, &str)> = self
+            .bundle_names
+            .iter()
+            .map(|(url, name)| (format!("/{}/{}", defs_keyword, escape_pointer(name)), url.as_str()))
+            .collect();
+        origins.extend(
+            self.replacements
+                .iter()
+                .filter(|replacement| !replacement.bundled)
+                .map(|replacement| (replacement.pointer.clone(), replacement.url.as_str())),
+        );
+        walk::subschemas(output).map(move |mut subschema| {
+            // The innermost origin containing the subschema, and the last of equal ones, as a
+            // ref to a ref is replaced after the ref itself.
+            let source = origins
+                .iter()
+                .filter(|(pointer, _)| {
+                    subschema.pointer == *pointer || subschema.pointer.starts_with(&format!("{}/", pointer))
+                })
+                .max_by_key(|(pointer, _)| pointer.len())
+                .map(|(_, url)| *url);
+            subschema.source = Some(source.unwrap_or(&self.root_url));
+            subschema
+        })
+    }
+
+    /// The issues met during the last run, in the order they were met.
+    ///
+    /// ```
+    /// # use jsonref::{JsonRef, Warning};
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"$defs": {"name": {"type": "string"}},
+    ///      "properties": {"name": {"$ref": "#/$defs/name", "description": "Full name"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// assert_eq!(jsonref.warnings(), [Warning::SiblingKeysDiscarded {
+    ///     pointer: "/properties/name".into(),
+    ///     keys: vec!["description".into()],
+    /// }]);
+    /// ```
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Report what the last run replaced in `output`, keyed by the JSON pointer of each replaced
+    /// `$ref`. Each entry has the `ref` as written, the absolute URL it came `from`, the
+    /// `original` object containing the ref and the `replacement` now found there.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"properties": {"prop1": {"title": "name"},
+    ///                     "prop2": {"$ref": "#/properties/prop1"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// let diff = jsonref.diff(&input);
+    /// assert_eq!(diff["/properties/prop2"]["original"], json!({"$ref": "#/properties/prop1"}));
+    /// assert_eq!(diff["/properties/prop2"]["replacement"], json!({"title": "name"}));
+    /// assert!(diff["/properties/prop2"]["from"].as_str().unwrap().ends_with("#/properties/prop1"));
+    /// ```
+    pub fn diff(&self, output: &Value) -> Value {
+        let mut report = Map::new();
+        for replacement in &self.replacements {
+            // A ref to a ref is replaced twice at the same pointer, report the one as written.
+            report.entry(replacement.pointer.clone()).or_insert_with(|| {
+                serde_json::json!({
+                    "ref": replacement.reference,
+                    "from": replacement.url,
+                    "original": replacement.original,
+                    "replacement": output.pointer(&replacement.pointer).cloned().unwrap_or(Value::Null),
+                })
+            });
+        }
+        Value::Object(report)
+    }
+
+    /// Make `schema` available to `$ref`s by its `$id`, so it is never fetched. Refs within it are
+    /// resolved against the `$id`. Definitions are normalized, and legacy drafts migrated, if
+    /// [`JsonRef::set_normalize_definitions`] or [`JsonRef::set_migrate_drafts`] was set before
+    /// registering.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.register(json!({"$id": "https://example.com/name.json", "type": "string"})).unwrap();
+    ///
+    /// let mut input = json!({"properties": {"name": {"$ref": "https://example.com/name.json"}}});
+    /// jsonref.deref_value(&mut input).unwrap();
+    ///
+    /// assert_eq!(input["properties"]["name"]["type"], json!("string"));
+    /// ```
+    pub fn register(&mut self, mut schema: Value) -> Result<()> {
+        self.prepare(&mut schema);
+        let id = schema.get("$id").and_then(Value::as_str).ok_or(Error::SchemaWithoutId)?;
+        let mut url = Url::parse(id).context(UrlParseError {url: id.to_owned()})?;
+        url.set_fragment(None);
+        self.schema_cache.insert(url.to_string(), schema);
+        Ok(())
+    }
+
+    /// Make `schema` available to `$ref`s at `url`, so it is never fetched.
+    pub fn register_at(&mut self, url: &str, mut schema: Value) {
+        self.prepare(&mut schema);
+        self.schema_cache.insert(url.to_owned(), schema);
+    }
+
+    /// Parse and [`JsonRef::register`] a schema embedded with `include_str!`. See
+    /// [`register_embedded!`] to register several.
+    pub fn register_str(&mut self, schema: &str) -> Result<()> {
+        let schema = serde_json::from_str(schema).context(SchemaNotJsonSerde {url: "embedded schema"})?;
+        self.register(schema)
+    }
+
+    /// The URLs of every document this instance has loaded, sorted. Includes the root of each
+    /// dereferenced document, using the anonymous `file://` URL for values.
+    pub fn documents(&self) -> Vec<String> {
+        self.schema_cache.urls()
+    }
+
+    /// Every document this instance has loaded, as a JSON object of URL to document, to save
+    /// with [`JsonRef::write_snapshot`] and load again for runs that need nothing fetched.
+    pub fn snapshot(&self) -> Value {
+        Value::Object(self.schema_cache.entries().into_iter().map(|(url, document)| (url, Value::clone(&document))).collect())
+    }
+
+    /// Write [`JsonRef::snapshot`] to the file at `path`.
+    pub fn write_snapshot(&self, path: &str) -> Result<()> {
+        let json = serde_json::to_vec_pretty(&self.snapshot()).context(SchemaNotJsonSerde {url: path.to_owned()})?;
+        fs::write(path, json).context(WriteOutput {path: path.to_owned()})
+    }
+
+    /// Load a snapshot written by [`JsonRef::write_snapshot`], making each of its documents
+    /// available at its URL as [`JsonRef::register_at`] does, so the same refs resolve the same
+    /// way without fetching anything.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// let snapshot = std::env::temp_dir().join("jsonref-snapshot-doctest.json");
+    /// let snapshot = snapshot.to_str().unwrap();
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// let output = jsonref.deref_file("fixtures/nested_relative/base.json").unwrap();
+    /// jsonref.write_snapshot(snapshot).unwrap();
+    ///
+    /// let mut offline = JsonRef::new();
+    /// offline.load_snapshot(snapshot).unwrap();
+    /// assert_eq!(offline.documents(), jsonref.documents());
+    ///
+    /// let url = jsonref.documents().into_iter().find(|url| url.ends_with("base.json")).unwrap().to_owned();
+    /// assert_eq!(offline.deref_url(&url).unwrap(), output);
+    /// ```
+    pub fn load_snapshot(&mut self, path: &str) -> Result<()> {
+        let file = fs::File::open(path).context(SchemaFromFile {filename: path.to_owned()})?;
+        let snapshot: Value = serde_json::from_reader(io::BufReader::new(file)).context(SchemaNotJsonSerde {url: path.to_owned()})?;
+        let documents = match snapshot {
+            Value::Object(documents) => documents,
+            _ => {
+                return Err(Error::InvalidSnapshot {
+                    path: path.to_owned(),
+                    reason: "not an object of URL to document".to_owned(),
+                })
+            }
+        };
+        for (url, document) in documents {
+            Url::parse(&url).map_err(|error| Error::InvalidSnapshot {
+                path: path.to_owned(),
+                reason: format!("{} is not a URL: {}", url, error),
+            })?;
+            self.schema_cache.insert(url, document);
+        }
+        Ok(())
+    }
+
+    /// Fetch the document at `url` again, replacing the cached copy, for when it is known to
+    /// have changed. Any fragment of `url` is ignored.
+    pub fn refresh(&mut self, url: &str) -> Result<()> {
+        let mut document_url = Url::parse(url).context(UrlParseError {url: url.to_owned()})?;
+        document_url.set_fragment(None);
+        let mut fetched = self.fetch(&document_url)?;
+        self.prepare(&mut fetched);
+        self.cache_fetched(document_url.to_string(), fetched);
+        Ok(())
+    }
+
+    /// Statistics of `output` and every document this instance has loaded, including the
+    /// original of each document it dereferenced.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// let mut jsonref = JsonRef::new();
+    /// let output = jsonref.deref_file("fixtures/nested_relative/base.json").unwrap();
+    ///
+    /// let report = jsonref.stats(&output);
+    ///
+    /// assert_eq!(report.refs, 5);
+    /// assert_eq!(report.fan_out.len(), 2);
+    /// assert_eq!(report.properties, 7);
+    /// ```
+    pub fn stats(&self, output: &Value) -> analyze::SchemaStats {
+        self.with_sources(|sources| analyze::stats(sources, output))
+    }
+
+    /// The document dependency graph of every document read or registered so far, see
+    /// [`analyze::ref_graph`].
+    pub fn ref_graph(&self) -> BTreeMap<String, BTreeSet<String>> {
+        self.with_sources(analyze::ref_graph)
+    }
+
+    /// Call `f` with every document loaded so far and its URL, sorted by URL.
+    fn with_sources<T>(&self, f: impl FnOnce(&[(&str, &Value)]) -> T) -> T {
+        let entries = self.schema_cache.entries();
+        let sources: Vec<(&str, &Value)> = entries.iter().map(|(url, document)| (url.as_str(), &**document)).collect();
+        f(&sources)
+    }
+
+    /// Replace `$ref`s in `value` without following any: `rewrite` is called for each one,
+    /// resolved as dereferencing would, and returns the new ref or `None` to keep it. Relative
+    /// refs are resolved against the current working directory, or an enclosing `$id`. Returns
+    /// how many refs were replaced.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"$id": "https://example.com/v1/person.json",
+    ///      "properties": {"address": {"$ref": "address.json"}, "name": {"$ref": "#/$defs/name"}}}
+    /// );
+    ///
+    /// let jsonref = JsonRef::new();
+    /// let replaced = jsonref.rewrite_refs(&mut input, |site| {
+    ///     site.url.strip_prefix("https://example.com/v1/")
+    ///         .filter(|_| !site.reference.starts_with('#'))
+    ///         .map(|path| format!("https://cdn.example.com/v2/{}", path))
+    /// }).unwrap();
+    ///
+    /// assert_eq!(replaced, 1);
+    /// assert_eq!(input["properties"]["address"], json!({"$ref": "https://cdn.example.com/v2/address.json"}));
+    /// assert_eq!(input["properties"]["name"], json!({"$ref": "#/$defs/name"}));
+    /// ```
+    pub fn rewrite_refs<F>(&self, value: &mut Value, rewrite: F) -> Result<usize>
+    where
+        F: FnMut(&RefSite) -> Option<String>,
+    {
+        let anon_file_url = format!("file://{}/anon.json", env::current_dir().context(JSONRefError {})?.to_string_lossy());
+        self.rewrite_refs_with_url(value, &anon_file_url, rewrite)
+    }
+
+    /// [`JsonRef::rewrite_refs`] with relative refs resolved against `url`.
+    pub fn rewrite_refs_with_url<F>(&self, value: &mut Value, url: &str, mut rewrite: F) -> Result<usize>
+    where
+        F: FnMut(&RefSite) -> Option<String>,
+    {
+        let mut replaced = 0;
+        self.rewrite_at(value, url.to_owned(), "", &mut rewrite, &mut replaced)?;
+        Ok(replaced)
+    }
+
+    fn rewrite_at(
+        &self,
+        value: &mut Value,
+        id: String,
+        pointer: &str,
+        rewrite: &mut dyn FnMut(&RefSite) -> Option<String>,
+        replaced: &mut usize,
+    ) -> Result<()> {
+        let new_id = self.join_id(value, id);
+        if let Some(obj) = value.as_object_mut() {
+            if let Some(Value::String(reference)) = obj.get_mut("$ref") {
+                if self.strict_refs {
+                    check_uri_reference(reference)?;
+                }
+                let id_url = Url::parse(&new_id).context(UrlParseError {url: new_id.clone()})?;
+                let ref_url = id_url.join(reference).context(UrlParseError {url: reference.clone()})?;
+                let site = RefSite {
+                    reference,
+                    url: ref_url.as_str(),
+                    pointer,
+                };
+                if let Some(new_reference) = rewrite(&site) {
+                    *reference = new_reference;
+                    *replaced += 1;
+                }
+            }
+            for (key, child) in obj.iter_mut() {
+                let child_pointer = format!("{}/{}", pointer, escape_pointer(key));
+                self.rewrite_at(child, new_id.clone(), &child_pointer, rewrite, replaced)?;
+            }
+        } else if let Some(array) = value.as_array_mut() {
+            for (index, child) in array.iter_mut().enumerate() {
+                self.rewrite_at(child, new_id.clone(), &format!("{}/{}", pointer, index), rewrite, replaced)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// deref a serde_json value directly. Uses the current working directory for any relative
+    /// refs.
+    pub fn deref_value(&mut self, value: &mut Value) -> Result<()> {
+        let anon_file_url = format!("file://{}/anon.json", env::current_dir().context(JSONRefError {})?.to_string_lossy());
+        self.run(value, anon_file_url)
+    }
+
+    /// deref a schema generated by [`schemars`] into a flattened serde_json value, with its
+    /// `definitions` removed once nothing refers to them. Needs the `schemars` feature.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use schemars::{schema_for, JsonSchema};
+    /// use serde_json::json;
+    ///
+    /// #[derive(JsonSchema)]
+    /// struct Point { x: i32 }
+    ///
+    /// #[derive(JsonSchema)]
+    /// struct Line { start: Point, end: Point }
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// let schema = jsonref.deref_schemars(&schema_for!(Line)).unwrap();
+    ///
+    /// assert!(schema.get("definitions").is_none());
+    /// assert_eq!(schema["properties"]["start"]["properties"]["x"]["type"], json!("integer"));
+    /// ```
+    #[cfg(feature = "schemars")]
+    pub fn deref_schemars(&mut self, root: &schemars::schema::RootSchema) -> Result<Value> {
+        let mut value = serde_json::to_value(root).context(SchemaNotJsonSerde {url: "schemars RootSchema"})?;
+        self.deref_value(&mut value)?;
+        if !has_local_ref(&value) {
+            if let Some(obj) = value.as_object_mut() {
+                obj.remove("definitions");
+                obj.remove("$defs");
+            }
+        }
+        Ok(value)
+    }
+
+    /// deref a serde_json value directly, resolving relative refs against `url` as if the value
+    /// had been loaded from there.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let base = format!("file://{}/fixtures/nested_relative/base.json", std::env::current_dir().unwrap().display());
+    /// let mut input = json!({"properties": {"prop1": {"$ref": "other.json#/properties/prop1"}}});
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// jsonref.deref_value_with_url(&mut input, &base).unwrap();
+    ///
+    /// assert_eq!(input["properties"]["prop1"], json!({"title": "sub property title in other.json"}));
+    /// ```
+    pub fn deref_value_with_url(&mut self, value: &mut Value, url: &str) -> Result<()> {
+        let url = Url::parse(url).context(UrlParseError {url: url.to_owned()})?;
+        self.run(value, url.to_string())
+    }
+
+    /// deref several documents that refer to each other, returning them in the order given. Each
+    /// is registered at its URL, and at its `$id` if it has one, before any is dereferenced, so
+    /// refs between them never fetch anything. Paths that are not URLs are taken relative to the
+    /// current working directory and need not exist.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let person = json!(
+    ///     {"$id": "https://example.com/person.json",
+    ///      "properties": {"address": {"$ref": "address.json"}},
+    ///      "$defs": {"name": {"type": "string"}}}
+    /// );
+    /// let address = json!({"properties": {"recipient": {"$ref": "https://example.com/person.json#/$defs/name"}}});
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// let output = jsonref
+    ///     .deref_documents(vec![("schemas/person.json", person), ("https://example.com/address.json", address)])
+    ///     .unwrap();
+    ///
+    /// assert_eq!(output[0]["properties"]["address"]["properties"]["recipient"], json!({"type": "string"}));
+    /// assert_eq!(output[1]["properties"]["recipient"], json!({"type": "string"}));
+    /// ```
+    pub fn deref_documents<I, S>(&mut self, documents: I) -> Result<Vec<Value>>
+    where
+        I: IntoIterator<Item = (S, Value)>,
+        S: AsRef<str>,
+    {
+        let cwd = Url::from_directory_path(env::current_dir().context(JSONRefError {})?).map_err(|_| Error::UnsupportedUrl {
+            url: "current directory".to_owned(),
+            reason: "not an absolute path".to_owned(),
+        })?;
+        let mut documents: Vec<(String, Value)> = documents
+            .into_iter()
+            .map(|(location, document)| {
+                let location = location.as_ref();
+                let url = Url::parse(location)
+                    .or_else(|_| cwd.join(location))
+                    .context(UrlParseError {url: location.to_owned()})?;
+                Ok((url.to_string(), document))
+            })
+            .collect::<Result<_>>()?;
+
+        for (url, document) in &documents {
+            self.register_at(url, document.clone());
+            if document.get("$id").and_then(Value::as_str).is_some() && !self.json_reference {
+                self.register(document.clone())?;
+            }
+        }
+        for (url, document) in documents.iter_mut() {
+            self.run(document, url.clone())?;
+        }
+        Ok(documents.into_iter().map(|(_, document)| document).collect())
+    }
+
+    /// Dereference a well-known schema by name, such as `tsconfig` or `.prettierrc`, looked up
+    /// in the [SchemaStore](https://www.schemastore.org) catalog or the one set with
+    /// [`JsonRef::set_schema_catalog`]. A name matches an entry with that `name`, one of its
+    /// `fileMatch` patterns or the file name of its `url`, ignoring case and a `.json`
+    /// extension. The catalog and the schema are cached as any other document, so later lookups
+    /// and refs into the schema are not fetched again.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.set_schema_catalog("fixtures/schemastore/catalog.json");
+    ///
+    /// let tsconfig = jsonref.deref_known("tsconfig").unwrap();
+    /// assert_eq!(
+    ///     tsconfig["properties"]["compilerOptions"]["properties"]["target"],
+    ///     json!({"enum": ["es5", "es2015", "esnext"]})
+    /// );
+    /// assert_eq!(jsonref.deref_known(".prettierrc").unwrap()["properties"]["semi"], json!({"type": "boolean"}));
+    /// ```
+    pub fn deref_known(&mut self, name: &str) -> Result<Value> {
+        let url = self.known_schema_url(name)?;
+        let mut value = match self.schema_cache.get(&url) {
+            Some(cached) => Value::clone(&cached),
+            None => {
+                let fetched = self.fetch(&Url::parse(&url).context(UrlParseError {url: url.clone()})?)?;
+                self.cache_fetched(url.clone(), fetched.clone());
+                fetched
+            }
+        };
+
+        self.run(&mut value, url)?;
+        Ok(value)
+    }
+
+    /// The url of the well-known schema `name`, found as for [`JsonRef::deref_known`], to
+    /// write refs into it.
+    pub fn known_schema_url(&mut self, name: &str) -> Result<String> {
+        let catalog_url = self.schema_catalog_url()?;
+        let catalog = match self.schema_cache.get(catalog_url.as_str()) {
+            Some(cached) => Value::clone(&cached),
+            None => {
+                let fetched = self.fetch(&catalog_url)?;
+                self.cache_fetched(catalog_url.to_string(), fetched.clone());
+                fetched
+            }
+        };
+        let schemas = catalog.get("schemas").and_then(Value::as_array).ok_or_else(|| Error::CatalogResponse {
+            url: catalog_url.to_string(),
+            reason: "no `schemas` array".to_owned(),
+        })?;
+        let wanted = catalog_name(name);
+        let entry_url = schemas
+            .iter()
+            .find(|entry| {
+                let url_name = entry.get("url").and_then(Value::as_str).and_then(|url| url.rsplit('/').next());
+                entry
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .into_iter()
+                    .chain(url_name)
+                    .chain(entry.get("fileMatch").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str))
+                    .any(|candidate| catalog_name(candidate) == wanted)
+            })
+            .and_then(|entry| entry.get("url").and_then(Value::as_str))
+            .ok_or_else(|| Error::UnknownSchema {
+                name: name.to_owned(),
+                catalog: catalog_url.to_string(),
+            })?;
+        let url = catalog_url.join(entry_url).context(UrlParseError {url: entry_url.to_owned()})?;
+        Ok(url.to_string())
+    }
+
+    fn schema_catalog_url(&self) -> Result<Url> {
+        if let Ok(url) = Url::parse(&self.schema_catalog) {
+            return Ok(url);
+        }
+        let path = env::current_dir().context(JSONRefError {})?.join(&self.schema_catalog);
+        Url::from_file_path(&path).map_err(|_| Error::UnsupportedUrl {
+            url: self.schema_catalog.clone(),
+            reason: "not a url or a path".to_owned(),
+        })
+    }
+
+    /// deref from a URL. A document already loaded or registered at `url`, such as one from
+    /// [`JsonRef::load_snapshot`], is used rather than fetched. Otherwise it is fetched as the
+    /// documents refs point into are, through the layers, URL rewriter and mirrors:
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// # let jsonref = JsonRef::new();
+    /// # use serde_json::Value;
+    /// # use std::fs;
+    /// let mut jsonref = JsonRef::new();
+    /// # jsonref.set_reference_key("__reference__");
+    /// let input_url = jsonref.deref_url("https://gist.githubusercontent.com/kindly/91e09f88ced65aaca1a15d85a56a28f9/raw/52f8477435cff0b73c54aacc70926c101ce6c685/base.json").unwrap();
+    /// # let file = fs::File::open("fixtures/nested_relative/expected.json").unwrap();
+    /// # let file_expected: Value = serde_json::from_reader(file).unwrap();
+    /// # assert_eq!(input_url, file_expected)
+    /// ```
+    pub fn deref_url(&mut self, url: &str) -> Result<Value> {
+        let mut value = match self.schema_cache.get(url) {
+            Some(cached) => Value::clone(&cached),
+            None => {
+                let mut document_url = Url::parse(url).context(UrlParseError {url})?;
+                document_url.set_fragment(None);
+                let document = self.load(&document_url)?;
+                Value::clone(&document)
+            }
+        };
+
+        self.run(&mut value, url.to_string())?;
+        Ok(value)
+    }
+
+    /// deref from a File:
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// # let jsonref = JsonRef::new();
+    /// # use serde_json::Value;
+    /// # use std::fs;
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// # jsonref.set_reference_key("__reference__");
+    /// let file_example = jsonref
+    ///     .deref_file("fixtures/nested_relative/base.json")
+    ///     .unwrap();
+    /// # let file = fs::File::open("fixtures/nested_relative/expected.json").unwrap();
+    /// # let file_expected: Value = serde_json::from_reader(file).unwrap();
+    /// # assert_eq!(file_example, file_expected)
+    /// ```
+    pub fn deref_file(&mut self, file_path: &str) -> Result<Value> {
+        let file = fs::File::open(file_path).context(SchemaFromFile {filename: file_path.to_owned()})?;
+        let mut value = parse(file, file_path)?;
+        let path = PathBuf::from(file_path);
+        let absolute_path = fs::canonicalize(path).context(JSONRefError {})?;
+        let url = format!("file://{}", absolute_path.to_string_lossy());
+
+        self.run(&mut value, url)?;
+        Ok(value)
+    }
+
+    /// deref only the subtree of a serde_json value found at the JSON pointer `pointer`. Refs in
+    /// the subtree are resolved against the whole value, using any `$id` of its parents and the
+    /// current working directory for relative refs.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"properties": {"prop1": {"title": "name"},
+    ///                     "prop2": {"$ref": "#/properties/prop1"},
+    ///                     "prop3": {"$ref": "#/properties/prop1"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.deref_at(&mut input, "/properties/prop2").unwrap();
+    ///
+    /// assert_eq!(input["properties"]["prop2"], json!({"title": "name"}));
+    /// assert_eq!(input["properties"]["prop3"], json!({"$ref": "#/properties/prop1"}));
+    /// ```
+    pub fn deref_at(&mut self, value: &mut Value, pointer: &str) -> Result<()> {
+        let anon_file_url = format!("file://{}/anon.json", env::current_dir().context(JSONRefError {})?.to_string_lossy());
+        self.run_at(value, anon_file_url, &[pointer.to_owned()])
+    }
+
+    /// deref only the parts of a serde_json value selected by a JSONPath expression, such as
+    /// `$.paths..requestBody`. Supports child names, array indexes, wildcards and recursive
+    /// descent.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let mut input = json!(
+    ///     {"components": {"requestBodies": {"pet": {"description": "a pet"}}},
+    ///      "paths": {"/pets": {"post": {"requestBody": {"$ref": "#/components/requestBodies/pet"}}}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    ///
+    /// jsonref.deref_path(&mut input, "$.paths..requestBody").unwrap();
+    ///
+    /// assert_eq!(input["paths"]["/pets"]["post"]["requestBody"], json!({"description": "a pet"}));
+    /// ```
+    pub fn deref_path(&mut self, value: &mut Value, path: &str) -> Result<()> {
+        let mut pointers = jsonpath::pointers(value, path)?;
+        pointers.sort();
+        let mut outermost: Vec<String> = vec![];
+        for pointer in pointers {
+            let nested = outermost.iter().any(|parent| pointer_within(&pointer, parent));
+            if !nested {
+                outermost.push(pointer);
+            }
+        }
+
+        let anon_file_url = format!("file://{}/anon.json", env::current_dir().context(JSONRefError {})?.to_string_lossy());
+        self.run_at(value, anon_file_url, &outermost)
+    }
+
+    /// Check that every `$ref` in `value`, whose URL is `url`, and in the documents they point to
+    /// can be resolved, without dereferencing. Returns every one that can not, ordered by document
+    /// and pointer, or an empty list if the schema is sound.
+    ///
+    /// ```
+    /// # use jsonref::JsonRef;
+    /// use serde_json::json;
+    ///
+    /// let schema = json!(
+    ///     {"$defs": {"name": {"type": "string"}},
+    ///      "properties": {"name": {"$ref": "#/$defs/name"},
+    ///                     "age": {"$ref": "#/$defs/age"}}}
+    /// );
+    ///
+    /// let mut jsonref = JsonRef::new();
+    /// let broken = jsonref.check_value(&schema, "file:///schema.json");
+    ///
+    /// assert_eq!(broken.len(), 1);
+    /// assert_eq!(broken[0].pointer, "/properties/age");
+    /// assert_eq!(broken[0].reference, "#/$defs/age");
+    /// ```
+    pub fn check_value(&mut self, value: &Value, url: &str) -> Vec<BrokenRef> {
+        self.expire_cache();
+        self.schema_cache.insert(url.to_owned(), value.clone());
+        let mut broken = vec![];
+        let mut pending = vec![url.to_owned()];
+        let mut checked = HashSet::new();
+        while let Some(document) = pending.pop() {
+            if !checked.insert(document.clone()) {
+                continue;
+            }
+            let mut sites = vec![];
+            if let Some(cached) = self.schema_cache.get(&document) {
+                ref_sites(&cached, document.clone(), String::new(), &mut sites);
+            }
+            for (pointer, reference, base) in sites {
+                match self.resolve_document(&base, &reference) {
+                    Ok(target) => pending.push(target),
+                    Err(error) => broken.push(BrokenRef {
+                        document: document.clone(),
+                        pointer,
+                        reference,
+                        error,
+                    }),
+                }
+            }
+        }
+        broken.sort_by(|a, b| (&a.document, &a.pointer).cmp(&(&b.document, &b.pointer)));
+        broken
+    }
+
+    /// Check that every `$ref` in a file, and in the documents they point to, can be resolved,
+    /// see [`JsonRef::check_value`]. Errors if the file itself can not be read.
+    pub fn check_file(&mut self, file_path: &str) -> Result<Vec<BrokenRef>> {
+        let file = fs::File::open(file_path).context(SchemaFromFile {filename: file_path.to_owned()})?;
+        let value = parse(file, file_path)?;
+        let absolute_path = fs::canonicalize(file_path).context(JSONRefError {})?;
+        let url = format!("file://{}", absolute_path.to_string_lossy());
+        Ok(self.check_value(&value, &url))
+    }
+
+    /// Resolve `reference` against `base` as `deref` would, returning the URL of the document it
+    /// points into.
+    fn resolve_document(&mut self, base: &str, reference: &str) -> Result<String> {
+        if self.strict_refs {
+            check_uri_reference(reference)?;
+        }
+        let base_url = Url::parse(base).context(UrlParseError {url: base})?;
+        let ref_url = base_url.join(reference).context(UrlParseError {url: reference})?;
+        let mut ref_url_no_fragment = ref_url.clone();
+        ref_url_no_fragment.set_fragment(None);
+        let ref_no_fragment = ref_url_no_fragment.to_string();
+
+        let document = self.load(&ref_url_no_fragment)?;
+        if let Some(ref_fragment) = ref_url.fragment() {
+            if document.pointer(ref_fragment).is_none() {
+                return Err(Error::JsonPointerNotFound {pointer: format!("ref `{}` can not be resolved as pointer `{}` can not be found in the schema", reference, ref_fragment)});
+            }
+        }
+        Ok(ref_no_fragment)
+    }
+
+    fn run(&mut self, value: &mut Value, url: String) -> Result<()> {
+        self.run_at(value, url, &[String::new()])
+    }
+
+    fn run_at(&mut self, value: &mut Value, url: String, pointers: &[String]) -> Result<()> {
+        let original = if self.record_patch { Some(value.clone()) } else { None };
+        self.expire_cache();
+        self.patch.clear();
+        self.replacements.clear();
+        self.warnings.clear();
+
+        let mut pointers = pointers.to_vec();
+        if self.migrate_drafts {
+            for pointer in pointers.iter_mut() {
+                *pointer = transform::migrate_pointer(pointer);
+            }
+        }
+        if let Some(keyword) = self.normalize_definitions {
+            for pointer in pointers.iter_mut() {
+                *pointer = schema::rename_in_pointer(pointer, keyword);
+            }
+        }
+        self.prepare(value);
+
+        if self.check_data_pointers {
+            check_data_pointers(value, String::new())?;
+        }
+
+        let mut ids = HashMap::new();
+        let mut duplicates = vec![];
+        if !self.json_reference {
+            duplicate_ids(value, url.clone(), String::new(), &mut ids, &mut duplicates);
+        }
+        for warning in duplicates {
+            self.warn(warning);
+        }
+        if self.vocabulary_policy != VocabularyPolicy::Ignore && !self.json_reference {
+            self.check_vocabularies(value, &url)?;
+        }
+
+        self.schema_cache.insert(url.clone(), value.clone());
+        self.root_url = url.clone();
+        self.bundled.clear();
+        self.bundle_names.clear();
+
+        for pointer in &pointers {
+            let base = if self.json_reference { url.clone() } else { base_url_at(value, url.clone(), pointer) };
+            let subtree = value.pointer_mut(pointer).ok_or(Error::JsonPointerNotFound {
+                pointer: format!("pointer `{}` can not be found in the schema", pointer),
+            })?;
+            // Many inputs are already flat, and a quick scan saves walking them. OpenAPI documents
+            // are always walked, as their `operationRef`s are rewritten too.
+            if self.openapi || has_ref(subtree) {
+                self.deref(subtree, base, &vec![], pointer)?;
+            }
+        }
+
+        if self.bundle_meta_schema && !self.json_reference {
+            if let Some(reference) = value.get("$schema").and_then(|meta_schema| meta_schema.as_str()).map(str::to_owned) {
+                let mut meta_schema_url = Url::parse(&url)
+                    .and_then(|url| url.join(&reference))
+                    .context(UrlParseError {url: reference.clone()})?;
+                meta_schema_url.set_fragment(None);
+                let document = meta_schema_url.to_string();
+                let meta_schema = match self.schema_cache.get(&document) {
+                    Some(cached) => Value::clone(&cached),
+                    None => {
+                        let fetched = self.fetch(&meta_schema_url)?;
+                        self.cache_fetched(document.clone(), fetched.clone());
+                        fetched
+                    }
+                };
+                self.bundle(meta_schema, &reference, &meta_schema_url, document, &[])?;
+            }
+        }
+
+        if let Some(threshold) = self.dedupe_threshold {
+            self.dedupe(value, threshold)?;
+        }
+
+        if !self.bundled.is_empty() {
+            if let Some(obj) = value.as_object_mut() {
+                let defs = obj
+                    .entry(self.defs_keyword())
+                    .or_insert_with(|| Value::Object(Map::new()));
+                if let Some(defs) = defs.as_object_mut() {
+                    defs.extend(mem::take(&mut self.bundled));
+                }
+            }
+        }
+
+        if !self.strip_keywords.is_empty() {
+            transform::strip_keywords(value, &self.strip_keywords);
+        }
+        if self.flatten_all_of {
+            transform::flatten_all_of(value);
+        }
+        for pass in &self.passes {
+            pass.0.run(value);
+        }
+        if self.crd_structural {
+            for warning in crd::structural(value) {
+                self.warn(warning);
+            }
+        }
+        if self.deterministic {
+            transform::sort_keys(value);
+        }
+
+        if let Some(original) = original {
+            self.patch = patch::diff(&original, value);
+        }
+
+        #[cfg(feature = "jsonschema")]
+        if self.validate_meta_schema {
+            let violations = validate::meta_schema_violations(value)?;
+            if !violations.is_empty() {
+                return Err(Error::MetaSchemaViolations { violations });
+            }
+        }
+        Ok(())
+    }
+
+    /// The `$ref` to leave in place of a ref that is not inlined. Refs into the root document are
+    /// made local so they stay valid wherever they end up in the output.
+    fn kept_ref(&self, ref_value: &Value, ref_url: &Url, ref_no_fragment: &str) -> Value {
+        if self.absolute_refs {
+            Value::String(ref_url.to_string())
+        } else if ref_no_fragment == self.root_url {
+            Value::String(format!("#{}", ref_url.fragment().unwrap_or("")))
+        } else {
+            ref_value.clone()
+        }
+    }
+
+    fn apply_id_policy(&self, schema: &mut Value) {
+        if self.id_policy == IdPolicy::Keep {
+            return;
+        }
+        schema::walk_schemas_mut(schema, "", &mut |_, subschema| {
+            if let Some(obj) = subschema.as_object_mut() {
+                obj.remove("$schema");
+                if self.id_policy == IdPolicy::Strip {
+                    obj.remove("$id");
+                } else if let Some(Value::String(id)) = obj.get_mut("$id") {
+                    if !id.starts_with('#') {
+                        *id = format!("#{}", fragment_id(id));
+                    }
+                }
+            }
+        });
+    }
+
+    fn add_source(&self, schema: &mut Value, ref_url: &Url) {
+        if let Some(source_key) = &self.source_key {
+            if let Some(obj) = annotatable(schema) {
+                obj.insert(source_key.clone(), Value::String(ref_url.to_string()));
+            }
+        }
+    }
+
+    /// The keyword bundled targets are put under.
+    fn defs_keyword(&self) -> &'static str {
+        self.normalize_definitions.unwrap_or(DefinitionsKeyword::Defs).as_str()
+    }
+
+    /// Move `schema` into the bundled `$defs` (once per target) and return the local pointer to it.
+    fn bundle(
+        &mut self,
+        mut schema: Value,
+        reference: &str,
+        ref_url: &Url,
+        ref_no_fragment: String,
+        used_refs: &[String],
+    ) -> Result<String> {
+        let ref_url_string = ref_url.to_string();
+
+        let defs_keyword = self.defs_keyword();
+        let defs_prefix = format!("/{}/", defs_keyword);
+
+        if ref_no_fragment == self.root_url {
+            if let Some(name) = ref_url.fragment().and_then(|fragment| fragment.strip_prefix(&defs_prefix)) {
+                if !name.contains('/') {
+                    return Ok(format!("#/{}/{}", defs_keyword, name));
+                }
+            }
+        }
+
+        if let Some(name) = self.bundle_names.get(&ref_url_string) {
+            return Ok(format!("#/{}/{}", defs_keyword, name));
+        }
+
+        // A target that is only a ref to a definition is an alias, so bundle the definition
+        // instead, keeping its name.
+        if let Some(alias) = schema.as_object().filter(|obj| obj.len() == 1).and_then(|obj| obj.get("$ref")).and_then(Value::as_str) {
+            let alias_url = Url::parse(&ref_no_fragment)
+                .and_then(|url| url.join(alias))
+                .ok()
+                .filter(names_definition);
+            if let Some(alias_url) = alias_url {
+                let mut alias_document = alias_url.clone();
+                alias_document.set_fragment(None);
+                let alias_document = alias_document.to_string();
+                let target = self
+                    .schema_cache
+                    .get(&alias_document)
+                    .and_then(|document| document.pointer(alias_url.fragment().unwrap_or_default()).cloned());
+                let circular = alias_url == *ref_url || used_refs.contains(&alias_url.to_string());
+                if let (Some(target), false) = (target, circular) {
+                    let mut new_used_refs = used_refs.to_vec();
+                    new_used_refs.push(ref_url_string.clone());
+                    let local_pointer = self.bundle(target, alias, &alias_url, alias_document, &new_used_refs)?;
+                    if let Some(name) = local_pointer.rsplit('/').next() {
+                        self.bundle_names.insert(ref_url_string, name.to_owned());
+                    }
+                    return Ok(local_pointer);
+                }
+            }
+        }
+
+        let base_name = self.bundle_base_name(&schema, ref_url);
+        let name = self.unique_bundle_name(&base_name, ref_url)?;
+
+        self.bundle_names.insert(ref_url_string.clone(), name.clone());
+        self.bundled.insert(name.clone(), Value::Null);
+
+        let mut new_used_refs = used_refs.to_vec();
+        new_used_refs.push(ref_url_string);
+        let bundled_pointer = format!("/{}/{}", defs_keyword, escape_pointer(&name));
+        self.deref(&mut schema, ref_no_fragment, &new_used_refs, &bundled_pointer)?;
+        self.apply_id_policy(&mut schema);
+        self.add_source(&mut schema, ref_url);
+        self.run_inline_hook(&mut schema, reference, ref_url, &bundled_pointer);
+
+        if self.name_collision == NameCollision::MergeIdentical && name != base_name {
+            self.bundled.remove(&name);
+            if let Some(identical) = self.identical_bundle_name(&base_name, &schema) {
+                self.replacements
+                    .retain(|replacement| !pointer_within(&replacement.pointer, &bundled_pointer) && replacement.pointer != bundled_pointer);
+                self.bundle_names.insert(ref_url.to_string(), identical.clone());
+                return Ok(format!("#/{}/{}", defs_keyword, identical));
+            }
+        }
+        self.bundled.insert(name.clone(), schema);
+        Ok(format!("#/{}/{}", defs_keyword, name))
+    }
+
+    fn run_inline_hook(&self, schema: &mut Value, reference: &str, ref_url: &Url, pointer: &str) {
+        if let Some(hook) = &self.inline_hook {
+            let site = RefSite {
+                reference,
+                url: ref_url.as_str(),
+                pointer,
+            };
+            (hook.0)(schema, &site);
+        }
+    }
+
+    /// The name to bundle `schema`, the target of `ref_url`, under before making it unique.
+    fn bundle_base_name(&self, schema: &Value, ref_url: &Url) -> String {
+        let named = match self.bundle_naming {
+            BundleNaming::Ref => self.definition_name(schema, ref_url),
+            BundleNaming::FileName => {
+                let mut document_url = ref_url.clone();
+                document_url.set_fragment(None);
+                Some(bundle_name(&document_url))
+            }
+            BundleNaming::Id => schema
+                .get("$id")
+                .and_then(Value::as_str)
+                .and_then(|id| ref_url.join(id).ok())
+                .map(|mut id_url| {
+                    id_url.set_fragment(None);
+                    bundle_name(&id_url)
+                }),
+            BundleNaming::Title => schema.get("title").and_then(Value::as_str).map(sanitize_name),
+        };
+        named.unwrap_or_else(|| bundle_name(ref_url))
+    }
+
+    /// The name of the entry in the `$defs` or `definitions` of the document of `ref_url` that
+    /// `schema` is identical to, when `ref_url` does not point at such an entry itself.
+    fn definition_name(&self, schema: &Value, ref_url: &Url) -> Option<String> {
+        if names_definition(ref_url) {
+            return None;
+        }
+        let mut document_url = ref_url.clone();
+        document_url.set_fragment(None);
+        let document = self.schema_cache.get(document_url.as_str())?;
+        ["$defs", "definitions"]
+            .iter()
+            .filter_map(|keyword| document.get(keyword).and_then(Value::as_object))
+            .flatten()
+            .find(|(_, definition)| *definition == schema)
+            .map(|(name, _)| sanitize_name(name))
+    }
+
+    /// Whether `name` is used in the bundle or the root document's own definitions.
+    fn bundle_name_taken(&self, name: &str) -> bool {
+        self.bundled.contains_key(name) || self.root_definition(name).is_some()
+    }
+
+    fn root_definition(&self, name: &str) -> Option<Value> {
+        self.schema_cache
+            .get(&self.root_url)
+            .and_then(|root| root.get(self.defs_keyword())?.get(name).cloned())
+    }
+
+    /// `base_name`, or if it is taken and the collision policy allows it, the first free name
+    /// with a suffix.
+    fn unique_bundle_name(&self, base_name: &str, ref_url: &Url) -> Result<String> {
+        if self.name_collision == NameCollision::Error && self.bundle_name_taken(base_name) {
+            return Err(Error::BundleNameCollision {
+                name: base_name.to_owned(),
+                url: ref_url.to_string(),
+            });
+        }
+        let mut name = base_name.to_owned();
+        let mut suffix = 2;
+        while self.bundle_name_taken(&name) {
+            name = format!("{}_{}", base_name, suffix);
+            suffix += 1;
+        }
+        Ok(name)
+    }
+
+    /// The name, `base_name` or one with a suffix, of a definition already identical to
+    /// `definition`.
+    fn identical_bundle_name(&self, base_name: &str, definition: &Value) -> Option<String> {
+        let mut name = base_name.to_owned();
+        let mut suffix = 2;
+        while self.bundle_name_taken(&name) {
+            if self.bundled.get(&name).cloned().or_else(|| self.root_definition(&name)).as_ref() == Some(definition) {
+                return Some(name);
+            }
+            name = format!("{}_{}", base_name, suffix);
+            suffix += 1;
+        }
+        None
+    }
+
+    /// Move targets inlined identically at `threshold` or more places in `value` into the bundle,
+    /// pointing each place at it instead.
+    fn dedupe(&mut self, value: &mut Value, threshold: usize) -> Result<()> {
+        let defs_keyword = self.defs_keyword();
+
+        // The pointers each url was inlined at, in the order the urls were first inlined. Only
+        // the first replacement at a pointer counts, as the others are refs to refs.
+        let mut sites: Vec<(String, Vec<String>)> = vec![];
+        let mut seen = HashSet::new();
+        for replacement in &self.replacements {
+            if replacement.bundled || !seen.insert(replacement.pointer.as_str()) {
+                continue;
+            }
+            match sites.iter_mut().find(|(url, _)| *url == replacement.url) {
+                Some((_, pointers)) => pointers.push(replacement.pointer.clone()),
+                None => sites.push((replacement.url.clone(), vec![replacement.pointer.clone()])),
+            }
+        }
+
+        let mut moved: Vec<(String, String)> = vec![];
+        for (url, pointers) in sites {
+            let pointers: Vec<String> = pointers
+                .into_iter()
+                .filter(|pointer| !moved.iter().any(|(site, _)| pointer_within(pointer, site)))
+                .collect();
+            if pointers.is_empty() || pointers.len() < threshold {
+                continue;
+            }
+            let definition = match value.pointer(&pointers[0]) {
+                Some(definition) => definition.clone(),
+                None => continue,
+            };
+            let matching: Vec<String> = pointers
+                .into_iter()
+                .filter(|pointer| value.pointer(pointer) == Some(&definition))
+                .collect();
+            if matching.len() < threshold {
+                continue;
+            }
+
+            let ref_url = Url::parse(&url).context(UrlParseError {url: url.clone()})?;
+            let mut ref_no_fragment = ref_url.clone();
+            ref_no_fragment.set_fragment(None);
+
+            // A definition of the root schema is already there to point at, as when bundling.
+            let defs_prefix = format!("/{}/", defs_keyword);
+            let root_name = ref_url
+                .fragment()
+                .filter(|_| ref_no_fragment.as_str() == self.root_url)
+                .and_then(|fragment| fragment.strip_prefix(&defs_prefix))
+                .filter(|name| !name.contains('/'));
+            // The location pointed at, and the name to bundle the definition under if it is not
+            // there already.
+            let (location, new_name) = match root_name {
+                Some(name) => (format!("/{}/{}", defs_keyword, name), None),
+                None => {
+                    let base_name = self.bundle_base_name(&definition, &ref_url);
+                    let identical = match self.name_collision {
+                        NameCollision::MergeIdentical => self.identical_bundle_name(&base_name, &definition),
+                        _ => None,
+                    };
+                    let name = match &identical {
+                        Some(name) => name.clone(),
+                        None => self.unique_bundle_name(&base_name, &ref_url)?,
+                    };
+                    let location = format!("/{}/{}", defs_keyword, escape_pointer(&name));
+                    (location, identical.is_none().then_some(name))
+                }
+            };
+            for pointer in matching {
+                if let Some(site) = value.pointer_mut(&pointer) {
+                    *site = serde_json::json!({"$ref": format!("#{}", location)});
+                }
+                moved.push((pointer, location.clone()));
+            }
+            if let Some(name) = new_name {
+                self.bundled.insert(name, definition);
+            }
+        }
+
+        for replacement in self.replacements.iter_mut() {
+            for (site, location) in &moved {
+                if replacement.pointer == *site {
+                    replacement.bundled = true;
+                } else if pointer_within(&replacement.pointer, site) {
+                    replacement.pointer = format!("{}{}", location, &replacement.pointer[site.len()..]);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn deref(
+        &mut self,
+        value: &mut Value,
+        id: String,
+        used_refs: &Vec<String>,
+        pointer: &str,
+    ) -> Result<()> {
+        let new_id = self.join_id(value, id);
+
+        if let Some(obj) = value.as_object_mut() {
+            if let Some(ref_value) = obj.remove("$ref") {
+                if let Some(ref_string) = ref_value.as_str() {
+                    if self.strict_refs {
+                        check_uri_reference(ref_string)?;
+                    }
+                    let id_url = Url::parse(&new_id).context(UrlParseError {url: new_id.clone()})?;
+                    let ref_url = id_url.join(ref_string).context(UrlParseError {url: ref_string.to_owned()})?;
+
+                    let too_deep = self.max_depth.is_some_and(|max_depth| used_refs.len() >= max_depth);
+                    let filtered_out = self.ref_filter.as_ref().is_some_and(|filter| {
+                        let site = RefSite {
+                            reference: ref_string,
+                            url: ref_url.as_str(),
+                            pointer,
+                        };
+                        !(filter.0)(&site)
+                    });
+                    let mut ref_url_no_fragment = ref_url.clone();
+                    ref_url_no_fragment.set_fragment(None);
+                    let ref_no_fragment = ref_url_no_fragment.to_string();
+
+                    let out_of_scope = match self.ref_scope {
+                        RefScope::All => false,
+                        RefScope::Internal => ref_no_fragment != self.root_url,
+                        RefScope::External => ref_no_fragment == self.root_url,
+                    };
+                    let kept_by_directive = obj.get(&self.directive_key).and_then(Value::as_str) == Some("jsonref:keep");
+                    if too_deep || filtered_out || out_of_scope || kept_by_directive {
+                        let kept_ref = self.kept_ref(&ref_value, &ref_url, &ref_no_fragment);
+                        obj.insert("$ref".to_string(), kept_ref);
+                        return Ok(());
+                    }
+
+                    let overridden = self.ref_resolver.as_ref().and_then(|resolver| {
+                        let site = RefSite {
+                            reference: ref_string,
+                            url: ref_url.as_str(),
+                            pointer,
+                        };
+                        (resolver.0)(&site)
+                    });
+                    let ref_url_string = ref_url.to_string();
+                    let mut schema = match overridden {
+                        Some(schema) => schema,
+                        None => {
+                            // Only the target is cloned out of the cached document.
+                            let document = self.load(&ref_url_no_fragment)?;
+                            match ref_url.fragment() {
+                                Some(ref_fragment) => document.pointer(ref_fragment).ok_or(
+                                    Error::JsonPointerNotFound {pointer: format!("ref `{}` can not be resolved as pointer `{}` can not be found in the schema", ref_string, ref_fragment)}
+                                    )?.clone(),
+                                None => Value::clone(&document),
+                            }
+                        }
+                    };
+
+                    if let Some(bundle_threshold) = self.bundle_threshold {
+                        let size = serde_json::to_vec(&schema).map_or(0, |bytes| bytes.len());
+                        if size > bundle_threshold {
+                            let local_pointer = self.bundle(schema, ref_string, &ref_url, ref_no_fragment, used_refs)?;
+                            let mut original = obj.clone();
+                            original.insert("$ref".to_string(), ref_value.clone());
+                            self.replace(Replacement {
+                                pointer: pointer.to_owned(),
+                                reference: ref_string.to_owned(),
+                                url: ref_url.to_string(),
+                                original: Value::Object(original),
+                                bundled: true,
+                            });
+                            obj.insert("$ref".to_string(), Value::String(local_pointer));
+                            return Ok(());
+                        }
+                    }
+
+                    if let Some(start) = used_refs.iter().position(|used| *used == ref_url_string) {
+                        if self.recursion_policy == RecursionPolicy::Error {
+                            let mut cycle = used_refs[start..].to_vec();
+                            cycle.push(ref_url_string);
+                            return Err(Error::RefCycle { cycle });
+                        }
+                        self.warn(Warning::RecursionTruncated {
+                            pointer: pointer.to_owned(),
+                            url: ref_url_string,
+                        });
+                        return Ok(());
+                    }
+
+                    let mut new_used_refs = used_refs.clone();
+                    new_used_refs.push(ref_url_string);
+
+                    let mut original = obj.clone();
+                    original.insert("$ref".to_string(), ref_value.clone());
+                    self.replace(Replacement {
+                        pointer: pointer.to_owned(),
+                        reference: ref_string.to_owned(),
+                        url: ref_url.to_string(),
+                        original: Value::Object(original),
+                        bundled: false,
+                    });
+
+                    self.deref(&mut schema, ref_no_fragment, &new_used_refs, pointer)?;
+                    self.apply_id_policy(&mut schema);
+                    self.add_source(&mut schema, &ref_url);
+                    self.run_inline_hook(&mut schema, ref_string, &ref_url, pointer);
+                    let mut old_value = mem::replace(value, schema);
+
+                    if self.merge_extensions {
+                        if let Some(site) = old_value.as_object_mut() {
+                            let extensions: Vec<String> =
+                                site.keys().filter(|key| key.starts_with("x-")).cloned().collect();
+                            if let Some(target) = annotatable(value).filter(|_| !extensions.is_empty()) {
+                                for key in extensions {
+                                    if let Some(extension) = site.remove(&key) {
+                                        target.insert(key, extension);
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    // OpenAPI 3.1 lets a Reference Object override the summary and description
+                    // of what it refers to.
+                    if self.openapi && self.openapi_overrides() {
+                        if let Some(site) = old_value.as_object_mut() {
+                            for key in ["summary", "description"] {
+                                if let (Some(text), Some(target)) = (site.remove(key), annotatable(value)) {
+                                    target.insert(key.to_string(), text);
+                                }
+                            }
+                        }
+                    }
+
+                    if let Some(reference_key) = self.reference_key.clone() {
+                        let reference_pointer = format!("{}/{}", pointer, escape_pointer(&reference_key));
+                        self.deref(&mut old_value, new_id, used_refs, &reference_pointer)?;
+                        if let Some(new_obj) = annotatable(value) {
+                            new_obj.insert(reference_key, old_value);
+                        } else if let Some(site) = old_value.as_object().filter(|site| !site.is_empty()) {
+                            self.warn(Warning::SiblingKeysDiscarded {
+                                pointer: pointer.to_owned(),
+                                keys: site.keys().cloned().collect(),
+                            });
+                        }
+                    } else if let Some(site) = old_value.as_object().filter(|site| !site.is_empty() && !self.json_reference) {
+                        self.warn(Warning::SiblingKeysDiscarded {
+                            pointer: pointer.to_owned(),
+                            keys: site.keys().cloned().collect(),
+                        });
+                    }
+                    // The inlined schema has already been dereferenced.
+                    return Ok(());
+                }
+                self.warn(Warning::RefNotString { pointer: pointer.to_owned() });
+                obj.insert("$ref".to_string(), ref_value);
+            }
+        }
+
+        if self.openapi || self.openrpc {
+            if let Some(obj) = value.as_object_mut() {
+                if self.openapi && openapi::is_link(pointer) {
+                    if let Some(Value::String(operation_ref)) = obj.get("operationRef") {
+                        let operation_url = Url::parse(&new_id)
+                            .and_then(|id_url| id_url.join(operation_ref))
+                            .context(UrlParseError {url: operation_ref.clone()})?;
+                        let mut document = operation_url.clone();
+                        document.set_fragment(None);
+                        let rewritten = if document.as_str() == self.root_url && !self.absolute_refs {
+                            format!("#{}", operation_url.fragment().unwrap_or_default())
+                        } else {
+                            operation_url.to_string()
+                        };
+                        obj.insert("operationRef".to_string(), Value::String(rewritten));
+                    }
+                }
+                for (key, obj_value) in obj.iter_mut() {
+                    let kept_component = self.keep_components && pointer.is_empty() && key == "components";
+                    let example = if self.openrpc {
+                        openrpc::is_example(pointer, key, obj_value)
+                    } else {
+                        openapi::is_example(pointer, key, obj_value)
+                    };
+                    if kept_component || example {
+                        continue;
+                    }
+                    let child_pointer = format!("{}/{}", pointer, escape_pointer(key));
+                    self.deref(obj_value, new_id.clone(), used_refs, &child_pointer)?
+                }
+                return Ok(());
+            }
+        } else if self.traversal_policy != TraversalPolicy::All {
+            return self.deref_subschemas(value, new_id, used_refs, pointer);
+        }
+
+        if let Some(obj) = value.as_object_mut() {
+            for (key, obj_value) in obj.iter_mut() {
+                let child_pointer = format!("{}/{}", pointer, escape_pointer(key));
+                self.deref(obj_value, new_id.clone(), used_refs, &child_pointer)?
+            }
+        } else if let Some(array) = value.as_array_mut() {
+            for (index, array_value) in array.iter_mut().enumerate() {
+                let child_pointer = format!("{}/{}", pointer, index);
+                self.deref(array_value, new_id.clone(), used_refs, &child_pointer)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the root document is OpenAPI 3.1 or later, where `$ref` siblings `summary` and
+    /// `description` override those of the target.
+    fn openapi_overrides(&self) -> bool {
+        let version = self
+            .schema_cache
+            .get(&self.root_url)
+            .and_then(|root| root.get("openapi")?.as_str().map(str::to_owned))
+            .unwrap_or_default();
+        let mut parts = version.split('.').map(|part| part.parse::<u32>().unwrap_or(0));
+        matches!((parts.next(), parts.next()), (Some(major), Some(minor)) if (major, minor) >= (3, 1))
+    }
+
+    /// Rewrite a document as it is read, as asked for by the options.
+    fn prepare(&self, schema: &mut Value) {
+        if self.migrate_drafts {
+            transform::migrate_to_2020_12(schema);
+        }
+        if let Some(keyword) = self.normalize_definitions {
+            schema::normalize_definitions(schema, keyword);
+        }
+    }
+
+    /// Apply the vocabulary policy to the vocabularies required by the meta-schema of `schema`.
+    fn check_vocabularies(&mut self, schema: &Value, url: &str) -> Result<()> {
+        let meta_schema = match schema.get("$schema").and_then(|meta_schema| meta_schema.as_str()) {
+            Some(meta_schema) => meta_schema,
+            None => return Ok(()),
+        };
+        let meta_schema_url = Url::parse(url)
+            .and_then(|url| url.join(meta_schema))
+            .context(UrlParseError {url: meta_schema})?;
+        let mut document_url = meta_schema_url.clone();
+        document_url.set_fragment(None);
+        let document = document_url.to_string();
+        if schema::STANDARD_DIALECTS.contains(&document.as_str()) {
+            return Ok(());
+        }
+
+        if !self.schema_cache.contains(&document) {
+            let fetched = self.fetch(&document_url)?;
+            self.cache_fetched(document.clone(), fetched);
+        }
+        let meta_schema = self.schema_cache.get(&document).unwrap_or_default();
+        let vocabularies = match meta_schema.get("$vocabulary").and_then(|vocabularies| vocabularies.as_object()) {
+            Some(vocabularies) => vocabularies.clone(),
+            None => return Ok(()),
+        };
+        // Vocabularies marked `false` are optional and may be ignored.
+        for (vocabulary, required) in vocabularies {
+            if required != Value::Bool(true) || schema::STANDARD_VOCABULARIES.contains(&vocabulary.as_str()) {
+                continue;
+            }
+            if self.vocabulary_policy == VocabularyPolicy::Error {
+                return Err(Error::UnsupportedVocabulary { meta_schema: document, vocabulary });
+            }
+            self.warn(Warning::UnsupportedVocabulary { meta_schema: document.clone(), vocabulary });
+        }
+        Ok(())
+    }
+
+    /// The base url for the contents of `value`, using its `$id` unless `$id` has no meaning.
+    fn join_id(&self, value: &Value, base: String) -> String {
+        if self.json_reference {
+            base
+        } else {
+            join_id(value, base)
+        }
+    }
+
+    fn cache_fetched(&self, url: String, document: Value) -> Arc<Value> {
+        self.schema_cache.insert_fetched(url, document)
+    }
+
+    /// The document at `url`, which has no fragment, from the cache, or fetched, prepared and
+    /// cached.
+    pub(crate) fn load(&self, url: &Url) -> Result<Arc<Value>> {
+        if let Some(document) = self.schema_cache.get(url.as_str()) {
+            return Ok(document);
+        }
+        let mut fetched = self.fetch(url)?;
+        self.prepare(&mut fetched);
+        Ok(self.cache_fetched(url.to_string(), fetched))
+    }
+
+    /// Drop the fetched documents older than the cache TTL.
+    fn expire_cache(&mut self) {
+        if let Some(ttl) = self.cache_ttl {
+            self.schema_cache.expire(ttl);
+        }
+    }
+
+    fn emit(&self, event: Event) {
+        if let Some(handler) = &self.event_handler {
+            (handler.0)(&event);
+        }
+    }
+
+    fn warn(&mut self, warning: Warning) {
+        self.emit(Event::Warning(&warning));
+        self.warnings.push(warning);
+    }
+
+    fn replace(&mut self, replacement: Replacement) {
+        self.emit(Event::Replacing(&replacement));
+        self.replacements.push(replacement);
+    }
+
+    /// Retrieve the document at `url` through the layers.
+    fn fetch(&self, url: &Url) -> Result<Value> {
+        self.emit(Event::Fetching { url: url.as_str() });
+        resolve::Next::new(&self.layers, &self.http, &|url| self.fetch_rewritten(url)).run(url)
+    }
+
+    /// Retrieve the document at `url`, or where the URL rewriter or a mirror sends it.
+    fn fetch_rewritten(&self, url: &Url) -> Result<Value> {
+        let mirrored = || {
+            let (prefix, base) = self.mirrors.iter().rev().find(|(prefix, _)| url.as_str().starts_with(prefix.as_str()))?;
+            Some(format!("{}{}", base, &url.as_str()[prefix.len()..]))
+        };
+        let rewritten = self.url_rewriter.as_ref().and_then(|rewriter| (rewriter.0)(url.as_str())).or_else(mirrored);
+        if let Some(rewritten) = rewritten {
+            let rewritten = Url::parse(&rewritten).context(UrlParseError {url: rewritten.clone()})?;
+            return self.fetch_from(&rewritten);
+        }
+        self.fetch_from(url)
+    }
+
+    /// Retrieve the document at `url`, from the embedded meta-schemas for `json-schema.org` urls
+    /// and the schema registry for `registry:` urls.
+    fn fetch_from(&self, url: &Url) -> Result<Value> {
+        if let Some(meta_schema) = self.offline_meta_schemas.then(|| metaschema::get(url)).flatten() {
+            return Ok(meta_schema);
+        }
+        if self.embed_non_json && matches!(url.scheme(), "file" | "http" | "https" | "http+unix") {
+            let (text, content_type) = fetch_text(&self.http, url)?;
+            if is_non_json(url, content_type.as_deref()) {
+                return Ok(Value::String(text));
+            }
+            return parse(text.as_bytes(), url.as_str());
+        }
+        if url.scheme() != "registry" {
+            return fetch(&self.http, url);
+        }
+        let registry = self.schema_registry.as_deref().ok_or_else(|| Error::UnsupportedUrl {
+            url: url.to_string(),
+            reason: "no schema registry is set".to_owned(),
+        })?;
+        let api_url = registry_url(registry, url)?;
+        let response = fetch_http(&self.http, &api_url)?;
+        let schema = response.get("schema").and_then(|schema| schema.as_str()).ok_or_else(|| Error::RegistryResponse {
+            url: api_url.clone(),
+            reason: "no `schema` string".to_owned(),
+        })?;
+        serde_json::from_str(schema).map_err(|error| Error::RegistryResponse {
+            url: api_url,
+            reason: format!("`schema` is not JSON: {}", error),
+        })
+    }
+
+    /// Dereference the subschemas of `schema`, following only keywords known to hold schemas, for
+    /// the traversal policies other than [`TraversalPolicy::All`].
+    fn deref_subschemas(&mut self, schema: &mut Value, id: String, used_refs: &Vec<String>, pointer: &str) -> Result<()> {
+        for (child_pointer, subschema) in schema::children_mut(schema) {
+            self.deref(subschema, id.clone(), used_refs, &format!("{}{}", pointer, child_pointer))?;
+        }
+        if self.traversal_policy == TraversalPolicy::KeepUnknown {
+            for (key, child) in schema.as_object_mut().into_iter().flatten() {
+                if !schema::holds_subschemas(key, child) {
+                    self.keep_refs(child, id.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrite every `$ref` in `value` as a ref that is not followed, without resolving any.
+    fn keep_refs(&self, value: &mut Value, id: String) {
+        let new_id = self.join_id(value, id);
+        if let Some(obj) = value.as_object_mut() {
+            if let Some(Value::String(reference)) = obj.get("$ref") {
+                let ref_url = Url::parse(&new_id).and_then(|id_url| id_url.join(reference));
+                if let Ok(ref_url) = ref_url {
+                    let mut ref_url_no_fragment = ref_url.clone();
+                    ref_url_no_fragment.set_fragment(None);
+                    let kept_ref = self.kept_ref(&obj["$ref"], &ref_url, ref_url_no_fragment.as_str());
+                    obj.insert("$ref".to_string(), kept_ref);
+                }
+            }
+            for child in obj.values_mut() {
+                self.keep_refs(child, new_id.clone());
+            }
+        } else if let Some(array) = value.as_array_mut() {
+            for child in array {
+                self.keep_refs(child, new_id.clone());
+            }
+        }
+    }
+}
+
+/// The base url for the contents of `value`, using its `$id` if it has one.
+fn join_id(value: &Value, base: String) -> String {
+    match value.get("$id").and_then(|id| id.as_str()) {
+        Some(id_string) => match Url::parse(&base).and_then(|base_url| base_url.join(id_string)) {
+            Ok(id_url) => id_url.to_string(),
+            Err(_) => id_string.to_string(),
+        },
+        None => base,
+    }
+}
+
+/// Fail with [`Error::InvalidDataPointer`] for the first `$data` in `value`, found at `pointer`,
+/// that is not a JSON pointer or relative JSON pointer.
+fn check_data_pointers(value: &Value, pointer: String) -> Result<()> {
+    match value {
+        Value::Object(obj) => {
+            if let Some(data) = obj.get("$data") {
+                let reason = match data.as_str() {
+                    Some(data) => data_pointer_error(data),
+                    None => Some("not a string"),
+                };
+                if let Some(reason) = reason {
+                    return Err(Error::InvalidDataPointer {
+                        pointer,
+                        value: data.as_str().map_or_else(|| data.to_string(), str::to_owned),
+                        reason: reason.to_owned(),
+                    });
+                }
+            }
+            for (key, child) in obj {
+                check_data_pointers(child, format!("{}/{}", pointer, escape_pointer(key)))?;
+            }
+        }
+        Value::Array(array) => {
+            for (index, child) in array.iter().enumerate() {
+                check_data_pointers(child, format!("{}/{}", pointer, index))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Why `data` is neither a JSON pointer (RFC 6901) nor a relative JSON pointer, a number of
+/// levels up followed by `#` or a JSON pointer.
+fn data_pointer_error(data: &str) -> Option<&'static str> {
+    let digits = data.len() - data.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let (levels, pointer) = data.split_at(digits);
+    if levels.len() > 1 && levels.starts_with('0') {
+        return Some("the number of levels up has a leading zero");
+    }
+    if !levels.is_empty() && pointer == "#" {
+        return None;
+    }
+    if !pointer.is_empty() && !pointer.starts_with('/') {
+        return Some("not a JSON pointer or relative JSON pointer");
+    }
+    let mut escapes = pointer.split('~').skip(1);
+    if escapes.any(|escaped| !escaped.starts_with('0') && !escaped.starts_with('1')) {
+        return Some("`~` must be followed by `0` or `1`");
+    }
+    None
+}
+
+/// Fail with [`Error::InvalidRef`] if `reference` is not a URI-reference as RFC 3986 defines it.
+fn check_uri_reference(reference: &str) -> Result<()> {
+    let invalid = |reason: String| {
+        Err(Error::InvalidRef {
+            reference: reference.to_owned(),
+            reason,
+        })
+    };
+    let mut chars = reference.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' => return invalid("contains a space".to_owned()),
+            '\\' => return invalid("contains a backslash, use `/` in paths".to_owned()),
+            '%' => {
+                let escape: String = chars.clone().take(2).collect();
+                if escape.len() != 2 || !escape.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return invalid(format!("has a bad escape `%{}`, `%` must be followed by two hex digits", escape));
+                }
+            }
+            c if c.is_ascii_alphanumeric() || "-._~:/?#[]@!$&'()*+,;=".contains(c) => {}
+            c => return invalid(format!("contains `{}`, which must be percent-encoded", c.escape_default())),
+        }
+    }
+    if reference.matches('#').count() > 1 {
+        return invalid("has more than one `#`".to_owned());
+    }
+    // A colon before any `/`, `?` or `#` ends a scheme, which must start with a letter.
+    let first_part = reference.split(['/', '?', '#']).next().unwrap_or_default();
+    if let Some((scheme, _)) = first_part.split_once(':') {
+        let valid_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+        if !valid_scheme {
+            return invalid(format!("`{}:` is not a valid scheme, write `./{}` for a relative path", scheme, reference));
+        }
+    }
+    Ok(())
+}
+
+/// Warn about every `$id` in `value`, found at `pointer`, that is already in `ids`, which maps
+/// absolute `$id`s to where they were first seen.
+fn duplicate_ids(value: &Value, base: String, pointer: String, ids: &mut HashMap<String, String>, warnings: &mut Vec<Warning>) {
+    match value {
+        Value::Object(obj) => {
+            let base = join_id(value, base);
+            if obj.get("$id").is_some_and(Value::is_string) {
+                match ids.get(&base) {
+                    Some(first) => warnings.push(Warning::DuplicateId {
+                        id: base.clone(),
+                        first: first.clone(),
+                        pointer: pointer.clone(),
+                    }),
+                    None => {
+                        ids.insert(base.clone(), pointer.clone());
+                    }
+                }
+            }
+            for (key, child) in obj {
+                duplicate_ids(child, base.clone(), format!("{}/{}", pointer, escape_pointer(key)), ids, warnings);
+            }
+        }
+        Value::Array(array) => {
+            for (index, child) in array.iter().enumerate() {
+                duplicate_ids(child, base.clone(), format!("{}/{}", pointer, index), ids, warnings);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Every `$ref` string in `value`, found at `pointer`, as its pointer, the reference and the base
+/// url it is resolved against.
+fn ref_sites(value: &Value, base: String, pointer: String, sites: &mut Vec<(String, String, String)>) {
+    match value {
+        Value::Object(obj) => {
+            let base = join_id(value, base);
+            if let Some(reference) = obj.get("$ref").and_then(Value::as_str) {
+                sites.push((pointer.clone(), reference.to_owned(), base.clone()));
+            }
+            for (key, child) in obj {
+                ref_sites(child, base.clone(), format!("{}/{}", pointer, escape_pointer(key)), sites);
+            }
+        }
+        Value::Array(array) => {
+            for (index, child) in array.iter().enumerate() {
+                ref_sites(child, base.clone(), format!("{}/{}", pointer, index), sites);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The base url in effect at `pointer` in `value`, taking every `$id` above it into account.
+fn base_url_at(value: &Value, url: String, pointer: &str) -> String {
+    let mut base = url;
+    let mut parent = String::new();
+    for token in pointer.split('/').skip(1) {
+        if let Some(parent_value) = value.pointer(&parent) {
+            base = join_id(parent_value, base);
+        }
+        parent.push('/');
+        parent.push_str(token);
+    }
+    base
+}
+
+/// Whether `value` has any `$ref`.
+fn has_ref(value: &Value) -> bool {
+    match value {
+        Value::Object(obj) => obj.contains_key("$ref") || obj.values().any(has_ref),
+        Value::Array(array) => array.iter().any(has_ref),
+        _ => false,
+    }
+}
+
+/// Whether `value` still has a `$ref` into its own document.
+#[cfg(feature = "schemars")]
+fn has_local_ref(value: &Value) -> bool {
+    match value {
+        Value::Object(obj) => {
+            obj.get("$ref").and_then(Value::as_str).is_some_and(|reference| reference.starts_with('#'))
+                || obj.values().any(has_local_ref)
+        }
+        Value::Array(array) => array.iter().any(has_local_ref),
+        _ => false,
+    }
+}
+
+/// The catalog of [SchemaStore](https://www.schemastore.org), for [`JsonRef::deref_known`].
+const SCHEMASTORE_CATALOG: &str = "https://www.schemastore.org/api/json/catalog.json";
+
+/// `name` as compared to the names of catalog entries: lower case, without a `.json` extension.
+fn catalog_name(name: &str) -> String {
+    let name = name.to_lowercase();
+    name.strip_suffix(".json").map(str::to_owned).unwrap_or(name)
+}
+
+/// Fetch the document at `url`, which has no fragment.
+pub(crate) fn fetch(http: &http::Http, url: &Url) -> Result<Value> {
+    let url_string = url.to_string();
+    if url_string.starts_with("http") {
+        fetch_http(http, &url_string)
+    } else if url_string.starts_with("file") {
+        let file = fs::File::open(url.path()).context(SchemaFromFile {filename: url_string.clone()})?;
+        parse(file, &url_string)
+    } else {
+        Err(Error::UnsupportedUrl {url: url_string, reason: "only file and http urls are supported".to_owned()})
+    }
+}
+
+/// The registry API url for the schema version named by a `registry://subject/version` url.
+fn registry_url(registry: &str, url: &Url) -> Result<String> {
+    let subject = url.host_str().filter(|subject| !subject.is_empty()).ok_or_else(|| Error::UnsupportedUrl {
+        url: url.to_string(),
+        reason: "registry urls need a subject, as in `registry://subject/version`".to_owned(),
+    })?;
+    let version = match url.path().trim_matches('/') {
+        "" => "latest",
+        version => version,
+    };
+    Ok(format!("{}/subjects/{}/versions/{}", registry, subject, version))
+}
+
+fn fetch_http(http: &http::Http, url: &str) -> Result<Value> {
+    let response = http.get(url)?;
+    #[cfg(feature = "serde_yaml")]
+    if is_yaml(url) {
+        return parse(response.into_reader(), url);
+    }
+    response.into_json().context(SchemaNotJson {url})
+}
+
+/// The text of the document at the `file` or `http` url `url`, with its `Content-Type` if it was
+/// given one.
+fn fetch_text(http: &http::Http, url: &Url) -> Result<(String, Option<String>)> {
+    let url_string = url.to_string();
+    if url.scheme() == "file" {
+        let text = fs::read_to_string(url.path()).context(SchemaFromFile {filename: url_string})?;
+        Ok((text, None))
+    } else {
+        let response = http.get(&url_string)?;
+        let content_type = response.header("content-type").map(str::to_owned);
+        let text = response.into_string().context(SchemaNotJson {url: url_string})?;
+        Ok((text, content_type))
+    }
+}
+
+/// Whether the document at `url` is known not to be JSON (or YAML): neither its `Content-Type` nor
+/// its file extension says it is, and at least one of them says something else. Documents with
+/// neither are taken to be JSON.
+fn is_non_json(url: &Url, content_type: Option<&str>) -> bool {
+    let essence = content_type.map(|content_type| {
+        let essence = content_type.split(';').next().unwrap_or(content_type);
+        essence.trim().to_ascii_lowercase()
+    });
+    let json_type = essence
+        .as_deref()
+        .is_some_and(|essence| essence.ends_with("/json") || essence.ends_with("+json") || essence.contains("yaml"));
+    let file_name = url.path_segments().and_then(|mut segments| segments.next_back()).unwrap_or("");
+    let extension = file_name.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase());
+    let json_extension = matches!(extension.as_deref(), Some("json" | "yaml" | "yml"));
+    !json_type && !json_extension && (essence.is_some() || extension.is_some())
+}
+
+/// Parse the document read from `url`, as YAML if it has a `.yaml` or `.yml` extension and the
+/// `serde_yaml` feature is enabled, otherwise as JSON.
+fn parse<R: io::Read>(reader: R, url: &str) -> Result<Value> {
+    #[cfg(feature = "serde_yaml")]
+    if is_yaml(url) {
+        return serde_yaml::from_reader(reader).context(SchemaNotYaml {url});
+    }
+    serde_json::from_reader(reader).context(SchemaNotJsonSerde {url})
+}
+
+#[cfg(feature = "serde_yaml")]
+fn is_yaml(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url).to_ascii_lowercase();
+    path.ends_with(".yaml") || path.ends_with(".yml")
+}
+
+/// The object to add keys to for an inlined schema. Boolean schemas are first replaced with the
+/// equivalent object schema, and `None` is returned for values that are not schemas.
+fn annotatable(schema: &mut Value) -> Option<&mut Map<String, Value>> {
+    if let Value::Bool(valid) = schema {
+        *schema = if *valid {
+            Value::Object(Map::new())
+        } else {
+            serde_json::json!({"not": {}})
+        };
+    }
+    schema.as_object_mut()
+}
+
+/// A plain name for a schema `$id`, taken from its file name.
+fn fragment_id(id: &str) -> String {
+    let path = id.split('#').next().unwrap_or(id);
+    let file_name = path.rsplit('/').find(|segment| !segment.is_empty()).unwrap_or("schema");
+    let stem = file_name.split('.').next().unwrap_or(file_name);
+    let name: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .collect();
+    if name.is_empty() {
+        "schema".to_owned()
+    } else {
+        name
+    }
+}
+
+/// Whether `pointer` is strictly inside the value at `parent`.
+fn pointer_within(pointer: &str, parent: &str) -> bool {
+    pointer.starts_with(parent) && pointer[parent.len()..].starts_with('/')
+}
+
+/// Escape a key for use as a JSON pointer token.
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Name for a bundled `$defs` entry, taken from the last token of the fragment or the file name.
+fn bundle_name(ref_url: &Url) -> String {
+    let from_fragment = ref_url
+        .fragment()
+        .and_then(|fragment| fragment.rsplit('/').find(|token| !token.is_empty()))
+        .map(|token| token.replace("~1", "/").replace("~0", "~"));
+    let from_path = || {
+        ref_url
+            .path_segments()
+            .and_then(|mut segments| segments.rfind(|segment| !segment.is_empty()))
+            .map(|segment| segment.split('.').next().unwrap_or(segment).to_owned())
+    };
+    sanitize_name(&from_fragment.or_else(from_path).unwrap_or_default())
+}
+
+/// Whether the fragment of `ref_url` points at an entry of `$defs` or `definitions`.
+fn names_definition(ref_url: &Url) -> bool {
+    let fragment = ref_url.fragment().unwrap_or_default();
+    ["/$defs/", "/definitions/"]
+        .iter()
+        .any(|prefix| fragment.strip_prefix(prefix).is_some_and(|name| !name.contains('/')))
+}
+
+/// `name` with any character that is awkward in a JSON pointer or a type name replaced.
+fn sanitize_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+    if name.is_empty() {
+        "schema".to_owned()
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_uri_reference, data_pointer_error, is_non_json, registry_url, Error, ErrorKind, Event, IdPolicy, JsonRef, RefScope, TraversalPolicy, VocabularyPolicy, Warning};
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use url::Url;
+
+    #[test]
+    fn json_no_refs() {
+        let no_ref_example = json!({"properties": {"prop1": {"title": "proptitle"}}});
+
+        let mut jsonref = JsonRef::new();
+
+        let mut input = no_ref_example.clone();
+
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(input, no_ref_example)
+    }
+
+    #[test]
+    fn json_with_recursion() {
+        let mut simple_refs_example = json!(
+            {"properties": {"prop1": {"$ref": "#"}}}
+        );
+
+        let simple_refs_expected = json!(
+            {"properties": {"prop1": {"properties": {"prop1": {}}}}
+            }
+        );
+
+        let mut jsonref = JsonRef::new();
+        jsonref.deref_value(&mut simple_refs_example).unwrap();
+        jsonref.set_reference_key("__reference__");
+
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&simple_refs_example).unwrap()
+        );
+
+        assert_eq!(simple_refs_example, simple_refs_expected)
+    }
+
+    #[test]
+    fn simple_from_url() {
+        let mut simple_refs_example = json!(
+            {"properties": {"prop1": {"title": "name"},
+                            "prop2": {"$ref": "https://gist.githubusercontent.com/kindly/35a631d33792413ed8e34548abaa9d61/raw/b43dc7a76cc2a04fde2a2087f0eb389099b952fb/test.json", "title": "old_title"}}
+            }
+        );
+
+        let simple_refs_expected = json!(
+            {"properties": {"prop1": {"title": "name"},
+                            "prop2": {"title": "title from url", "__reference__": {"title": "old_title"}}}
+            }
+        );
+
+        let mut jsonref = JsonRef::new();
+        jsonref.set_reference_key("__reference__");
+        jsonref.deref_value(&mut simple_refs_example).unwrap();
+
+        assert_eq!(simple_refs_example, simple_refs_expected)
+    }
+
+    #[test]
+    fn nested_with_ref_from_url() {
+        let mut simple_refs_example = json!(
+            {"properties": {"prop1": {"title": "name"},
+                            "prop2": {"$ref": "https://gist.githubusercontent.com/kindly/35a631d33792413ed8e34548abaa9d61/raw/0a691c035251f742e8710f71ba92ead307823385/test_nested.json"}}
+            }
+        );
+
+        let simple_refs_expected = json!(
+            {"properties": {"prop1": {"title": "name"},
+                            "prop2": {"__reference__": {},
+                                      "title": "title from url",
+                                      "properties": {"prop1": {"title": "sub property title in url"},
+                                                     "prop2": {"__reference__": {}, "title": "sub property title in url"}}
+                            }}
+            }
+        );
+
+        let mut jsonref = JsonRef::new();
+        jsonref.set_reference_key("__reference__");
+        jsonref.deref_value(&mut simple_refs_example).unwrap();
+
+        assert_eq!(simple_refs_example, simple_refs_expected)
+    }
+
+    #[test]
+    fn nested_ref_from_local_file() {
+        let mut jsonref = JsonRef::new();
+        jsonref.set_reference_key("__reference__");
+        let file_example = jsonref
+            .deref_file("fixtures/nested_relative/base.json")
+            .unwrap();
+
+        let file = fs::File::open("fixtures/nested_relative/expected.json").unwrap();
+        let file_expected: Value = serde_json::from_reader(file).unwrap();
+
+        println!("{}", serde_json::to_string_pretty(&file_example).unwrap());
+
+        assert_eq!(file_example, file_expected)
+    }
+
+    #[test]
+    fn max_depth_keeps_deeper_refs() {
+        let mut jsonref = JsonRef::new();
+        jsonref.set_max_depth(1);
+        jsonref.set_absolute_refs(true);
+        let file_example = jsonref
+            .deref_file("fixtures/nested_relative/base.json")
+            .unwrap();
+
+        let other = fs::canonicalize("fixtures/nested_relative/other.json").unwrap();
+        let expected_ref = format!("file://{}#/properties/prop1", other.to_string_lossy());
+
+        assert_eq!(
+            file_example["properties"]["prop3"]["properties"]["prop2"],
+            json!({"$ref": expected_ref})
+        );
+        assert_eq!(
+            file_example["properties"]["prop2"],
+            json!({"title": "sub property title in base.json"})
+        );
+    }
+
+    #[test]
+    fn max_depth_zero_inlines_nothing() {
+        let mut input = json!(
+            {"properties": {"prop1": {"title": "name"},
+                            "prop2": {"$ref": "#/properties/prop1"}}}
+        );
+        let expected = input.clone();
+
+        let mut jsonref = JsonRef::new();
+        jsonref.set_max_depth(0);
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(input, expected)
+    }
+
+    #[test]
+    fn ref_filter_by_location() {
+        let mut input = json!(
+            {"properties": {"prop1": {"title": "name"},
+                            "prop2": {"$ref": "#/properties/prop1"},
+                            "prop/3": {"$ref": "#/properties/prop1"}}}
+        );
+
+        let mut jsonref = JsonRef::new();
+        jsonref.set_ref_filter(|site| site.pointer == "/properties/prop~13");
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(input["properties"]["prop2"], json!({"$ref": "#/properties/prop1"}));
+        assert_eq!(input["properties"]["prop/3"], json!({"title": "name"}));
+    }
+
+    #[test]
+    fn internal_scope_reads_no_files() {
+        let mut jsonref = JsonRef::new();
+        jsonref.set_ref_scope(RefScope::Internal);
+        let file_example = jsonref
+            .deref_file("fixtures/nested_relative/base.json")
+            .unwrap();
+
+        assert_eq!(
+            file_example["properties"]["prop2"],
+            json!({"title": "sub property title in base.json"})
+        );
+        assert_eq!(file_example["properties"]["prop3"], json!({"$ref": "other.json"}));
+        assert_eq!(jsonref.documents().len(), 1);
+    }
+
+    #[test]
+    fn external_scope_keeps_internal_refs() {
+        let mut jsonref = JsonRef::new();
+        jsonref.set_ref_scope(RefScope::External);
+        let file_example = jsonref
+            .deref_file("fixtures/nested_relative/base.json")
+            .unwrap();
+
+        assert_eq!(file_example["properties"]["prop2"], json!({"$ref": "#/properties/prop1"}));
+        assert_eq!(
+            file_example["properties"]["prop3"]["properties"]["prop2"],
+            json!({"title": "sub property title in other.json"})
+        );
+        assert_eq!(
+            file_example["properties"]["prop5"],
+            json!({"title": "sub property title in other.json"})
+        );
+    }
+
+    #[test]
+    fn deref_at_uses_parent_id() {
+        let mut input = json!(
+            {"properties": {"prop1": {"$id": "fixtures/nested_relative/",
+                                      "properties": {"inner": {"$ref": "other.json#/properties/prop1"}}},
+                            "prop2": {"$ref": "#/properties/missing"}}}
+        );
+
+        let mut jsonref = JsonRef::new();
+        jsonref
+            .deref_at(&mut input, "/properties/prop1/properties/inner")
+            .unwrap();
+
+        assert_eq!(
+            input["properties"]["prop1"]["properties"]["inner"],
+            json!({"title": "sub property title in other.json"})
+        );
+        assert_eq!(input["properties"]["prop2"], json!({"$ref": "#/properties/missing"}));
+        assert!(jsonref.deref_at(&mut input, "/properties/prop3").is_err());
+    }
+
+    #[test]
+    fn refs_in_arrays_and_flatten_all_of() {
+        let mut input = json!(
+            {"definitions": {"named": {"properties": {"name": {"type": "string"}}, "required": ["name"]}},
+             "allOf": [{"$ref": "#/definitions/named"},
+                       {"properties": {"age": {"type": "integer"}}}]}
+        );
+
+        let mut jsonref = JsonRef::new();
+        jsonref.set_flatten_all_of(true);
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(
+            input["properties"],
+            json!({"name": {"type": "string"}, "age": {"type": "integer"}})
+        );
+        assert_eq!(input["required"], json!(["name"]));
+        assert!(input.get("allOf").is_none());
+    }
+
+    #[test]
+    fn strip_ids_of_inlined_schemas() {
+        let mut input = json!(
+            {"$defs": {"a": {"$id": "https://example.com/a.json",
+                             "$schema": "https://json-schema.org/draft/2020-12/schema",
+                             "properties": {"b": {"$id": "b.json", "type": "string"}}}},
+             "properties": {"prop1": {"$ref": "#/$defs/a"}}}
+        );
+
+        let mut jsonref = JsonRef::new();
+        jsonref.set_id_policy(IdPolicy::Strip);
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(
+            input["properties"]["prop1"],
+            json!({"properties": {"b": {"type": "string"}}})
+        );
+        assert_eq!(input["$defs"]["a"]["$id"], json!("https://example.com/a.json"));
+    }
+
+    #[test]
+    fn merge_extensions_with_reference_key() {
+        let mut input = json!(
+            {"properties": {"prop1": {"title": "name"},
+                            "prop2": {"$ref": "#/properties/prop1", "title": "old_title", "x-internal": true}}}
+        );
+
+        let mut jsonref = JsonRef::new();
+        jsonref.set_reference_key("__reference__");
+        jsonref.set_merge_extensions(true);
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(
+            input["properties"]["prop2"],
+            json!({"title": "name", "x-internal": true, "__reference__": {"title": "old_title"}})
+        );
+    }
+
+    #[test]
+    fn reference_key_on_non_object_targets() {
+        let mut input = json!(
+            {"definitions": {"anything": true, "nothing": false, "colours": ["red", "green"]},
+             "properties": {"prop1": {"$ref": "#/definitions/anything", "title": "old_title"},
+                            "prop2": {"$ref": "#/definitions/nothing"},
+                            "prop3": {"$ref": "#/definitions/colours", "title": "colours"}}}
+        );
+
+        let mut jsonref = JsonRef::new();
+        jsonref.set_reference_key("__reference__");
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(
+            input["properties"]["prop1"],
+            json!({"__reference__": {"title": "old_title"}})
+        );
+        assert_eq!(
+            input["properties"]["prop2"],
+            json!({"not": {}, "__reference__": {}})
+        );
+        assert_eq!(input["properties"]["prop3"], json!(["red", "green"]));
+        assert_eq!(
+            jsonref.warnings(),
+            [Warning::SiblingKeysDiscarded {
+                pointer: "/properties/prop3".to_owned(),
+                keys: vec!["title".to_owned()],
+            }]
+        );
+    }
+
+    #[test]
+    fn source_key_for_nested_refs() {
+        let mut jsonref = JsonRef::new();
+        jsonref.set_source_key("$deref_from");
+        let file_example = jsonref
+            .deref_file("fixtures/nested_relative/base.json")
+            .unwrap();
+
+        let other = fs::canonicalize("fixtures/nested_relative/other.json").unwrap();
+        let other_url = format!("file://{}", other.to_string_lossy());
+
+        assert_eq!(file_example["properties"]["prop3"]["$deref_from"], json!(other_url));
+        assert_eq!(
+            file_example["properties"]["prop3"]["properties"]["prop2"]["$deref_from"],
+            json!(format!("{}#/properties/prop1", other_url))
+        );
+        assert!(file_example["properties"]["prop1"].get("$deref_from").is_none());
+    }
+
+    #[test]
+    fn replacements_of_nested_refs() {
+        let mut jsonref = JsonRef::new();
+        let file_example = jsonref
+            .deref_file("fixtures/nested_relative/base.json")
+            .unwrap();
+
+        let mut pointers: Vec<&str> = jsonref
+            .replacements()
+            .iter()
+            .map(|replacement| replacement.pointer.as_str())
+            .collect();
+        pointers.sort();
+        assert_eq!(
+            pointers,
+            vec![
+                "/properties/prop2",
+                "/properties/prop3",
+                "/properties/prop3/properties/prop2",
+                "/properties/prop4",
+                "/properties/prop5",
+                "/properties/prop5",
+            ]
+        );
+
+        let diff = jsonref.diff(&file_example);
+        assert_eq!(diff["/properties/prop3"]["ref"], json!("other.json"));
+        assert_eq!(diff["/properties/prop3"]["replacement"], file_example["properties"]["prop3"]);
+        assert_eq!(diff["/properties/prop5"]["ref"], json!("other.json#/properties/prop2"));
+    }
+
+    #[test]
+    fn dedupe_repeated_file_targets() {
+        let mut input = json!(
+            {"properties": {"a": {"$ref": "fixtures/nested_relative/other.json"},
+                            "b": {"$ref": "fixtures/nested_relative/other.json"},
+                            "c": {"$ref": "fixtures/nested_relative/other.json", "title": "different"},
+                            "d": {"$ref": "fixtures/nested_relative/base.json#/properties/prop1"}}}
+        );
+
+        let mut jsonref = JsonRef::new();
+        jsonref.set_dedupe_threshold(2);
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(input["properties"]["a"], json!({"$ref": "#/$defs/other"}));
+        assert_eq!(input["properties"]["b"], json!({"$ref": "#/$defs/other"}));
+        assert_eq!(input["properties"]["c"], json!({"$ref": "#/$defs/other"}));
+        assert_eq!(input["properties"]["d"], json!({"title": "sub property title in base.json"}));
+        assert_eq!(input["$defs"]["other"]["title"], json!("title from url"));
+        assert!(jsonref
+            .replacements()
+            .iter()
+            .any(|replacement| replacement.pointer == "/$defs/other/properties/prop2"));
+    }
+
+    #[test]
+    fn dedupe_nested_targets() {
+        let mut jsonref = JsonRef::new();
+        jsonref.register_at("https://example.com/defs.json", json!(
+            {"$defs": {"inner": {"type": "string"},
+                       "outer": {"properties": {"a": {"$ref": "#/$defs/inner"}, "b": {"$ref": "#/$defs/inner"}}}}}
+        ));
+        let mut input = json!(
+            {"properties": {"x": {"$ref": "https://example.com/defs.json#/$defs/outer"},
+                            "y": {"$ref": "https://example.com/defs.json#/$defs/outer"}}}
+        );
+
+        jsonref.set_dedupe_threshold(0);
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(input["properties"]["x"], json!({"$ref": "#/$defs/outer"}));
+        assert_eq!(input["properties"]["y"], json!({"$ref": "#/$defs/outer"}));
+        assert_eq!(input["$defs"]["outer"]["properties"]["a"], json!({"type": "string"}));
+    }
+
+    #[test]
+    fn dedupe_reuses_root_definitions() {
+        let mut input = json!(
+            {"$defs": {"name": {"type": "string", "minLength": 1}},
+             "properties": {"first": {"$ref": "#/$defs/name"}, "last": {"$ref": "#/$defs/name"}}}
+        );
+
+        let mut jsonref = JsonRef::new();
+        jsonref.set_dedupe_threshold(2);
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(input["properties"]["first"], json!({"$ref": "#/$defs/name"}));
+        assert_eq!(input["properties"]["last"], json!({"$ref": "#/$defs/name"}));
+        assert_eq!(input["$defs"], json!({"name": {"type": "string", "minLength": 1}}));
+    }
+
+    #[test]
+    fn bundle_large_targets() {
+        let mut jsonref = JsonRef::new();
+        jsonref.set_bundle_threshold(60);
+        let file_example = jsonref
+            .deref_file("fixtures/nested_relative/base.json")
+            .unwrap();
+
+        assert_eq!(file_example["properties"]["prop3"], json!({"$ref": "#/$defs/other"}));
+        assert_eq!(
+            file_example["$defs"]["other"]["properties"]["prop2"],
+            json!({"title": "sub property title in other.json"})
+        );
+        assert_eq!(
+            file_example["properties"]["prop4"],
+            json!({"title": "sub property title in other.json"})
+        );
+    }
+
+    #[test]
+    fn bundle_recursive_target() {
+        let mut input = json!(
+            {"definitions": {"node": {"properties": {"child": {"$ref": "#/definitions/node"}}}},
+             "properties": {"root": {"$ref": "#/definitions/node"}}}
+        );
+
+        let mut jsonref = JsonRef::new();
+        jsonref.set_bundle_threshold(0);
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(input["properties"]["root"], json!({"$ref": "#/$defs/node"}));
+        assert_eq!(
+            input["$defs"]["node"],
+            json!({"properties": {"child": {"$ref": "#/$defs/node"}}})
+        );
+    }
+
+    #[test]
+    fn codegen_profile_keeps_identity() {
+        let mut jsonref = JsonRef::new();
+        jsonref.set_codegen_profile(true);
+
+        let output = jsonref.deref_file("fixtures/nested_relative/base.json").unwrap();
+
+        assert_eq!(output["properties"]["prop3"], json!({"$ref": "#/$defs/other"}));
+        assert_eq!(output["properties"]["prop5"], json!({"$ref": "#/$defs/prop2"}));
+        // other.json's own ref now points at the shared entry rather than a copy.
+        assert_eq!(output["$defs"]["prop2"], json!({"$ref": "#/$defs/prop1"}));
+        assert_eq!(output["$defs"]["other"]["properties"]["prop2"], json!({"$ref": "#/$defs/prop1"}));
+    }
+
+    #[cfg(feature = "serde_yaml")]
+    #[test]
+    fn yaml_files() {
+        let mut jsonref = JsonRef::new();
+        let output = jsonref.deref_file("fixtures/yaml/base.yaml").unwrap();
+        assert_eq!(output, json!({"properties": {"name": {"type": "string", "title": "Name"}}}));
+    }
+
+    #[test]
+    fn warnings() {
+        let mut input = json!(
+            {"$defs": {"a": {"$id": "https://example.com/a.json"},
+                       "b": {"$id": "https://example.com/a.json"},
+                       "node": {"properties": {"child": {"$ref": "#/$defs/node"}}}},
+             "properties": {"$ref": {"type": "string"}}}
+        );
+        let mut jsonref = JsonRef::new();
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(input["properties"]["$ref"], json!({"type": "string"}));
+        let warnings: Vec<String> = jsonref.warnings().iter().map(ToString::to_string).collect();
+        let anon = format!("file://{}/anon.json", std::env::current_dir().unwrap().to_string_lossy());
+        assert_eq!(warnings, vec![
+            "$id https://example.com/a.json at /$defs/b is already declared at /$defs/a".to_owned(),
+            format!("recursive $ref to {}#/$defs/node at /$defs/node/properties/child/properties/child was left out", anon),
+            "$ref at /properties is not a string and was skipped".to_owned(),
+        ]);
+    }
+
+    #[test]
+    fn uri_references() {
+        for valid in ["#/$defs/a", "other.json#/properties/a%20b", "https://example.com/a.json", "./1:2.json", "urn:x"] {
+            assert!(check_uri_reference(valid).is_ok(), "{}", valid);
+        }
+        for invalid in ["a b.json", "dir\\a.json", "a%2.json", "a%zz", "é.json", "a#b#c", "1:2.json", "<a>"] {
+            assert!(check_uri_reference(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn data_pointers() {
+        for valid in ["", "/a/b", "/a~1b~0", "0", "1/password", "12#", "0/items/0"] {
+            assert_eq!(data_pointer_error(valid), None, "{}", valid);
+        }
+        for invalid in ["a", "01/a", "#", "1a", "/a~2", "/a~"] {
+            assert!(data_pointer_error(invalid).is_some(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn error_kinds() {
+        let mut jsonref = JsonRef::new();
+        assert_eq!(jsonref.deref_file("fixtures/missing.json").unwrap_err().kind(), ErrorKind::Io);
+        assert_eq!(jsonref.deref_file("Cargo.toml").unwrap_err().kind(), ErrorKind::Parse);
+        let mut input = json!({"$ref": "other.json"});
+        assert_eq!(jsonref.deref_value(&mut input).unwrap_err().kind(), ErrorKind::Io);
+        let tls = crate::http::HostOptions::new().root_certificates(b"not pem").unwrap_err();
+        assert_eq!(tls.kind(), ErrorKind::Configuration);
+    }
+
+    #[test]
+    fn clone_keeps_cache() {
+        let mut jsonref = JsonRef::default();
+        jsonref.set_reference_key("__reference__");
+        jsonref.register_at("https://example.com/name.json", json!({"type": "string"}));
+        let mut clone = jsonref.clone();
+
+        let mut input = json!({"properties": {"name": {"$ref": "https://example.com/name.json"}}});
+        clone.deref_value(&mut input).unwrap();
+        assert_eq!(input["properties"]["name"]["type"], "string");
+        assert!(input["properties"]["name"].get("__reference__").is_some());
+    }
+
+    #[test]
+    fn keep_unknown_refs() {
+        let mut jsonref = JsonRef::new();
+        jsonref.set_traversal_policy(TraversalPolicy::KeepUnknown);
+        jsonref.set_absolute_refs(true);
+        jsonref.register_at("https://example.com/name.json", json!({"type": "string"}));
+
+        let mut input = json!(
+            {"$defs": {"name": {"$ref": "name.json"}},
+             "properties": {"name": {"$ref": "#/$defs/name"}, "x-ids": {"const": {"$ref": "name.json"}}},
+             "x-vendor": {"$ref": "name.json"}}
+        );
+        jsonref.deref_value_with_url(&mut input, "https://example.com/root.json").unwrap();
+
+        assert_eq!(input["properties"]["name"], json!({"type": "string"}));
+        assert_eq!(input["properties"]["x-ids"]["const"], json!({"$ref": "https://example.com/name.json"}));
+        assert_eq!(input["x-vendor"], json!({"$ref": "https://example.com/name.json"}));
+    }
+
+    #[test]
+    fn registry_urls() {
+        let registry = "http://localhost:8081";
+        let url = |url| registry_url(registry, &url::Url::parse(url).unwrap());
+
+        assert_eq!(url("registry://orders-value/3").unwrap(), "http://localhost:8081/subjects/orders-value/versions/3");
+        assert_eq!(url("registry://orders-value").unwrap(), "http://localhost:8081/subjects/orders-value/versions/latest");
+        assert!(url("registry:orders-value").is_err());
+
+        let mut input = json!({"$ref": "registry://orders-value/3"});
+        let error = JsonRef::new().deref_value(&mut input).unwrap_err();
+        assert_eq!(error.to_string(), "Can not retrieve registry://orders-value/3: no schema registry is set");
+    }
+
+    #[test]
+    fn vocabularies() {
+        let mut jsonref = JsonRef::new();
+        jsonref.set_vocabulary_policy(VocabularyPolicy::Error);
+        jsonref.register_at("https://example.com/dialect", json!(
+            {"$vocabulary": {"https://json-schema.org/draft/2020-12/vocab/core": true,
+                             "https://example.com/vocab/optional": false,
+                             "https://example.com/vocab/required": true}}
+        ));
+
+        let mut standard = json!({"$schema": "https://json-schema.org/draft/2020-12/schema#"});
+        jsonref.deref_value(&mut standard).unwrap();
+
+        let mut custom = json!({"$schema": "https://example.com/dialect"});
+        let error = jsonref.deref_value(&mut custom).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Meta-schema https://example.com/dialect requires vocabulary https://example.com/vocab/required, which is not supported"
+        );
+    }
+
+    #[test]
+    fn register_by_id() {
+        let mut jsonref = JsonRef::new();
+        jsonref
+            .register_str(r#"{"$id": "urn:example:name#", "type": "string"}"#)
+            .unwrap();
+        assert!(jsonref.register(json!({"type": "string"})).is_err());
+
+        let mut input = json!({"properties": {"name": {"$ref": "urn:example:name"}}});
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(input["properties"]["name"], json!({"$id": "urn:example:name#", "type": "string"}));
+    }
+
+    #[test]
+    fn cache_ttl_and_refresh() {
+        let fetches = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counted = fetches.clone();
+        let mut jsonref = JsonRef::new();
+        jsonref.add_layer(move |_: &url::Url, _: crate::resolve::Next| {
+            let version = counted.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(json!({"title": version}))
+        });
+        let schema = json!({"$ref": "https://example.com/versioned.json"});
+
+        let mut output = schema.clone();
+        jsonref.deref_value(&mut output).unwrap();
+        jsonref.deref_value(&mut output.clone()).unwrap();
+        assert_eq!(output, json!({"title": 0}));
+
+        jsonref.refresh("https://example.com/versioned.json#/title").unwrap();
+        let mut output = schema.clone();
+        jsonref.deref_value(&mut output).unwrap();
+        assert_eq!(output, json!({"title": 1}));
+
+        jsonref.set_cache_ttl(std::time::Duration::ZERO);
+        let mut output = schema.clone();
+        jsonref.deref_value(&mut output).unwrap();
+        assert_eq!(output, json!({"title": 2}));
+    }
+
+    #[test]
+    fn bundle_keeps_definition_names() {
+        let mut jsonref = JsonRef::new();
+        jsonref.register_at("https://example.com/defs.json", json!(
+            {"$defs": {"Person": {"properties": {"name": {"type": "string"}}},
+                       "Address": {"properties": {"street": {"type": "string"}}}},
+             "properties": {"owner": {"$ref": "#/$defs/Person"},
+                            "address": {"properties": {"street": {"type": "string"}}}}}
+        ));
+        jsonref.set_codegen_profile(true);
+
+        let mut input = json!(
+            {"properties": {"owner": {"$ref": "https://example.com/defs.json#/properties/owner"},
+                            "person": {"$ref": "https://example.com/defs.json#/$defs/Person"},
+                            "address": {"$ref": "https://example.com/defs.json#/properties/address"}}}
+        );
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(input["properties"]["owner"], json!({"$ref": "#/$defs/Person"}));
+        assert_eq!(input["properties"]["person"], json!({"$ref": "#/$defs/Person"}));
+        assert_eq!(input["properties"]["address"], json!({"$ref": "#/$defs/Address"}));
+        assert_eq!(input["$defs"].as_object().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn embed_only_non_json() {
+        let mut jsonref = JsonRef::new();
+        jsonref.set_embed_non_json(true);
+
+        let mut input = json!({"description": {"$ref": "fixtures/embed/person.md"}});
+        jsonref.deref_value(&mut input).unwrap();
+        assert_eq!(input["description"], json!("# Person\n\nSomeone with a *name*.\n"));
+
+        let mut input = json!({"items": {"$ref": "fixtures/embed/broken.json"}});
+        assert!(jsonref.deref_value(&mut input).is_err());
+
+        let url = Url::parse("http://example.com/schema").unwrap();
+        assert!(!is_non_json(&url, None));
+        assert!(!is_non_json(&url, Some("application/schema+json; charset=utf-8")));
+        assert!(is_non_json(&url, Some("text/markdown")));
+        let url = Url::parse("http://example.com/schema.json").unwrap();
+        assert!(!is_non_json(&url, Some("text/plain")));
+    }
+
+    #[test]
+    fn documents_without_refs() {
+        let mut jsonref = JsonRef::new();
+        jsonref.register_at("https://example.com/dialect.json", json!({"type": ["object", "boolean"]}));
+        jsonref.set_bundle_meta_schema(true);
+        jsonref.set_deterministic(true);
+        jsonref.set_record_patch(true);
+        jsonref.add_pass(|schema: &mut Value| {
+            schema["title"] = json!("checked");
+        });
+
+        let mut input = json!({"type": "object", "$schema": "https://example.com/dialect.json", "properties": {"b": {}, "a": {}}});
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(
+            input.to_string(),
+            r#"{"$defs":{"dialect":{"type":["object","boolean"]}},"$schema":"https://example.com/dialect.json","properties":{"a":{},"b":{}},"title":"checked","type":"object"}"#
+        );
+        assert_eq!(jsonref.patch().len(), 2);
+    }
+
+    #[test]
+    fn operation_refs_without_refs() {
+        let mut jsonref = JsonRef::new();
+        jsonref.set_openapi(true);
+        jsonref.set_absolute_refs(true);
+
+        let mut spec = json!(
+            {"openapi": "3.1.0",
+             "paths": {"/pets": {"get": {"responses": {"200": {"description": "pets",
+                                                              "links": {"self": {"operationRef": "#/paths/~1pets/get"}}}}}}}}
+        );
+        jsonref.deref_value_with_url(&mut spec, "https://example.com/api.json").unwrap();
+
+        assert_eq!(
+            spec["paths"]["/pets"]["get"]["responses"]["200"]["links"]["self"]["operationRef"],
+            json!("https://example.com/api.json#/paths/~1pets/get")
+        );
+    }
+
+    #[test]
+    fn deterministic_output() {
+        /// `value` with the keys of every object inserted in reverse order.
+        fn reversed(value: &Value) -> Value {
+            match value {
+                Value::Object(obj) => Value::Object(obj.iter().rev().map(|(key, value)| (key.clone(), reversed(value))).collect()),
+                Value::Array(array) => Value::Array(array.iter().map(reversed).collect()),
+                other => other.clone(),
+            }
+        }
+
+        let defs = json!(
+            {"$defs": {"address": {"type": "object", "title": "an address large enough to be bundled",
+                                   "properties": {"street": {"type": "string"}, "city": {"type": "string"}}},
+                       "name": {"type": "string"},
+                       "tag": {"type": "string", "maxLength": 10}}}
+        );
+        let input = json!(
+            {"properties": {"home": {"$ref": "https://example.com/defs.json#/$defs/address"},
+                            "work": {"$ref": "https://example.com/defs.json#/$defs/address"},
+                            "name": {"$ref": "https://example.com/defs.json#/$defs/name"},
+                            "tags": {"type": "array", "items": {"$ref": "https://example.com/defs.json#/$defs/tag"}},
+                            "labels": {"type": "array", "items": {"$ref": "https://example.com/defs.json#/$defs/tag"}}}}
+        );
+        let run = |defs: Value, mut input: Value| {
+            let mut jsonref = JsonRef::new();
+            jsonref.register_at("https://example.com/defs.json", defs);
+            jsonref.set_bundle_threshold(40);
+            jsonref.set_dedupe_threshold(2);
+            jsonref.set_deterministic(true);
+            jsonref.deref_value(&mut input).unwrap();
+            input.to_string()
+        };
+
+        let first = run(defs.clone(), input.clone());
+        let second = run(reversed(&defs), reversed(&input));
+        assert_eq!(first, second);
+
+        let output: Value = serde_json::from_str(&first).unwrap();
+        assert_eq!(output["properties"]["home"], json!({"$ref": "#/$defs/address"}));
+        assert_eq!(output["properties"]["name"], json!({"type": "string"}));
+        assert_eq!(output["properties"]["tags"]["items"], json!({"$ref": "#/$defs/tag"}));
+        assert_eq!(output["properties"]["labels"]["items"], json!({"$ref": "#/$defs/tag"}));
+    }
+
+    #[test]
+    fn cached_document_unchanged_by_fragment_refs() {
+        let mut document = json!(
+            {"$defs": {"person": {"type": "object", "properties": {"name": {"$ref": "#/$defs/name"}}},
+                       "name": {"type": "string"}}}
+        );
+        for index in 0..500 {
+            document["$defs"][format!("filler{}", index)] = json!({"type": "integer", "minimum": index});
+        }
+        let mut jsonref = JsonRef::new();
+        jsonref.register_at("https://example.com/big.json", document.clone());
+
+        let mut input = json!({"properties": {"owner": {"$ref": "https://example.com/big.json#/$defs/person"},
+                                              "author": {"$ref": "https://example.com/big.json#/$defs/person"}}});
+        jsonref.deref_value(&mut input).unwrap();
+
+        let person = json!({"type": "object", "properties": {"name": {"type": "string"}}});
+        assert_eq!(input["properties"]["owner"], person);
+        assert_eq!(input["properties"]["author"], person);
+        assert_eq!(*jsonref.schema_cache.get("https://example.com/big.json").unwrap(), document);
+    }
+
+    #[test]
+    fn deref_documents_cycle() {
+        let fetched = Arc::new(Mutex::new(vec![]));
+        let mut jsonref = JsonRef::new();
+        let seen = fetched.clone();
+        jsonref.set_event_handler(move |event| {
+            if let Event::Fetching { url } = event {
+                seen.lock().unwrap().push(url.to_string());
+            }
+        });
+
+        let a = json!({"title": "a", "properties": {"b": {"$ref": "b.json"}}});
+        let b = json!({"title": "b", "properties": {"a": {"$ref": "a.json"}}});
+        let output = jsonref
+            .deref_documents(vec![("https://example.com/a.json", a), ("https://example.com/b.json", b)])
+            .unwrap();
+
+        assert_eq!(
+            output[0],
+            json!({"title": "a", "properties": {"b": {"title": "b", "properties": {"a": {"title": "a", "properties": {"b": {}}}}}}})
+        );
+        assert_eq!(
+            output[1],
+            json!({"title": "b", "properties": {"a": {"title": "a", "properties": {"b": {"title": "b", "properties": {"a": {}}}}}}})
+        );
+        assert!(fetched.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn deref_documents_by_id() {
+        let fetched = Arc::new(Mutex::new(vec![]));
+        let mut jsonref = JsonRef::new();
+        let seen = fetched.clone();
+        jsonref.set_event_handler(move |event| {
+            if let Event::Fetching { url } = event {
+                seen.lock().unwrap().push(url.to_string());
+            }
+        });
+
+        let name = json!({"$id": "https://schemas.example.com/name.json", "type": "string"});
+        let person = json!({"properties": {"name": {"$ref": "https://schemas.example.com/name.json"}}});
+        let output = jsonref
+            .deref_documents(vec![("missing/name.json", name), ("missing/person.json", person)])
+            .unwrap();
+
+        assert_eq!(output[1]["properties"]["name"]["type"], json!("string"));
+        assert!(fetched.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_snapshots() {
+        let path = std::env::temp_dir().join(format!("jsonref-invalid-snapshot-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut jsonref = JsonRef::new();
+
+        fs::write(path, r#"[{"type": "string"}]"#).unwrap();
+        let error = jsonref.load_snapshot(path).unwrap_err();
+        assert!(matches!(&error, Error::InvalidSnapshot { reason, .. } if reason == "not an object of URL to document"));
+        assert_eq!(error.kind(), ErrorKind::Parse);
+
+        fs::write(path, r#"{"name.json": {"type": "string"}}"#).unwrap();
+        let error = jsonref.load_snapshot(path).unwrap_err();
+        assert!(matches!(&error, Error::InvalidSnapshot { reason, .. } if reason.starts_with("name.json is not a URL")));
+        assert!(jsonref.documents().is_empty());
+
+        jsonref.register_at("https://example.com/name.json", json!({"type": "string"}));
+        jsonref.write_snapshot(path).unwrap();
+        let mut offline = JsonRef::new();
+        offline.load_snapshot(path).unwrap();
+        assert_eq!(offline.snapshot(), json!({"https://example.com/name.json": {"type": "string"}}));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,58 @@
+//! The official JSON Schema meta-schemas, embedded so refs to `json-schema.org` resolve without
+//! network access.
+
+use serde_json::Value;
+use url::Url;
+
+/// The embedded meta-schemas, by their path on `json-schema.org`.
+const META_SCHEMAS: &[(&str, &str)] = &[
+    ("/draft-04/schema", include_str!("../meta-schemas/draft-04/schema.json")),
+    ("/draft-06/schema", include_str!("../meta-schemas/draft-06/schema.json")),
+    ("/draft-07/schema", include_str!("../meta-schemas/draft-07/schema.json")),
+    ("/draft/2019-09/schema", include_str!("../meta-schemas/draft/2019-09/schema.json")),
+    ("/draft/2019-09/meta/core", include_str!("../meta-schemas/draft/2019-09/meta/core.json")),
+    ("/draft/2019-09/meta/applicator", include_str!("../meta-schemas/draft/2019-09/meta/applicator.json")),
+    ("/draft/2019-09/meta/validation", include_str!("../meta-schemas/draft/2019-09/meta/validation.json")),
+    ("/draft/2019-09/meta/meta-data", include_str!("../meta-schemas/draft/2019-09/meta/meta-data.json")),
+    ("/draft/2019-09/meta/format", include_str!("../meta-schemas/draft/2019-09/meta/format.json")),
+    ("/draft/2019-09/meta/content", include_str!("../meta-schemas/draft/2019-09/meta/content.json")),
+    ("/draft/2020-12/schema", include_str!("../meta-schemas/draft/2020-12/schema.json")),
+    ("/draft/2020-12/meta/core", include_str!("../meta-schemas/draft/2020-12/meta/core.json")),
+    ("/draft/2020-12/meta/applicator", include_str!("../meta-schemas/draft/2020-12/meta/applicator.json")),
+    ("/draft/2020-12/meta/unevaluated", include_str!("../meta-schemas/draft/2020-12/meta/unevaluated.json")),
+    ("/draft/2020-12/meta/validation", include_str!("../meta-schemas/draft/2020-12/meta/validation.json")),
+    ("/draft/2020-12/meta/meta-data", include_str!("../meta-schemas/draft/2020-12/meta/meta-data.json")),
+    ("/draft/2020-12/meta/format-annotation", include_str!("../meta-schemas/draft/2020-12/meta/format-annotation.json")),
+    ("/draft/2020-12/meta/format-assertion", include_str!("../meta-schemas/draft/2020-12/meta/format-assertion.json")),
+    ("/draft/2020-12/meta/content", include_str!("../meta-schemas/draft/2020-12/meta/content.json")),
+];
+
+/// The embedded meta-schema at `url`, over `http` or `https`, if there is one.
+pub(crate) fn get(url: &Url) -> Option<Value> {
+    if !matches!(url.scheme(), "http" | "https") || url.host_str() != Some("json-schema.org") {
+        return None;
+    }
+    let path = url.path().trim_end_matches(".json");
+    META_SCHEMAS
+        .iter()
+        .find(|(meta_schema_path, _)| *meta_schema_path == path)
+        .map(|(_, text)| serde_json::from_str(text).expect("embedded meta-schemas are JSON"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_meta_schemas() {
+        for (path, text) in META_SCHEMAS {
+            let meta_schema: Value = serde_json::from_str(text).unwrap();
+            let id = meta_schema.get("$id").or_else(|| meta_schema.get("id")).and_then(Value::as_str).unwrap();
+            assert_eq!(Url::parse(id).unwrap().path(), *path);
+        }
+        let draft_07 = get(&Url::parse("https://json-schema.org/draft-07/schema").unwrap()).unwrap();
+        assert_eq!(draft_07["$id"], "http://json-schema.org/draft-07/schema#");
+        assert!(get(&Url::parse("https://json-schema.org/draft-08/schema").unwrap()).is_none());
+        assert!(get(&Url::parse("https://example.com/draft-07/schema").unwrap()).is_none());
+    }
+}
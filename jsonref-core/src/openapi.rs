@@ -0,0 +1,61 @@
+//! The parts of OpenAPI documents that need more than generic dereferencing, see
+//! [`JsonRef::set_openapi`](crate::JsonRef::set_openapi).
+
+use serde_json::Value;
+
+/// The last token of `pointer` but `n`, still escaped.
+fn token(pointer: &str, n: usize) -> Option<&str> {
+    pointer.rsplit('/').nth(n).filter(|_| !pointer.is_empty())
+}
+
+/// Whether `value`, under `key` of the object at `pointer`, is example data rather than part
+/// of the description: an `example`, the `value` of an Example Object, or the `examples` of a
+/// schema. Any `$ref` in it is data too.
+pub(crate) fn is_example(pointer: &str, key: &str, value: &Value) -> bool {
+    match key {
+        "example" => true,
+        "examples" => value.is_array(),
+        "value" => token(pointer, 1) == Some("examples"),
+        _ => false,
+    }
+}
+
+/// Whether the object at `pointer` is a Link Object, an entry of a `links` map.
+pub(crate) fn is_link(pointer: &str) -> bool {
+    token(pointer, 1) == Some("links")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::JsonRef;
+    use serde_json::json;
+
+    #[test]
+    fn callbacks_links_and_webhooks() {
+        let mut jsonref = JsonRef::new();
+        jsonref.set_openapi(true);
+        let output = jsonref.deref_file("fixtures/openapi/api.json").unwrap();
+        let shared = jsonref
+            .documents()
+            .into_iter()
+            .find(|url| url.ends_with("shared.json"))
+            .unwrap()
+            .to_owned();
+
+        let post = &output["paths"]["/subscriptions"]["post"];
+        let callback = &post["callbacks"]["onEvent"]["{$request.body#/callbackUrl}"]["post"];
+        assert_eq!(callback["requestBody"]["content"]["application/json"]["schema"]["properties"]["id"], json!({"type": "string"}));
+        assert_eq!(callback["requestBody"]["content"]["application/json"]["schema"]["examples"], json!([{"$ref": "not a reference"}]));
+        assert_eq!(
+            post["responses"]["201"]["links"]["unsubscribe"]["operationRef"],
+            json!(format!("{}#/paths/~1subscriptions~1{{id}}/delete", shared))
+        );
+        assert_eq!(
+            post["requestBody"]["content"]["application/json"]["example"],
+            json!({"callbackUrl": "https://example.com/hook", "$ref": "kept as data"})
+        );
+        assert_eq!(post["requestBody"]["description"], json!("where to send events"));
+        assert!(jsonref.warnings().is_empty());
+        assert_eq!(output["webhooks"]["renewal"]["post"]["responses"]["200"], json!({"description": "renewed"}));
+    }
+}
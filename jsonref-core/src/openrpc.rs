@@ -0,0 +1,46 @@
+//! The parts of OpenRPC documents that need more than generic dereferencing, see
+//! [`JsonRef::set_openrpc`](crate::JsonRef::set_openrpc).
+
+use serde_json::Value;
+
+/// Whether `value`, under `key` of the object at `pointer`, is example data rather than part
+/// of the description: the `value` of an Example Object, or the `examples` of a schema. Any
+/// `$ref` in it is data too. The `examples` of a method are Example Pairing Objects, which may
+/// be refs themselves, so they are dereferenced.
+pub(crate) fn is_example(pointer: &str, key: &str, value: &Value) -> bool {
+    match key {
+        "examples" => value.is_array() && !is_method(pointer),
+        "value" => pointer.split('/').any(|token| matches!(token, "examples" | "examplePairingObjects")),
+        _ => false,
+    }
+}
+
+/// Whether the object at `pointer` is a Method Object, an entry of the root `methods`.
+fn is_method(pointer: &str) -> bool {
+    let mut tokens = pointer.split('/').skip(1);
+    matches!(
+        (tokens.next(), tokens.next(), tokens.next()),
+        (Some("methods"), Some(index), None) if index.parse::<usize>().is_ok()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::JsonRef;
+    use serde_json::json;
+
+    #[test]
+    fn methods_and_components() {
+        let mut jsonref = JsonRef::new();
+        jsonref.set_openrpc(true);
+        let output = jsonref.deref_file("fixtures/openrpc/api.json").unwrap();
+
+        let method = &output["methods"][0];
+        assert_eq!(method["params"][0], json!({"name": "id", "required": true, "schema": {"type": "integer"}}));
+        assert_eq!(method["result"]["schema"]["properties"]["id"], json!({"type": "integer"}));
+        assert_eq!(method["result"]["schema"]["examples"], json!([{"id": 1, "name": {"$ref": "not a reference"}}]));
+        assert_eq!(method["errors"][0]["code"], json!(404));
+        assert_eq!(method["examples"][0]["result"]["value"], json!({"id": 1, "name": "Rex", "$ref": "kept as data"}));
+        assert!(jsonref.warnings().is_empty());
+    }
+}
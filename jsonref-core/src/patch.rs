@@ -0,0 +1,170 @@
+//! [JSON Patch (RFC 6902)](https://www.rfc-editor.org/rfc/rfc6902) operations describing how
+//! dereferencing changed a document.
+
+use crate::{escape_pointer, Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A single JSON Patch operation. Serializes to the RFC 6902 form, e.g.
+/// `{"op": "replace", "path": "/properties/prop2", "value": {...}}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+}
+
+/// The operations that turn `from` into `to`.
+///
+/// ```
+/// use jsonref::patch::{diff, PatchOperation};
+/// use serde_json::json;
+///
+/// let from = json!({"properties": {"prop1": {"$ref": "#/definitions/name"}}});
+/// let to = json!({"properties": {"prop1": {"type": "string"}}});
+///
+/// assert_eq!(diff(&from, &to), vec![
+///     PatchOperation::Remove { path: "/properties/prop1/$ref".into() },
+///     PatchOperation::Add { path: "/properties/prop1/type".into(), value: json!("string") },
+/// ]);
+/// ```
+pub fn diff(from: &Value, to: &Value) -> Vec<PatchOperation> {
+    let mut operations = vec![];
+    diff_at(from, to, "", &mut operations);
+    operations
+}
+
+fn diff_at(from: &Value, to: &Value, path: &str, operations: &mut Vec<PatchOperation>) {
+    if from == to {
+        return;
+    }
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            for key in from.keys().filter(|key| !to.contains_key(*key)) {
+                operations.push(PatchOperation::Remove {
+                    path: format!("{}/{}", path, escape_pointer(key)),
+                });
+            }
+            for (key, to_value) in to {
+                let key_path = format!("{}/{}", path, escape_pointer(key));
+                match from.get(key) {
+                    Some(from_value) => diff_at(from_value, to_value, &key_path, operations),
+                    None => operations.push(PatchOperation::Add {
+                        path: key_path,
+                        value: to_value.clone(),
+                    }),
+                }
+            }
+        }
+        (Value::Array(from), Value::Array(to)) => {
+            for (index, (from_value, to_value)) in from.iter().zip(to).enumerate() {
+                diff_at(from_value, to_value, &format!("{}/{}", path, index), operations);
+            }
+            for index in (to.len()..from.len()).rev() {
+                operations.push(PatchOperation::Remove {
+                    path: format!("{}/{}", path, index),
+                });
+            }
+            for (index, to_value) in to.iter().enumerate().skip(from.len()) {
+                operations.push(PatchOperation::Add {
+                    path: format!("{}/{}", path, index),
+                    value: to_value.clone(),
+                });
+            }
+        }
+        _ => operations.push(PatchOperation::Replace {
+            path: path.to_owned(),
+            value: to.clone(),
+        }),
+    }
+}
+
+/// Apply `operations` to `value` in order.
+pub fn apply(value: &mut Value, operations: &[PatchOperation]) -> Result<()> {
+    for operation in operations {
+        match operation {
+            PatchOperation::Replace { path, value: new_value } => {
+                *pointer_mut(value, path)? = new_value.clone();
+            }
+            PatchOperation::Add { path, value: new_value } => {
+                let (parent_path, token) = split_path(path)?;
+                match pointer_mut(value, parent_path)? {
+                    Value::Object(obj) => {
+                        obj.insert(unescape_pointer(token), new_value.clone());
+                    }
+                    Value::Array(array) => {
+                        let index = if token == "-" {
+                            array.len()
+                        } else {
+                            token.parse().ok().filter(|index| *index <= array.len()).ok_or_else(|| not_found(path))?
+                        };
+                        array.insert(index, new_value.clone());
+                    }
+                    _ => return Err(not_found(path)),
+                }
+            }
+            PatchOperation::Remove { path } => {
+                let (parent_path, token) = split_path(path)?;
+                let removed = match pointer_mut(value, parent_path)? {
+                    Value::Object(obj) => obj.remove(&unescape_pointer(token)).is_some(),
+                    Value::Array(array) => match token.parse::<usize>() {
+                        Ok(index) if index < array.len() => {
+                            array.remove(index);
+                            true
+                        }
+                        _ => false,
+                    },
+                    _ => false,
+                };
+                if !removed {
+                    return Err(not_found(path));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn pointer_mut<'a>(value: &'a mut Value, path: &str) -> Result<&'a mut Value> {
+    value.pointer_mut(path).ok_or_else(|| not_found(path))
+}
+
+fn split_path(path: &str) -> Result<(&str, &str)> {
+    path.rsplit_once('/').ok_or_else(|| not_found(path))
+}
+
+fn unescape_pointer(token: &str) -> String {
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+fn not_found(path: &str) -> Error {
+    Error::JsonPointerNotFound {
+        pointer: format!("patch path `{}` can not be found in the document", path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, diff, PatchOperation};
+    use serde_json::json;
+
+    #[test]
+    fn diff_and_apply_round_trip() {
+        let from = json!({"a/b": [1, 2, 3], "c": {"d": 1}, "e": "x"});
+        let to = json!({"a/b": [1, 5], "c": {"f": [true]}, "e": {"g": null}});
+
+        let operations = diff(&from, &to);
+        assert_eq!(
+            serde_json::to_value(&operations[0]).unwrap(),
+            json!({"op": "replace", "path": "/a~1b/1", "value": 5})
+        );
+        assert!(operations.contains(&PatchOperation::Remove { path: "/a~1b/2".into() }));
+
+        let mut patched = from.clone();
+        apply(&mut patched, &operations).unwrap();
+        assert_eq!(patched, to);
+
+        assert!(apply(&mut patched, &[PatchOperation::Remove { path: "/missing".into() }]).is_err());
+    }
+}
@@ -0,0 +1,766 @@
+//! Layers that documents are retrieved through, to add policies such as allowlists, mirrors or
+//! logging without changing how refs are resolved.
+//!
+//! A document a ref points into is looked for, in order, in
+//!
+//! 1. the documents a [`JsonRef`] has already read or registered,
+//! 2. each [`Layer`] added with [`JsonRef::add_layer`], in the order added,
+//! 3. the URL rewriter, schema registry and `file`/`http` fetching of the [`JsonRef`].
+//!
+//! Each layer can answer itself, refuse, or pass the URL on, possibly changed, to the rest of
+//! the chain with [`Next::run`]. Closures taking the URL and a [`Next`] are layers too.
+//!
+//! ```
+//! use jsonref::resolve::{Allowlist, Documents};
+//! use jsonref::JsonRef;
+//! use serde_json::json;
+//!
+//! let mut jsonref = JsonRef::new();
+//! jsonref.add_layer(|url: &url::Url, next: jsonref::resolve::Next| {
+//!     eprintln!("retrieving {}", url);
+//!     next.run(url)
+//! });
+//! jsonref.add_layer(Documents::new([("https://example.com/name.json", json!({"type": "string"}))]));
+//! jsonref.add_layer(Allowlist::new(&["https://example.com/"]));
+//!
+//! let mut input = json!({"properties": {"name": {"$ref": "https://example.com/name.json"}}});
+//! jsonref.deref_value(&mut input).unwrap();
+//! assert_eq!(input["properties"]["name"], json!({"type": "string"}));
+//!
+//! let mut input = json!({"$ref": "https://elsewhere.example.org/name.json"});
+//! assert!(jsonref.deref_value(&mut input).is_err());
+//! ```
+//!
+//! [`SharedCache`] keeps what the layers after it retrieve, for instances to share, and
+//! [`Retry`] retries what they fail to retrieve over the network. [`CircuitBreaker`] stops
+//! asking hosts that keep failing.
+//!
+//! [`JsonRef`]: crate::JsonRef
+//! [`JsonRef::add_layer`]: crate::JsonRef::add_layer
+
+use crate::http::Http;
+use crate::{Callback, Error, ErrorKind, Result};
+use serde_json::Value;
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// A step in retrieving documents, see the [module documentation](self).
+pub trait Layer: Send + Sync {
+    /// Retrieve the document at `url`, which has no fragment, answering directly or passing it
+    /// to `next`.
+    fn retrieve(&self, url: &Url, next: Next<'_>) -> Result<Value>;
+}
+
+impl<F> Layer for F
+where
+    F: Fn(&Url, Next<'_>) -> Result<Value> + Send + Sync,
+{
+    fn retrieve(&self, url: &Url, next: Next<'_>) -> Result<Value> {
+        self(url, next)
+    }
+}
+
+/// The rest of the chain after a layer.
+#[derive(Clone, Copy)]
+pub struct Next<'a> {
+    layers: &'a [Callback<dyn Layer>],
+    #[cfg_attr(not(any(feature = "azure", feature = "gcs")), allow(dead_code))]
+    http: &'a Http,
+    fetch: &'a dyn Fn(&Url) -> Result<Value>,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(layers: &'a [Callback<dyn Layer>], http: &'a Http, fetch: &'a dyn Fn(&Url) -> Result<Value>) -> Next<'a> {
+        Next { layers, http, fetch }
+    }
+
+    /// The HTTP settings of the [`JsonRef`](crate::JsonRef), for layers sending requests of
+    /// their own.
+    #[cfg(any(feature = "azure", feature = "gcs"))]
+    pub(crate) fn http(&self) -> &'a Http {
+        self.http
+    }
+
+    /// Retrieve the document at `url` with the rest of the chain.
+    pub fn run(self, url: &Url) -> Result<Value> {
+        match self.layers.split_first() {
+            Some((layer, layers)) => layer.0.retrieve(url, Next { layers, ..self }),
+            None => (self.fetch)(url),
+        }
+    }
+}
+
+/// Only retrieve URLs under one of the given URL prefixes, failing with
+/// [`Error::UnsupportedUrl`] for any other. A URL is under a prefix when it has the same scheme,
+/// host and port, and its path is the prefix's path or continues it after a `/`, so
+/// `https://example.com` does not admit `https://example.com.evil.org/`.
+#[derive(Debug, Clone)]
+pub struct Allowlist {
+    prefixes: Vec<String>,
+}
+
+impl Allowlist {
+    pub fn new<S: AsRef<str>>(prefixes: &[S]) -> Allowlist {
+        Allowlist {
+            prefixes: prefixes.iter().map(|prefix| prefix.as_ref().to_owned()).collect(),
+        }
+    }
+}
+
+impl Layer for Allowlist {
+    fn retrieve(&self, url: &Url, next: Next<'_>) -> Result<Value> {
+        if self.prefixes.iter().any(|prefix| under_prefix(url, prefix)) {
+            next.run(url)
+        } else {
+            Err(Error::UnsupportedUrl {
+                url: url.to_string(),
+                reason: "not in the allowlist".to_owned(),
+            })
+        }
+    }
+}
+
+/// Whether `url` is under the URL `prefix`, as [`Allowlist`] decides. A prefix that is not a URL
+/// matches nothing.
+pub(crate) fn under_prefix(url: &Url, prefix: &str) -> bool {
+    let prefix = match Url::parse(prefix) {
+        Ok(prefix) => prefix,
+        Err(_) => return false,
+    };
+    if url.scheme() != prefix.scheme()
+        || url.host() != prefix.host()
+        || url.port_or_known_default() != prefix.port_or_known_default()
+    {
+        return false;
+    }
+    let path = prefix.path();
+    if path.is_empty() || path.ends_with('/') {
+        url.path().starts_with(path)
+    } else {
+        url.path() == path || url.path().strip_prefix(path).is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+/// Answer with fixed documents by URL, passing on any other. Unlike
+/// [`JsonRef::register_at`](crate::JsonRef::register_at), the documents can be placed after
+/// other layers, and shared between instances by adding the same layer.
+#[derive(Debug, Clone, Default)]
+pub struct Documents {
+    documents: HashMap<String, Value>,
+}
+
+impl Documents {
+    pub fn new<I, S>(documents: I) -> Documents
+    where
+        I: IntoIterator<Item = (S, Value)>,
+        S: Into<String>,
+    {
+        Documents {
+            documents: documents.into_iter().map(|(url, document)| (url.into(), document)).collect(),
+        }
+    }
+}
+
+impl Layer for Documents {
+    fn retrieve(&self, url: &Url, next: Next<'_>) -> Result<Value> {
+        match self.documents.get(url.as_str()) {
+            Some(document) => Ok(document.clone()),
+            None => next.run(url),
+        }
+    }
+}
+
+/// Keep every document retrieved through the rest of the chain, answering later requests for
+/// it from memory. Clones share the documents, so adding clones of one cache, or
+/// [`SharedCache::global`], to many instances saves each of them fetching the same documents,
+/// as for the short-lived instance of each request in a web server.
+///
+/// ```
+/// use jsonref::resolve::SharedCache;
+/// use jsonref::JsonRef;
+/// use serde_json::json;
+///
+/// let cache = SharedCache::new();
+/// cache.insert("https://example.com/name.json", json!({"type": "string"}));
+///
+/// for _ in 0..2 {
+///     let mut jsonref = JsonRef::new();
+///     jsonref.add_layer(cache.clone());
+///     let mut input = json!({"$ref": "https://example.com/name.json"});
+///     jsonref.deref_value(&mut input).unwrap();
+///     assert_eq!(input, json!({"type": "string"}));
+/// }
+/// assert_eq!(cache.len(), 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SharedCache {
+    /// The documents, split by a hash of their URL so that instances on different threads
+    /// rarely wait on the same lock.
+    shards: Arc<[RwLock<HashMap<String, Value>>]>,
+}
+
+/// The number of shards of a [`SharedCache`].
+const SHARDS: usize = 16;
+
+impl Default for SharedCache {
+    fn default() -> SharedCache {
+        SharedCache {
+            shards: (0..SHARDS).map(|_| RwLock::new(HashMap::new())).collect(),
+        }
+    }
+}
+
+impl SharedCache {
+    pub fn new() -> SharedCache {
+        SharedCache::default()
+    }
+
+    /// The cache shared by the whole process, created when first used. Nothing uses it unless
+    /// it is added as a layer.
+    pub fn global() -> SharedCache {
+        static GLOBAL: OnceLock<SharedCache> = OnceLock::new();
+        GLOBAL.get_or_init(SharedCache::new).clone()
+    }
+
+    fn shard(&self, url: &str) -> &RwLock<HashMap<String, Value>> {
+        let mut hasher = DefaultHasher::new();
+        url.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+
+    // A panic elsewhere while a shard was locked leaves its documents whole, as each change is a
+    // single map operation, so poisoned locks are used as they are.
+
+    /// The document at `url`, if held.
+    pub fn get(&self, url: &str) -> Option<Value> {
+        self.shard(url).read().unwrap_or_else(PoisonError::into_inner).get(url).cloned()
+    }
+
+    /// Add, or replace, the document at `url`, which should have no fragment.
+    pub fn insert(&self, url: &str, document: Value) {
+        self.shard(url).write().unwrap_or_else(PoisonError::into_inner).insert(url.to_owned(), document);
+    }
+
+    /// Drop the document at `url`, so it is retrieved again when next needed.
+    pub fn remove(&self, url: &str) {
+        self.shard(url).write().unwrap_or_else(PoisonError::into_inner).remove(url);
+    }
+
+    /// Drop every document.
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            shard.write().unwrap_or_else(PoisonError::into_inner).clear();
+        }
+    }
+
+    /// The number of documents held.
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.read().unwrap_or_else(PoisonError::into_inner).len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Layer for SharedCache {
+    fn retrieve(&self, url: &Url, next: Next<'_>) -> Result<Value> {
+        if let Some(document) = self.get(url.as_str()) {
+            return Ok(document);
+        }
+        let document = next.run(url)?;
+        self.insert(url.as_str(), document.clone());
+        Ok(document)
+    }
+}
+
+/// Remember documents the rest of the chain failed to retrieve, failing again straight away
+/// with [`Error::RecentlyFailed`] for `ttl` after, so a dead URL referred to in many places is
+/// only tried once.
+///
+/// ```
+/// use jsonref::resolve::{FailureCache, Next};
+/// use jsonref::{Error, JsonRef};
+/// use serde_json::json;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let attempts = Arc::new(AtomicUsize::new(0));
+/// let counted = attempts.clone();
+/// let mut jsonref = JsonRef::new();
+/// jsonref.add_layer(FailureCache::new(Duration::from_secs(60)));
+/// jsonref.add_layer(move |url: &url::Url, _: Next| {
+///     counted.fetch_add(1, Ordering::SeqCst);
+///     Err(Error::UnsupportedUrl { url: url.to_string(), reason: "gone".to_owned() })
+/// });
+///
+/// let schema = json!({"properties": {"a": {"$ref": "https://example.com/gone.json"},
+///                                    "b": {"$ref": "https://example.com/gone.json#/b"}}});
+/// assert_eq!(jsonref.check_value(&schema, "file:///schema.json").len(), 2);
+/// assert_eq!(attempts.load(Ordering::SeqCst), 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FailureCache {
+    ttl: Duration,
+    failures: Arc<Mutex<HashMap<String, Failure>>>,
+}
+
+#[derive(Debug, Clone)]
+struct Failure {
+    failed_at: Instant,
+    reason: String,
+    kind: ErrorKind,
+}
+
+impl FailureCache {
+    pub fn new(ttl: Duration) -> FailureCache {
+        FailureCache {
+            ttl,
+            failures: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl Layer for FailureCache {
+    fn retrieve(&self, url: &Url, next: Next<'_>) -> Result<Value> {
+        let recent = self.failures.lock().ok().and_then(|failures| {
+            failures
+                .get(url.as_str())
+                .filter(|failure| failure.failed_at.elapsed() < self.ttl)
+                .cloned()
+        });
+        if let Some(failure) = recent {
+            return Err(Error::RecentlyFailed {
+                url: url.to_string(),
+                reason: failure.reason,
+                kind: failure.kind,
+            });
+        }
+        let retrieved = next.run(url);
+        if let Ok(mut failures) = self.failures.lock() {
+            match &retrieved {
+                Ok(_) => failures.remove(url.as_str()),
+                Err(error) => failures.insert(
+                    url.to_string(),
+                    Failure {
+                        failed_at: Instant::now(),
+                        reason: error.to_string(),
+                        kind: error.kind(),
+                    },
+                ),
+            };
+        }
+        retrieved
+    }
+}
+
+/// Try documents the rest of the chain fails to retrieve over the network again, waiting
+/// longer before each attempt, so a transient error such as a 502 from a schema registry does
+/// not fail the whole run. By default, connection failures, `429` and `5xx` responses are
+/// retried, with waits doubling from 100ms up to 10s, less up to half of each at random so
+/// instances started together do not retry together.
+///
+/// ```
+/// use jsonref::resolve::{Next, Retry};
+/// use jsonref::JsonRef;
+/// use serde_json::json;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let attempts = Arc::new(AtomicUsize::new(0));
+/// let counted = attempts.clone();
+/// let mut jsonref = JsonRef::new();
+/// jsonref.add_layer(
+///     Retry::new(3)
+///         .backoff(Duration::from_millis(1), Duration::from_millis(10))
+///         .retry_on(&[502, 503]),
+/// );
+/// jsonref.add_layer(move |url: &url::Url, _: Next| {
+///     if counted.fetch_add(1, Ordering::SeqCst) < 2 {
+///         let response = ureq::Response::new(502, "Bad Gateway", "").unwrap();
+///         return Err(jsonref::Error::SchemaFromUrl {
+///             url: url.to_string(),
+///             source: ureq::Error::Status(502, response),
+///         });
+///     }
+///     Ok(json!({"type": "string"}))
+/// });
+///
+/// let mut input = json!({"$ref": "https://registry.example.com/name.json"});
+/// jsonref.deref_value(&mut input).unwrap();
+/// assert_eq!(input, json!({"type": "string"}));
+/// assert_eq!(attempts.load(Ordering::SeqCst), 3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Retry {
+    attempts: u32,
+    initial: Duration,
+    max: Duration,
+    jitter: bool,
+    statuses: Option<Vec<u16>>,
+}
+
+impl Retry {
+    /// Retry up to `attempts` attempts in all, the first included.
+    pub fn new(attempts: u32) -> Retry {
+        Retry {
+            attempts: attempts.max(1),
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+            jitter: true,
+            statuses: None,
+        }
+    }
+
+    /// Wait `initial` before the second attempt, doubling for each one after, up to `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Retry {
+        self.initial = initial;
+        self.max = max;
+        self
+    }
+
+    /// Whether to take up to half of each wait off at random. On by default.
+    pub fn jitter(mut self, jitter: bool) -> Retry {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Retry responses with these status codes, rather than `429` and `5xx` ones. Connection
+    /// failures are still retried.
+    pub fn retry_on(mut self, statuses: &[u16]) -> Retry {
+        self.statuses = Some(statuses.to_vec());
+        self
+    }
+
+    fn retries(&self, error: &Error) -> bool {
+        match (error, &self.statuses) {
+            (Error::SchemaFromUrl { source: ureq::Error::Status(status, _), .. }, Some(statuses)) => {
+                statuses.contains(status)
+            }
+            _ => error.kind() == ErrorKind::Network,
+        }
+    }
+
+    /// The wait after the failed attempt numbered `attempt`, from 0.
+    fn wait(&self, attempt: u32) -> Duration {
+        let wait = self.initial.saturating_mul(2u32.saturating_pow(attempt)).min(self.max);
+        if !self.jitter {
+            return wait;
+        }
+        let random = RandomState::new().build_hasher().finish();
+        wait / 2 + (wait / 2).mul_f64((random % 1000) as f64 / 1000.0)
+    }
+}
+
+impl Layer for Retry {
+    fn retrieve(&self, url: &Url, next: Next<'_>) -> Result<Value> {
+        let mut attempt = 0;
+        loop {
+            match next.run(url) {
+                Err(error) if attempt + 1 < self.attempts && self.retries(&error) => {
+                    std::thread::sleep(self.wait(attempt));
+                    attempt += 1;
+                }
+                retrieved => return retrieved,
+            }
+        }
+    }
+}
+
+/// Stop asking a host that has failed `threshold` times in a row over the network, failing
+/// every document on it straight away with [`Error::HostUnavailable`] for `cooldown` after,
+/// rather than waiting for each to time out. After the cooldown one request is let through,
+/// which closes the circuit again if it succeeds. Clones share their state, and
+/// [`CircuitBreaker::report`] summarises the hosts that failed, for after a large batch.
+///
+/// ```
+/// use jsonref::resolve::{CircuitBreaker, Next};
+/// use jsonref::{Error, JsonRef};
+/// use serde_json::json;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let attempts = Arc::new(AtomicUsize::new(0));
+/// let counted = attempts.clone();
+/// let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+/// let mut jsonref = JsonRef::new();
+/// jsonref.add_layer(breaker.clone());
+/// jsonref.add_layer(move |url: &url::Url, _: Next| {
+///     counted.fetch_add(1, Ordering::SeqCst);
+///     let response = ureq::Response::new(503, "Service Unavailable", "").unwrap();
+///     Err(Error::SchemaFromUrl { url: url.to_string(), source: ureq::Error::Status(503, response) })
+/// });
+///
+/// let schema = json!({"properties": {"a": {"$ref": "https://down.example.com/a.json"},
+///                                    "b": {"$ref": "https://down.example.com/b.json"},
+///                                    "c": {"$ref": "https://down.example.com/c.json"},
+///                                    "d": {"$ref": "https://down.example.com/d.json"}}});
+/// assert_eq!(jsonref.check_value(&schema, "file:///schema.json").len(), 4);
+/// assert_eq!(attempts.load(Ordering::SeqCst), 2);
+///
+/// let report = breaker.report();
+/// assert_eq!(report[0].host, "down.example.com");
+/// assert_eq!((report[0].failures, report[0].refused, report[0].open), (2, 2, true));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    threshold: usize,
+    cooldown: Duration,
+    hosts: Arc<Mutex<HashMap<String, HostState>>>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct HostState {
+    /// Failures in a row.
+    failures: usize,
+    refused: usize,
+    opened_at: Option<Instant>,
+    reason: String,
+}
+
+/// How a host has fared, from [`CircuitBreaker::report`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostReport {
+    pub host: String,
+    /// The failures in a row, up to the last success.
+    pub failures: usize,
+    /// The documents failed without asking the host.
+    pub refused: usize,
+    /// Whether the host is not being asked.
+    pub open: bool,
+    /// The last error from the host.
+    pub reason: String,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: usize, cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            threshold: threshold.max(1),
+            cooldown,
+            hosts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The hosts that have failed or been refused since they last succeeded, by name.
+    pub fn report(&self) -> Vec<HostReport> {
+        let hosts = match self.hosts.lock() {
+            Ok(hosts) => hosts,
+            Err(_) => return vec![],
+        };
+        let mut report: Vec<HostReport> = hosts
+            .iter()
+            .filter(|(_, state)| state.failures > 0 || state.refused > 0)
+            .map(|(host, state)| HostReport {
+                host: host.clone(),
+                failures: state.failures,
+                refused: state.refused,
+                open: state.opened_at.is_some_and(|opened_at| opened_at.elapsed() < self.cooldown),
+                reason: state.reason.clone(),
+            })
+            .collect();
+        report.sort_by(|first, second| first.host.cmp(&second.host));
+        report
+    }
+}
+
+impl Layer for CircuitBreaker {
+    fn retrieve(&self, url: &Url, next: Next<'_>) -> Result<Value> {
+        let host = match url.host_str() {
+            Some(host) => host.to_owned(),
+            None => return next.run(url),
+        };
+        if let Ok(mut hosts) = self.hosts.lock() {
+            if let Some(state) = hosts.get_mut(&host) {
+                match state.opened_at {
+                    Some(opened_at) if opened_at.elapsed() < self.cooldown => {
+                        state.refused += 1;
+                        return Err(Error::HostUnavailable {
+                            url: url.to_string(),
+                            host,
+                            failures: state.failures,
+                            reason: state.reason.clone(),
+                        });
+                    }
+                    // Let this request through to try the host again, refusing the others
+                    // for another cooldown unless it succeeds.
+                    Some(_) => state.opened_at = Some(Instant::now()),
+                    None => {}
+                }
+            }
+        }
+        let retrieved = next.run(url);
+        if let Ok(mut hosts) = self.hosts.lock() {
+            match &retrieved {
+                Err(error) if error.kind() == ErrorKind::Network => {
+                    let state = hosts.entry(host).or_default();
+                    state.failures += 1;
+                    state.reason = error.to_string();
+                    if state.failures >= self.threshold {
+                        state.opened_at = Some(Instant::now());
+                    }
+                }
+                Err(_) => {}
+                Ok(_) => {
+                    hosts.remove(&host);
+                }
+            }
+        }
+        retrieved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{under_prefix, Allowlist, Documents, FailureCache, Next, SharedCache};
+    use crate::{Error, JsonRef};
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use url::Url;
+
+    #[test]
+    fn layers_in_order() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let mut jsonref = JsonRef::new();
+        let first = seen.clone();
+        jsonref.add_layer(move |url: &Url, next: Next| {
+            first.lock().unwrap().push(url.to_string());
+            next.run(&Url::parse(&url.as_str().replace("old.example.com", "example.com")).unwrap())
+        });
+        let second = seen.clone();
+        jsonref.add_layer(move |url: &Url, _: Next| {
+            second.lock().unwrap().push(url.to_string());
+            Ok(json!({"type": "string"}))
+        });
+        jsonref.add_layer(Allowlist::new(&["nothing"]));
+
+        let mut input = json!({"$ref": "https://old.example.com/name.json"});
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(input, json!({"type": "string"}));
+        assert_eq!(*seen.lock().unwrap(), ["https://old.example.com/name.json", "https://example.com/name.json"]);
+    }
+
+    #[test]
+    fn allowlist_prefixes() {
+        let allowed = |prefix: &str, url: &str| under_prefix(&Url::parse(url).unwrap(), prefix);
+
+        assert!(allowed("https://example.com", "https://example.com/a.json"));
+        assert!(!allowed("https://example.com", "https://example.com.evil.org/a.json"));
+        assert!(!allowed("https://example.com", "https://example.com:8443/a.json"));
+        assert!(!allowed("https://example.com", "http://example.com/a.json"));
+        assert!(allowed("https://example.com/schemas", "https://example.com/schemas/a.json"));
+        assert!(!allowed("https://example.com/schemas", "https://example.com/schemas-evil/a.json"));
+        assert!(allowed("https://example.com/schemas/", "https://example.com/schemas/a.json"));
+        assert!(!allowed("nothing", "https://example.com/a.json"));
+    }
+
+    #[test]
+    fn deref_url_through_layers() {
+        let mut jsonref = JsonRef::new();
+        jsonref.add_layer(Documents::new([(
+            "https://example.com/root.json",
+            json!({"$defs": {"a": {"type": "string"}}, "properties": {"a": {"$ref": "#/$defs/a"}}}),
+        )]));
+
+        let output = jsonref.deref_url("https://example.com/root.json").unwrap();
+        assert_eq!(output["properties"]["a"], json!({"type": "string"}));
+
+        let base = std::fs::canonicalize("fixtures/nested_relative/base.json").unwrap();
+        let output = jsonref.deref_url(&format!("file://{}", base.to_string_lossy())).unwrap();
+        assert_eq!(output["properties"]["prop3"]["title"], "title from url");
+    }
+
+    #[test]
+    fn shared_cache_across_threads() {
+        let cache = SharedCache::new();
+        let workers: Vec<_> = (0..8)
+            .map(|worker| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    for index in 0..50 {
+                        cache.insert(&format!("https://example.com/{}/{}.json", worker, index), json!(index));
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        assert_eq!(cache.len(), 400);
+        assert_eq!(cache.get("https://example.com/3/7.json"), Some(json!(7)));
+        cache.remove("https://example.com/3/7.json");
+        assert_eq!(cache.get("https://example.com/3/7.json"), None);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn shared_cache_survives_poisoned_shards() {
+        let cache = SharedCache::new();
+        let url = "https://example.com/name.json";
+        cache.insert(url, json!({"type": "string"}));
+
+        let poisoner = cache.clone();
+        let panicked = std::thread::spawn(move || {
+            let _documents = poisoner.shard(url).write().unwrap();
+            panic!("poison the shard");
+        })
+        .join();
+        assert!(panicked.is_err());
+        assert!(cache.shard(url).is_poisoned());
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                std::thread::spawn(move || cache.get(url))
+            })
+            .collect();
+        for reader in readers {
+            assert_eq!(reader.join().unwrap(), Some(json!({"type": "string"})));
+        }
+        cache.insert(url, json!({"type": "integer"}));
+        assert_eq!(cache.get(url), Some(json!({"type": "integer"})));
+        assert_eq!(cache.len(), 1);
+        cache.remove(url);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn failures_expire() {
+        let attempts = Arc::new(Mutex::new(0));
+        let mut jsonref = JsonRef::new();
+        jsonref.add_layer(FailureCache::new(Duration::from_millis(100)));
+        let counted = attempts.clone();
+        jsonref.add_layer(move |url: &Url, _: Next| {
+            let mut attempts = counted.lock().unwrap();
+            *attempts += 1;
+            match *attempts {
+                1 => Err(Error::UnsupportedUrl {url: url.to_string(), reason: "down".to_owned()}),
+                _ => Ok(json!({"type": "string"})),
+            }
+        });
+
+        let mut input = json!({"$ref": "https://example.com/name.json"});
+        assert!(matches!(jsonref.deref_value(&mut input.clone()), Err(Error::UnsupportedUrl { .. })));
+        assert!(matches!(jsonref.deref_value(&mut input.clone()), Err(Error::RecentlyFailed { .. })));
+        assert_eq!(*attempts.lock().unwrap(), 1);
+
+        thread::sleep(Duration::from_millis(150));
+        jsonref.deref_value(&mut input).unwrap();
+        assert_eq!(input, json!({"type": "string"}));
+        assert_eq!(*attempts.lock().unwrap(), 2);
+    }
+}
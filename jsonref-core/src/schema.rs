@@ -0,0 +1,285 @@
+//! Knowledge of which JSON Schema keywords contain subschemas, used to walk schemas without
+//! mistaking property names or data for keywords.
+
+use crate::escape_pointer;
+use serde_json::Value;
+
+/// Keywords whose value is an object mapping names to subschemas.
+pub(crate) const SCHEMA_MAP_KEYWORDS: [&str; 5] = [
+    "properties",
+    "patternProperties",
+    "$defs",
+    "definitions",
+    "dependentSchemas",
+];
+
+/// Keywords whose value is a single subschema.
+pub(crate) const SCHEMA_KEYWORDS: [&str; 12] = [
+    "additionalProperties",
+    "additionalItems",
+    "contains",
+    "not",
+    "if",
+    "then",
+    "else",
+    "propertyNames",
+    "unevaluatedItems",
+    "unevaluatedProperties",
+    "contentSchema",
+    "items",
+];
+
+/// Keywords whose value is an array of subschemas. `items` may also be an array in older drafts.
+pub(crate) const SCHEMA_ARRAY_KEYWORDS: [&str; 5] = ["allOf", "anyOf", "oneOf", "prefixItems", "items"];
+
+/// The `$schema` URIs of the standard dialects, whose vocabularies need no checking.
+pub(crate) const STANDARD_DIALECTS: [&str; 5] = [
+    "https://json-schema.org/draft/2020-12/schema",
+    "https://json-schema.org/draft/2019-09/schema",
+    "http://json-schema.org/draft-07/schema",
+    "http://json-schema.org/draft-06/schema",
+    "http://json-schema.org/draft-04/schema",
+];
+
+/// The vocabularies of drafts 2019-09 and 2020-12. None of them changes how `$ref` resolves,
+/// though `$recursiveRef` and `$dynamicRef` are not followed.
+pub(crate) const STANDARD_VOCABULARIES: [&str; 14] = [
+    "https://json-schema.org/draft/2020-12/vocab/core",
+    "https://json-schema.org/draft/2020-12/vocab/applicator",
+    "https://json-schema.org/draft/2020-12/vocab/unevaluated",
+    "https://json-schema.org/draft/2020-12/vocab/validation",
+    "https://json-schema.org/draft/2020-12/vocab/meta-data",
+    "https://json-schema.org/draft/2020-12/vocab/format-annotation",
+    "https://json-schema.org/draft/2020-12/vocab/format-assertion",
+    "https://json-schema.org/draft/2020-12/vocab/content",
+    "https://json-schema.org/draft/2019-09/vocab/core",
+    "https://json-schema.org/draft/2019-09/vocab/applicator",
+    "https://json-schema.org/draft/2019-09/vocab/validation",
+    "https://json-schema.org/draft/2019-09/vocab/meta-data",
+    "https://json-schema.org/draft/2019-09/vocab/format",
+    "https://json-schema.org/draft/2019-09/vocab/content",
+];
+
+/// Which of the two keywords holding reusable definitions a schema should use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionsKeyword {
+    /// `$defs`, used since draft 2019-09.
+    Defs,
+    /// `definitions`, used up to draft-07.
+    Definitions,
+}
+
+impl DefinitionsKeyword {
+    /// The keyword as it appears in a schema.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DefinitionsKeyword::Defs => "$defs",
+            DefinitionsKeyword::Definitions => "definitions",
+        }
+    }
+
+    fn other(&self) -> DefinitionsKeyword {
+        match self {
+            DefinitionsKeyword::Defs => DefinitionsKeyword::Definitions,
+            DefinitionsKeyword::Definitions => DefinitionsKeyword::Defs,
+        }
+    }
+}
+
+/// How the value of a keyword holds subschemas.
+#[derive(PartialEq)]
+enum Holds {
+    Map,
+    Array,
+    One,
+    Nothing,
+}
+
+fn holds(key: &str, value: &Value) -> Holds {
+    if SCHEMA_MAP_KEYWORDS.contains(&key) {
+        if value.is_object() {
+            Holds::Map
+        } else {
+            Holds::Nothing
+        }
+    } else if SCHEMA_ARRAY_KEYWORDS.contains(&key) && value.is_array() {
+        Holds::Array
+    } else if SCHEMA_KEYWORDS.contains(&key) {
+        Holds::One
+    } else {
+        Holds::Nothing
+    }
+}
+
+/// Whether the value of the keyword `key` holds subschemas.
+pub(crate) fn holds_subschemas(key: &str, value: &Value) -> bool {
+    holds(key, value) != Holds::Nothing
+}
+
+/// The direct subschemas of `schema`, each with the JSON pointer to it from `schema`, following
+/// only keywords known to contain subschemas.
+pub(crate) fn children(schema: &Value) -> Vec<(String, &Value)> {
+    let mut found = vec![];
+    for (key, child) in schema.as_object().into_iter().flatten() {
+        let key_pointer = format!("/{}", escape_pointer(key));
+        match holds(key, child) {
+            Holds::Map => {
+                for (name, subschema) in child.as_object().into_iter().flatten() {
+                    found.push((format!("{}/{}", key_pointer, escape_pointer(name)), subschema));
+                }
+            }
+            Holds::Array => {
+                for (index, subschema) in child.as_array().into_iter().flatten().enumerate() {
+                    found.push((format!("{}/{}", key_pointer, index), subschema));
+                }
+            }
+            Holds::One => found.push((key_pointer, child)),
+            Holds::Nothing => {}
+        }
+    }
+    found
+}
+
+/// [`children`], mutably.
+pub(crate) fn children_mut(schema: &mut Value) -> Vec<(String, &mut Value)> {
+    let mut found = vec![];
+    for (key, child) in schema.as_object_mut().into_iter().flatten() {
+        let key_pointer = format!("/{}", escape_pointer(key));
+        match holds(key, child) {
+            Holds::Map => {
+                for (name, subschema) in child.as_object_mut().into_iter().flatten() {
+                    found.push((format!("{}/{}", key_pointer, escape_pointer(name)), subschema));
+                }
+            }
+            Holds::Array => {
+                for (index, subschema) in child.as_array_mut().into_iter().flatten().enumerate() {
+                    found.push((format!("{}/{}", key_pointer, index), subschema));
+                }
+            }
+            Holds::One => found.push((key_pointer, child)),
+            Holds::Nothing => {}
+        }
+    }
+    found
+}
+
+/// The keyword tokens of `pointer`, a pointer to a subschema from the walks here, leaving out the
+/// property names and indexes between them.
+pub(crate) fn pointer_keywords(pointer: &str) -> Vec<&str> {
+    let mut keywords = vec![];
+    let mut tokens = pointer.split('/').skip(1).peekable();
+    while let Some(keyword) = tokens.next() {
+        keywords.push(keyword);
+        let next_is_index = tokens.peek().is_some_and(|next| next.parse::<usize>().is_ok());
+        if SCHEMA_MAP_KEYWORDS.contains(&keyword) || SCHEMA_ARRAY_KEYWORDS.contains(&keyword) && next_is_index {
+            tokens.next();
+        }
+    }
+    keywords
+}
+
+/// Call `visit` with the pointer of every schema in `schema`, parents before children, following
+/// only keywords known to contain subschemas.
+pub(crate) fn walk_schemas_mut(schema: &mut Value, pointer: &str, visit: &mut dyn FnMut(&str, &mut Value)) {
+    visit(pointer, schema);
+    for (child_pointer, subschema) in children_mut(schema) {
+        walk_schemas_mut(subschema, &format!("{}{}", pointer, child_pointer), visit);
+    }
+}
+
+/// Rename every `definitions`/`$defs` keyword in `schema` to `keyword`, and rewrite the pointers
+/// of `$ref`s in it to match.
+pub(crate) fn normalize_definitions(schema: &mut Value, keyword: DefinitionsKeyword) {
+    let from = keyword.other().as_str();
+    let to = keyword.as_str();
+    walk_schemas_mut(schema, "", &mut |_, subschema| {
+        if let Some(obj) = subschema.as_object_mut() {
+            if let Some(definitions) = obj.remove(from) {
+                match obj.get_mut(to).and_then(|existing| existing.as_object_mut()) {
+                    Some(existing) => {
+                        if let Value::Object(definitions) = definitions {
+                            for (name, definition) in definitions {
+                                existing.entry(name).or_insert(definition);
+                            }
+                        }
+                    }
+                    None => {
+                        obj.insert(to.to_owned(), definitions);
+                    }
+                }
+            }
+            if let Some(Value::String(reference)) = obj.get_mut("$ref") {
+                if let Some((document, fragment)) = reference.split_once('#') {
+                    *reference = format!("{}#{}", document, rename_in_pointer(fragment, keyword));
+                }
+            }
+        }
+    });
+}
+
+/// Rename the `definitions`/`$defs` keyword tokens of a JSON pointer into a schema to `keyword`,
+/// leaving property names and other data untouched.
+pub(crate) fn rename_in_pointer(pointer: &str, keyword: DefinitionsKeyword) -> String {
+    let from = keyword.other().as_str();
+    // Whether the next token is a keyword of a schema, rather than a name or an index.
+    let mut at_keyword = true;
+    // Whether the pointer has left known keywords, after which nothing is renamed.
+    let mut unknown = false;
+    let mut tokens = vec![];
+    for token in pointer.split('/') {
+        if tokens.is_empty() || unknown {
+            tokens.push(token.to_owned());
+            continue;
+        }
+        if !at_keyword {
+            at_keyword = true;
+            tokens.push(token.to_owned());
+            continue;
+        }
+        if SCHEMA_MAP_KEYWORDS.contains(&token) || SCHEMA_ARRAY_KEYWORDS.contains(&token) {
+            at_keyword = false;
+        } else if !SCHEMA_KEYWORDS.contains(&token) {
+            unknown = true;
+        }
+        if token == from {
+            tokens.push(keyword.as_str().to_owned());
+        } else {
+            tokens.push(token.to_owned());
+        }
+    }
+    tokens.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_definitions, rename_in_pointer, DefinitionsKeyword};
+    use serde_json::json;
+
+    #[test]
+    fn normalize_to_defs() {
+        let mut schema = json!(
+            {"definitions": {"a": {"properties": {"definitions": {"$ref": "#/definitions/b"}}},
+                             "b": {"definitions": {"c": {}}}},
+             "properties": {"definitions": {"$ref": "other.json#/definitions/b/definitions/c"}}}
+        );
+
+        normalize_definitions(&mut schema, DefinitionsKeyword::Defs);
+
+        assert_eq!(
+            schema,
+            json!(
+                {"$defs": {"a": {"properties": {"definitions": {"$ref": "#/$defs/b"}}},
+                           "b": {"$defs": {"c": {}}}},
+                 "properties": {"definitions": {"$ref": "other.json#/$defs/b/$defs/c"}}}
+            )
+        );
+        assert_eq!(
+            rename_in_pointer("/properties/definitions/definitions/a", DefinitionsKeyword::Defs),
+            "/properties/definitions/$defs/a"
+        );
+        assert_eq!(
+            rename_in_pointer("/paths/definitions", DefinitionsKeyword::Defs),
+            "/paths/definitions"
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! A JSON tree in which identical subtrees share storage, for keeping dereferenced output where
+//! one large definition is inlined in many places.
+//!
+//! Dereferencing still builds a plain [`Value`] first; converting it with [`Sharer::share`] keeps
+//! one copy of each distinct subtree, so the memory held afterwards is that of the distinct parts
+//! only. A [`Sharer`] used for several outputs also shares subtrees between them.
+//!
+//! ```
+//! use jsonref::share::Sharer;
+//! use jsonref::JsonRef;
+//! use serde_json::json;
+//!
+//! let mut output = json!(
+//!     {"$defs": {"address": {"properties": {"street": {"type": "string"}}}},
+//!      "properties": {"home": {"$ref": "#/$defs/address"}, "work": {"$ref": "#/$defs/address"}}}
+//! );
+//! JsonRef::new().deref_value(&mut output).unwrap();
+//!
+//! let mut sharer = Sharer::new();
+//! let shared = sharer.share(&output);
+//!
+//! let home = shared.get("properties").and_then(|properties| properties.get("home")).unwrap();
+//! let work = shared.get("properties").and_then(|properties| properties.get("work")).unwrap();
+//! assert!(home.ptr_eq(work));
+//! assert_eq!(shared.to_value(), output);
+//! ```
+
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use serde_json::{Map, Number, Value};
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+#[derive(Debug)]
+enum Node {
+    Null,
+    Bool(bool),
+    Number(Number),
+    String(String),
+    Array(Vec<SharedValue>),
+    Object(Vec<(String, SharedValue)>),
+}
+
+/// An immutable JSON value whose arrays and objects hold their items by reference counted
+/// pointer, so that equal items can be one and the same. Cloning is cheap.
+#[derive(Debug, Clone)]
+pub struct SharedValue(Arc<Node>);
+
+impl SharedValue {
+    /// The value of `key`, if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&SharedValue> {
+        match &*self.0 {
+            Node::Object(entries) => entries.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// The item at `index`, if this is an array that long.
+    pub fn index(&self, index: usize) -> Option<&SharedValue> {
+        match &*self.0 {
+            Node::Array(items) => items.get(index),
+            _ => None,
+        }
+    }
+
+    /// Whether `self` and `other` are stored in the same place, which for values from the same
+    /// [`Sharer`] is whenever they are equal.
+    pub fn ptr_eq(&self, other: &SharedValue) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// A plain, unshared copy.
+    pub fn to_value(&self) -> Value {
+        match &*self.0 {
+            Node::Null => Value::Null,
+            Node::Bool(boolean) => Value::Bool(*boolean),
+            Node::Number(number) => Value::Number(number.clone()),
+            Node::String(string) => Value::String(string.clone()),
+            Node::Array(items) => Value::Array(items.iter().map(SharedValue::to_value).collect()),
+            Node::Object(entries) => Value::Object(
+                entries
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.to_value()))
+                    .collect::<Map<String, Value>>(),
+            ),
+        }
+    }
+}
+
+impl PartialEq for SharedValue {
+    fn eq(&self, other: &SharedValue) -> bool {
+        if self.ptr_eq(other) {
+            return true;
+        }
+        match (&*self.0, &*other.0) {
+            (Node::Null, Node::Null) => true,
+            (Node::Bool(a), Node::Bool(b)) => a == b,
+            (Node::Number(a), Node::Number(b)) => a == b,
+            (Node::String(a), Node::String(b)) => a == b,
+            (Node::Array(a), Node::Array(b)) => a == b,
+            (Node::Object(a), Node::Object(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Serialize for SharedValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &*self.0 {
+            Node::Null => serializer.serialize_unit(),
+            Node::Bool(boolean) => serializer.serialize_bool(*boolean),
+            Node::Number(number) => number.serialize(serializer),
+            Node::String(string) => serializer.serialize_str(string),
+            Node::Array(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            Node::Object(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (name, value) in entries {
+                    map.serialize_entry(name, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+/// A node held by a [`Sharer`], compared by the identity of its children. As children are shared
+/// before their parents, two nodes are equal exactly when they have the same children.
+struct Interned(SharedValue);
+
+impl PartialEq for Interned {
+    fn eq(&self, other: &Interned) -> bool {
+        match (&*self.0 .0, &*other.0 .0) {
+            (Node::Array(a), Node::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.ptr_eq(b))
+            }
+            (Node::Object(a), Node::Object(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|((a_name, a), (b_name, b))| a_name == b_name && a.ptr_eq(b))
+            }
+            _ => self.0 == other.0,
+        }
+    }
+}
+
+impl Eq for Interned {}
+
+impl Hash for Interned {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match &*self.0 .0 {
+            Node::Null => 0.hash(state),
+            Node::Bool(boolean) => boolean.hash(state),
+            Node::Number(number) => number.hash(state),
+            Node::String(string) => string.hash(state),
+            Node::Array(items) => {
+                for item in items {
+                    Arc::as_ptr(&item.0).hash(state);
+                }
+            }
+            Node::Object(entries) => {
+                for (name, value) in entries {
+                    name.hash(state);
+                    Arc::as_ptr(&value.0).hash(state);
+                }
+            }
+        }
+    }
+}
+
+/// Converts [`Value`]s into [`SharedValue`]s, keeping one copy of each distinct subtree of every
+/// value it has converted.
+#[derive(Default)]
+pub struct Sharer {
+    nodes: HashSet<Interned>,
+}
+
+impl Sharer {
+    /// Create a sharer holding nothing yet.
+    pub fn new() -> Sharer {
+        Sharer::default()
+    }
+
+    /// `value` with its identical subtrees, and any identical to ones shared before, stored once.
+    pub fn share(&mut self, value: &Value) -> SharedValue {
+        let node = match value {
+            Value::Null => Node::Null,
+            Value::Bool(boolean) => Node::Bool(*boolean),
+            Value::Number(number) => Node::Number(number.clone()),
+            Value::String(string) => Node::String(string.clone()),
+            Value::Array(items) => Node::Array(items.iter().map(|item| self.share(item)).collect()),
+            Value::Object(entries) => Node::Object(
+                entries
+                    .iter()
+                    .map(|(name, value)| (name.clone(), self.share(value)))
+                    .collect(),
+            ),
+        };
+        let interned = Interned(SharedValue(Arc::new(node)));
+        if let Some(existing) = self.nodes.get(&interned) {
+            return existing.0.clone();
+        }
+        let shared = interned.0.clone();
+        self.nodes.insert(interned);
+        shared
+    }
+
+    /// The number of distinct subtrees held.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether nothing has been shared yet.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sharer;
+    use serde_json::json;
+
+    #[test]
+    fn identical_subtrees_stored_once() {
+        let address = json!({"properties": {"street": {"type": "string"}, "city": {"type": "string"}}});
+        let output = json!({"items": [address.clone(), address.clone(), {"type": "string"}], "const": [1, 1.5, null, true]});
+
+        let mut sharer = Sharer::new();
+        let shared = sharer.share(&output);
+
+        let items = shared.get("items").unwrap();
+        assert!(items.index(0).unwrap().ptr_eq(items.index(1).unwrap()));
+        let city = items.index(0).unwrap().get("properties").unwrap().get("city").unwrap();
+        assert!(items.index(2).unwrap().ptr_eq(city));
+        assert_eq!(serde_json::to_string(&shared).unwrap(), serde_json::to_string(&output).unwrap());
+        assert_eq!(shared.to_value(), output);
+
+        let distinct = sharer.len();
+        let again = sharer.share(&json!({"other": address}));
+        assert_eq!(sharer.len(), distinct + 1);
+        assert!(again.get("other").unwrap().ptr_eq(items.index(0).unwrap()));
+    }
+}
@@ -0,0 +1,231 @@
+//! AWS Signature Version 4 signing of requests, for schemas on S3 or behind API Gateway that
+//! only answer signed requests. Needs the `ring` feature.
+
+use crate::http::{percent_decode, RequestSigner};
+use crate::Result;
+use ring::{digest, hmac};
+use std::env;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// Signs requests with AWS credentials, for use with
+/// [`HostOptions::signer`](crate::http::HostOptions::signer).
+///
+/// ```no_run
+/// use jsonref::http::HostOptions;
+/// use jsonref::sigv4::SigV4;
+/// use jsonref::JsonRef;
+/// use serde_json::json;
+///
+/// let signer = SigV4::from_env("eu-west-1", "s3").expect("AWS credentials in the environment");
+/// let mut jsonref = JsonRef::new();
+/// jsonref.set_host_options("schemas.s3.eu-west-1.amazonaws.com", HostOptions::new().signer(signer));
+///
+/// let mut input = json!({"$ref": "https://schemas.s3.eu-west-1.amazonaws.com/order.json"});
+/// jsonref.deref_value(&mut input).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct SigV4 {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    region: String,
+    service: String,
+}
+
+impl std::fmt::Debug for SigV4 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SigV4")
+            .field("access_key_id", &self.access_key_id)
+            .field("region", &self.region)
+            .field("service", &self.service)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The SHA-256 of an empty payload, hex encoded.
+const EMPTY_PAYLOAD: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+impl SigV4 {
+    /// Sign for `service`, such as `s3` or `execute-api`, in `region`.
+    pub fn new(access_key_id: &str, secret_access_key: &str, region: &str, service: &str) -> SigV4 {
+        SigV4 {
+            access_key_id: access_key_id.to_owned(),
+            secret_access_key: secret_access_key.to_owned(),
+            session_token: None,
+            region: region.to_owned(),
+            service: service.to_owned(),
+        }
+    }
+
+    /// Sign with the credentials in `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and, if set,
+    /// `AWS_SESSION_TOKEN`, `None` if either of the first two is not set.
+    pub fn from_env(region: &str, service: &str) -> Option<SigV4> {
+        let mut signer = SigV4::new(
+            &env::var("AWS_ACCESS_KEY_ID").ok()?,
+            &env::var("AWS_SECRET_ACCESS_KEY").ok()?,
+            region,
+            service,
+        );
+        signer.session_token = env::var("AWS_SESSION_TOKEN").ok();
+        Some(signer)
+    }
+
+    /// Send the session token of temporary credentials.
+    pub fn session_token(mut self, session_token: &str) -> SigV4 {
+        self.session_token = Some(session_token.to_owned());
+        self
+    }
+
+    /// The headers signing a `GET` of `url` at `amz_date`, as `20150830T123600Z`.
+    fn headers_at(&self, url: &Url, amz_date: &str) -> Vec<(String, String)> {
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_owned(),
+        };
+        let mut headers = vec![("host".to_owned(), host), ("x-amz-date".to_owned(), amz_date.to_owned())];
+        // S3 wants the payload hash in a header of its own.
+        if self.service == "s3" {
+            headers.push(("x-amz-content-sha256".to_owned(), EMPTY_PAYLOAD.to_owned()));
+        }
+        if let Some(session_token) = &self.session_token {
+            headers.push(("x-amz-security-token".to_owned(), session_token.clone()));
+        }
+        headers.sort();
+
+        let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+        let signed_headers = headers.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(";");
+        let canonical_request = format!(
+            "GET\n{}\n{}\n{}\n{}\n{}",
+            self.canonical_path(url),
+            canonical_query(url),
+            canonical_headers,
+            signed_headers,
+            EMPTY_PAYLOAD
+        );
+
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(digest::digest(&digest::SHA256, canonical_request.as_bytes()).as_ref())
+        );
+        let mut key = format!("AWS4{}", self.secret_access_key).into_bytes();
+        for part in [date, &self.region, &self.service, "aws4_request"] {
+            key = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &key), part.as_bytes()).as_ref().to_vec();
+        }
+        let signature = hex(hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &key), string_to_sign.as_bytes()).as_ref());
+
+        headers.retain(|(name, _)| name != "host");
+        headers.push((
+            "authorization".to_owned(),
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key_id, scope, signed_headers, signature
+            ),
+        ));
+        headers
+    }
+
+    /// The path of `url` encoded as AWS expects: once for S3, twice for other services.
+    fn canonical_path(&self, url: &Url) -> String {
+        let path = url
+            .path()
+            .split('/')
+            .map(|segment| encode(&percent_decode(segment)))
+            .collect::<Vec<_>>()
+            .join("/");
+        if self.service == "s3" {
+            path
+        } else {
+            path.replace('%', "%25")
+        }
+    }
+}
+
+impl RequestSigner for SigV4 {
+    fn sign(&self, url: &Url) -> Result<Vec<(String, String)>> {
+        Ok(self.headers_at(url, &amz_date(SystemTime::now())))
+    }
+}
+
+/// The query of `url`, sorted and encoded as AWS expects.
+fn canonical_query(url: &Url) -> String {
+    let mut pairs: Vec<(String, String)> = url.query_pairs().map(|(name, value)| (encode(&name), encode(&value))).collect();
+    pairs.sort();
+    pairs.iter().map(|(name, value)| format!("{}={}", name, value)).collect::<Vec<_>>().join("&")
+}
+
+/// Percent encode everything but unreserved characters.
+fn encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+    encoded
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// `time` as `20150830T123600Z`.
+fn amz_date(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default();
+    let (days, seconds) = ((seconds / 86400) as i64, seconds % 86400);
+    // Civil date from days since 1970-01-01, after Howard Hinnant's `civil_from_days`.
+    let shifted = days + 719468;
+    let era = shifted.div_euclid(146097);
+    let day_of_era = shifted.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{amz_date, percent_decode, SigV4};
+    use std::time::{Duration, UNIX_EPOCH};
+    use url::Url;
+
+    #[test]
+    fn aws_test_suite_get_vanilla() {
+        assert_eq!(amz_date(UNIX_EPOCH + Duration::from_secs(1440938160)), "20150830T123600Z");
+        assert_eq!(percent_decode("a%20b&c%2"), "a b&c%2");
+
+        let signer = SigV4::new("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "us-east-1", "service");
+        let headers = signer.headers_at(&Url::parse("https://example.amazonaws.com/").unwrap(), "20150830T123600Z");
+        assert_eq!(
+            headers,
+            [
+                ("x-amz-date".to_owned(), "20150830T123600Z".to_owned()),
+                (
+                    "authorization".to_owned(),
+                    "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+                     SignedHeaders=host;x-amz-date, \
+                     Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+                        .to_owned()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Finding where a JSON pointer points in the source text of a document, to report errors by
+//! line and column.
+//!
+//! `serde_json` keeps no positions, so the text is scanned again, only as far as the pointer
+//! needs, when a location is asked for.
+
+use std::ops::Range;
+
+/// A position in source text. Both are counted from 1 and the column is in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// The location of the value at `pointer` in the JSON text `source`, or `None` if the pointer
+/// does not point at anything or the text is not valid JSON on the way there.
+///
+/// ```
+/// use jsonref::spans::{locate, Location};
+///
+/// let source = r##"{
+///     "properties": {
+///         "name": {"$ref": "#/$defs/name"}
+///     }
+/// }"##;
+///
+/// assert_eq!(locate(source, "/properties/name/$ref"), Some(Location { line: 3, column: 26 }));
+/// assert_eq!(locate(source, "/properties/age"), None);
+/// ```
+pub fn locate(source: &str, pointer: &str) -> Option<Location> {
+    let before = &source[..span(source, pointer)?.start];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    Some(Location {
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count() + 1,
+    })
+}
+
+/// The byte range of the value at `pointer` in the JSON text `source`, see [`locate`].
+///
+/// ```
+/// use jsonref::spans::span;
+///
+/// let source = r#"{"name": {"type": "string"}}"#;
+///
+/// assert_eq!(&source[span(source, "/name").unwrap()], r#"{"type": "string"}"#);
+/// ```
+pub fn span(source: &str, pointer: &str) -> Option<Range<usize>> {
+    let tokens: Vec<String> = match pointer {
+        "" => vec![],
+        pointer => pointer
+            .strip_prefix('/')?
+            .split('/')
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect(),
+    };
+
+    let mut scanner = Scanner { bytes: source.as_bytes(), pos: 0 };
+    for token in tokens {
+        scanner.skip_whitespace();
+        match scanner.next()? {
+            b'{' => loop {
+                scanner.skip_whitespace();
+                let key = scanner.string()?;
+                scanner.skip_whitespace();
+                scanner.expect(b':')?;
+                if key == token {
+                    break;
+                }
+                scanner.skip_value()?;
+                scanner.skip_whitespace();
+                scanner.expect(b',')?;
+            },
+            b'[' => {
+                let index: usize = token.parse().ok()?;
+                for _ in 0..index {
+                    scanner.skip_value()?;
+                    scanner.skip_whitespace();
+                    scanner.expect(b',')?;
+                }
+            }
+            _ => return None,
+        }
+    }
+    scanner.skip_whitespace();
+    if matches!(scanner.peek()?, b'}' | b']' | b',') {
+        return None;
+    }
+    let start = scanner.pos;
+    scanner.skip_value()?;
+    Some(start..scanner.pos)
+}
+
+struct Scanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        (self.next()? == byte).then_some(())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Read a string, returning it with its escapes decoded.
+    fn string(&mut self) -> Option<String> {
+        self.expect(b'"')?;
+        let mut decoded: Vec<u16> = vec![];
+        let mut text = String::new();
+        loop {
+            match self.next()? {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = match self.next()? {
+                        b'u' => {
+                            let hex = std::str::from_utf8(self.bytes.get(self.pos..self.pos + 4)?).ok()?;
+                            self.pos += 4;
+                            decoded.push(u16::from_str_radix(hex, 16).ok()?);
+                            continue;
+                        }
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        other => other as char,
+                    };
+                    text.push_str(&String::from_utf16(&decoded).ok()?);
+                    decoded.clear();
+                    text.push(escaped);
+                }
+                _ => {
+                    text.push_str(&String::from_utf16(&decoded).ok()?);
+                    decoded.clear();
+                    // Copy the whole of a multi-byte character.
+                    let start = self.pos - 1;
+                    while self.peek().is_some_and(|byte| byte & 0xC0 == 0x80) {
+                        self.pos += 1;
+                    }
+                    text.push_str(std::str::from_utf8(&self.bytes[start..self.pos]).ok()?);
+                }
+            }
+        }
+        text.push_str(&String::from_utf16(&decoded).ok()?);
+        Some(text)
+    }
+
+    fn skip_value(&mut self) -> Option<()> {
+        self.skip_whitespace();
+        match self.peek()? {
+            b'"' => {
+                self.string()?;
+            }
+            b'{' | b'[' => {
+                let mut depth = 0;
+                loop {
+                    match self.peek()? {
+                        b'"' => {
+                            self.string()?;
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => depth -= 1,
+                        _ => {}
+                    }
+                    self.pos += 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+            _ => {
+                while !matches!(self.peek()?, b',' | b'}' | b']' | b' ' | b'\t' | b'\n' | b'\r') {
+                    self.pos += 1;
+                }
+            }
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{locate, Location};
+
+    #[test]
+    fn escapes_and_arrays() {
+        let source = "{\"a\\/b\": [1, {\"x\": \"}\"}, {\"é\\u00e9\": true}],\n \"c~d\": null}";
+        assert_eq!(locate(source, ""), Some(Location { line: 1, column: 1 }));
+        assert_eq!(locate(source, "/a~1b/1/x"), Some(Location { line: 1, column: 20 }));
+        assert_eq!(locate(source, "/a~1b/2/éé"), Some(Location { line: 1, column: 38 }));
+        assert_eq!(locate(source, "/c~0d"), Some(Location { line: 2, column: 9 }));
+        assert_eq!(locate(source, "/a~1b/3"), None);
+        assert_eq!(locate(source, "/c~0d/0"), None);
+    }
+}
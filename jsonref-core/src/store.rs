@@ -0,0 +1,101 @@
+//! The documents a [`JsonRef`](crate::JsonRef) has loaded, kept where the validators it hands out
+//! can read and add to them too.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Default)]
+struct Documents {
+    documents: HashMap<String, Arc<Value>>,
+    /// When each document that was fetched, rather than registered, was fetched.
+    fetched_at: HashMap<String, Instant>,
+}
+
+/// Documents by the URL they were loaded from, without a fragment.
+///
+/// A clone holds a copy of the documents, as a cloned `JsonRef` does, while [`Store::share`]
+/// gives a handle to the same documents.
+#[derive(Debug, Default)]
+pub(crate) struct Store(Arc<RwLock<Documents>>);
+
+impl Clone for Store {
+    fn clone(&self) -> Store {
+        Store(Arc::new(RwLock::new(self.read().clone())))
+    }
+}
+
+impl Store {
+    /// A handle to the same documents, seeing everything added through either.
+    #[cfg(feature = "jsonschema")]
+    pub(crate) fn share(&self) -> Store {
+        Store(self.0.clone())
+    }
+
+    pub(crate) fn get(&self, url: &str) -> Option<Arc<Value>> {
+        self.read().documents.get(url).cloned()
+    }
+
+    pub(crate) fn contains(&self, url: &str) -> bool {
+        self.read().documents.contains_key(url)
+    }
+
+    /// Every URL held, sorted.
+    pub(crate) fn urls(&self) -> Vec<String> {
+        let mut urls: Vec<String> = self.read().documents.keys().cloned().collect();
+        urls.sort_unstable();
+        urls
+    }
+
+    /// Every document held, sorted by URL.
+    pub(crate) fn entries(&self) -> Vec<(String, Arc<Value>)> {
+        let mut entries: Vec<(String, Arc<Value>)> = self
+            .read()
+            .documents
+            .iter()
+            .map(|(url, document)| (url.clone(), document.clone()))
+            .collect();
+        entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        entries
+    }
+
+    /// Hold `document` for `url`, without it ever expiring unless it was fetched before.
+    pub(crate) fn insert(&self, url: String, document: Value) {
+        self.write().documents.insert(url, Arc::new(document));
+    }
+
+    /// Hold `document` fetched from `url` now, returning it as held.
+    pub(crate) fn insert_fetched(&self, url: String, document: Value) -> Arc<Value> {
+        let document = Arc::new(document);
+        let mut documents = self.write();
+        documents.fetched_at.insert(url.clone(), Instant::now());
+        documents.documents.insert(url, document.clone());
+        document
+    }
+
+    /// Drop the fetched documents older than `ttl`.
+    pub(crate) fn expire(&self, ttl: Duration) {
+        let mut documents = self.write();
+        let expired: Vec<String> = documents
+            .fetched_at
+            .iter()
+            .filter(|(_, fetched_at)| fetched_at.elapsed() >= ttl)
+            .map(|(url, _)| url.clone())
+            .collect();
+        for url in expired {
+            documents.fetched_at.remove(&url);
+            documents.documents.remove(&url);
+        }
+    }
+
+    // A panic while the lock was held leaves no half-written entry behind, so the documents are
+    // still usable.
+    fn read(&self) -> RwLockReadGuard<'_, Documents> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Documents> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,115 @@
+//! Dereferencing progress as an async [`Stream`], for reporting it from async code such as a
+//! websocket handler.
+//!
+//! Dereferencing itself is blocking, so run it on a blocking thread, for example with tokio's
+//! `spawn_blocking`, and poll the stream from the async side. The stream ends once the
+//! [`JsonRef`] it came from, and every clone of it, has been dropped.
+//!
+//! ```
+//! use futures_core::Stream;
+//! use jsonref::stream::OwnedEvent;
+//! use jsonref::JsonRef;
+//! use serde_json::json;
+//!
+//! let mut jsonref = JsonRef::new();
+//! let events = jsonref.event_stream();
+//!
+//! let mut input = json!({"$defs": {"a": {}}, "properties": {"a": {"$ref": "#/$defs/a"}}});
+//! std::thread::spawn(move || jsonref.deref_value(&mut input).unwrap());
+//!
+//! // In async code: `while let Some(event) = events.next().await { ... }`
+//! # let mut events = Box::pin(events);
+//! # let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+//! # let mut replaced = vec![];
+//! # loop {
+//! #     match events.as_mut().poll_next(&mut context) {
+//! #         std::task::Poll::Ready(Some(OwnedEvent::Replacing(replacement))) => replaced.push(replacement.pointer),
+//! #         std::task::Poll::Ready(Some(_)) => {}
+//! #         std::task::Poll::Ready(None) => break,
+//! #         std::task::Poll::Pending => std::thread::yield_now(),
+//! #     }
+//! # }
+//! # assert_eq!(replaced, ["/properties/a"]);
+//! ```
+
+use crate::{Event, JsonRef, Replacement, Warning};
+use futures_channel::mpsc::{self, UnboundedReceiver};
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// An [`Event`] that owns what it refers to, so that it can be sent across threads.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OwnedEvent {
+    /// A document is about to be retrieved, as it was not already loaded.
+    Fetching { url: String },
+    /// A `$ref` is being replaced. Its target is dereferenced next.
+    Replacing(Replacement),
+    /// An issue was met, as also listed by [`JsonRef::warnings`].
+    Warning(Warning),
+}
+
+impl From<&Event<'_>> for OwnedEvent {
+    fn from(event: &Event<'_>) -> OwnedEvent {
+        match event {
+            Event::Fetching { url } => OwnedEvent::Fetching { url: url.to_string() },
+            Event::Replacing(replacement) => OwnedEvent::Replacing((*replacement).clone()),
+            Event::Warning(warning) => OwnedEvent::Warning((*warning).clone()),
+        }
+    }
+}
+
+/// The stream returned by [`JsonRef::event_stream`].
+#[derive(Debug)]
+pub struct EventStream {
+    receiver: UnboundedReceiver<OwnedEvent>,
+}
+
+impl Stream for EventStream {
+    type Item = OwnedEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<OwnedEvent>> {
+        Pin::new(&mut self.receiver).poll_next(context)
+    }
+}
+
+impl JsonRef {
+    /// Stream every [`Event`] from now on, replacing any handler set with
+    /// [`JsonRef::set_event_handler`]. See the [module docs](crate::stream).
+    pub fn event_stream(&mut self) -> EventStream {
+        let (sender, receiver) = mpsc::unbounded();
+        self.set_event_handler(move |event| {
+            // The stream being dropped only means nobody is listening any more.
+            let _ = sender.unbounded_send(OwnedEvent::from(event));
+        });
+        EventStream { receiver }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OwnedEvent;
+    use crate::JsonRef;
+    use futures_core::Stream;
+    use serde_json::json;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+
+    #[test]
+    fn stream_ends_with_jsonref() {
+        let mut jsonref = JsonRef::new();
+        let mut events = jsonref.event_stream();
+        let mut input = json!({"$defs": {"a": {}}, "properties": {"a": {"$ref": "#/$defs/a"}}});
+        jsonref.deref_value(&mut input).unwrap();
+
+        let mut context = Context::from_waker(Waker::noop());
+        match Pin::new(&mut events).poll_next(&mut context) {
+            Poll::Ready(Some(OwnedEvent::Replacing(replacement))) => assert_eq!(replacement.pointer, "/properties/a"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(Pin::new(&mut events).poll_next(&mut context).is_pending());
+
+        drop(jsonref);
+        assert_eq!(Pin::new(&mut events).poll_next(&mut context), Poll::Ready(None));
+    }
+}
@@ -0,0 +1,738 @@
+//! Transforms that can be applied to a schema once it has been dereferenced.
+
+use crate::escape_pointer;
+use crate::schema::{self, walk_schemas_mut, DefinitionsKeyword, SCHEMA_ARRAY_KEYWORDS, SCHEMA_KEYWORDS, SCHEMA_MAP_KEYWORDS};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// A pass over a dereferenced schema, added to a [`JsonRef`](crate::JsonRef) with
+/// [`JsonRef::add_pass`](crate::JsonRef::add_pass) to run after every run. The transforms of
+/// this module are available as passes, and closures taking the schema are passes too.
+pub trait Pass: Send + Sync {
+    fn run(&self, schema: &mut Value);
+}
+
+impl<F> Pass for F
+where
+    F: Fn(&mut Value) + Send + Sync,
+{
+    fn run(&self, schema: &mut Value) {
+        self(schema)
+    }
+}
+
+/// [`flatten_all_of`] as a [`Pass`].
+#[derive(Debug, Clone, Copy)]
+pub struct FlattenAllOf;
+
+impl Pass for FlattenAllOf {
+    fn run(&self, schema: &mut Value) {
+        flatten_all_of(schema);
+    }
+}
+
+/// [`strip_keywords`] as a [`Pass`].
+#[derive(Debug, Clone)]
+pub struct StripKeywords(pub Vec<String>);
+
+impl Pass for StripKeywords {
+    fn run(&self, schema: &mut Value) {
+        strip_keywords(schema, &self.0);
+    }
+}
+
+/// [`rereference`] as a [`Pass`], with its minimum size.
+#[derive(Debug, Clone, Copy)]
+pub struct Rereference(pub usize);
+
+impl Pass for Rereference {
+    fn run(&self, schema: &mut Value) {
+        rereference(schema, self.0);
+    }
+}
+
+/// [`migrate_to_2020_12`] as a [`Pass`].
+#[derive(Debug, Clone, Copy)]
+pub struct MigrateTo2020_12;
+
+impl Pass for MigrateTo2020_12 {
+    fn run(&self, schema: &mut Value) {
+        migrate_to_2020_12(schema);
+    }
+}
+
+/// Keywords that only annotate a schema. When merging, the first value found is kept instead of
+/// treating differing values as a conflict.
+const MERGE_ANNOTATIONS: [&str; 3] = ["title", "description", "$comment"];
+
+/// Keywords whose meaning depends on the other keywords of their own schema, so a schema using
+/// them cannot be merged with another that constrains anything.
+const MERGE_SCOPED: [&str; 4] = ["additionalProperties", "patternProperties", "unevaluatedProperties", "unevaluatedItems"];
+
+/// Merge every `allOf` made only of plain object schemas into the schema containing it,
+/// combining `properties` and `required`.
+///
+/// A property defined in more than one branch is combined in its own `allOf`, which is merged in
+/// turn. An `allOf` is left untouched when any branch contains a `$ref` or is not an object, or
+/// when two branches give different values for the same keyword. Keywords such as
+/// `additionalProperties` and `unevaluatedProperties` only see their own schema, so they conflict
+/// with any other constraint. The JSON pointers of the `allOf`s left untouched because of
+/// conflicting keywords are returned.
+///
+/// ```
+/// use serde_json::json;
+///
+/// let mut schema = json!(
+///     {"allOf": [{"properties": {"a": {"type": "string"}}, "required": ["a"]},
+///                {"properties": {"b": {"type": "number"}}, "required": ["b"]}]}
+/// );
+///
+/// let conflicts = jsonref::transform::flatten_all_of(&mut schema);
+///
+/// assert!(conflicts.is_empty());
+/// assert_eq!(schema, json!(
+///     {"properties": {"a": {"type": "string"}, "b": {"type": "number"}},
+///      "required": ["a", "b"]}
+/// ));
+/// ```
+pub fn flatten_all_of(schema: &mut Value) -> Vec<String> {
+    let mut conflicts = vec![];
+    flatten_at(schema, "", &mut conflicts);
+    conflicts
+}
+
+fn flatten_at(value: &mut Value, pointer: &str, conflicts: &mut Vec<String>) {
+    match value {
+        Value::Object(obj) => {
+            for (key, child) in obj.iter_mut() {
+                flatten_at(child, &format!("{}/{}", pointer, escape_pointer(key)), conflicts);
+            }
+        }
+        Value::Array(array) => {
+            for (index, child) in array.iter_mut().enumerate() {
+                flatten_at(child, &format!("{}/{}", pointer, index), conflicts);
+            }
+        }
+        _ => return,
+    }
+
+    let mergeable = match value.get("allOf") {
+        Some(Value::Array(branches)) => branches
+            .iter()
+            .all(|branch| branch.as_object().is_some_and(|obj| !obj.contains_key("$ref"))),
+        _ => false,
+    };
+    if !mergeable {
+        return;
+    }
+
+    let mut merged = value.as_object().cloned().unwrap_or_default();
+    let branches = merged.remove("allOf");
+    for branch in branches.iter().flat_map(|branches| branches.as_array()).flatten() {
+        if let Some(branch) = branch.as_object() {
+            if !merge_into(&mut merged, branch) {
+                conflicts.push(format!("{}/allOf", pointer));
+                return;
+            }
+        }
+    }
+
+    let mut merged = Value::Object(merged);
+    if let Some(Value::Object(properties)) = merged.get_mut("properties") {
+        for (key, property) in properties.iter_mut() {
+            if property.get("allOf").is_some() {
+                let property_pointer = format!("{}/properties/{}", pointer, escape_pointer(key));
+                flatten_at(property, &property_pointer, conflicts);
+            }
+        }
+    }
+    *value = merged;
+}
+
+/// Remove the given keywords, such as `description`, `examples`, `$comment` or `title`, from
+/// every schema in `schema`. Only keywords are removed, so properties that happen to share a
+/// name with one of them are kept.
+///
+/// ```
+/// use serde_json::json;
+///
+/// let mut schema = json!(
+///     {"description": "a person",
+///      "properties": {"description": {"type": "string", "description": "what they do"}}}
+/// );
+///
+/// jsonref::transform::strip_keywords(&mut schema, &["description"]);
+///
+/// assert_eq!(schema, json!({"properties": {"description": {"type": "string"}}}));
+/// ```
+pub fn strip_keywords<S: AsRef<str>>(schema: &mut Value, keywords: &[S]) {
+    walk_schemas_mut(schema, "", &mut |_, subschema| {
+        if let Some(obj) = subschema.as_object_mut() {
+            for keyword in keywords {
+                obj.remove(keyword.as_ref());
+            }
+        }
+    });
+}
+
+/// Sort the keys of every object in `value`, at any depth. Objects are already sorted unless
+/// `serde_json` is built with its `preserve_order` feature, which another crate in the build
+/// can turn on, so this gives the same serialized bytes either way.
+///
+/// ```
+/// use serde_json::json;
+///
+/// let mut value = json!({"type": "object", "properties": {"b": {}, "a": {"enum": [{"y": 1, "x": 2}]}}});
+///
+/// jsonref::transform::sort_keys(&mut value);
+///
+/// assert_eq!(value.to_string(), r#"{"properties":{"a":{"enum":[{"x":2,"y":1}]},"b":{}},"type":"object"}"#);
+/// ```
+pub fn sort_keys(value: &mut Value) {
+    match value {
+        Value::Object(obj) => {
+            let mut entries: Vec<(String, Value)> = std::mem::take(obj).into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (key, mut child) in entries {
+                sort_keys(&mut child);
+                obj.insert(key, child);
+            }
+        }
+        Value::Array(array) => array.iter_mut().for_each(sort_keys),
+        _ => {}
+    }
+}
+
+/// Factor subschemas that appear more than once back out into `$defs`, replacing every copy
+/// with a `$ref`, the reverse of inlining. Only subschemas whose serialized JSON is at least
+/// `min_size` bytes are considered, largest first. A subschema equal to an existing entry of the
+/// root `$defs` (or `definitions`) is replaced with a ref to that entry even if it appears only
+/// once. Returns the number of subschemas replaced.
+///
+/// ```
+/// use serde_json::json;
+///
+/// let address = json!({"title": "address", "properties": {"street": {"type": "string"}}});
+/// let mut schema = json!({"properties": {"home": address.clone(), "work": address.clone()}});
+///
+/// let replaced = jsonref::transform::rereference(&mut schema, 10);
+///
+/// assert_eq!(replaced, 2);
+/// assert_eq!(schema, json!(
+///     {"$defs": {"address": address},
+///      "properties": {"home": {"$ref": "#/$defs/address"}, "work": {"$ref": "#/$defs/address"}}}
+/// ));
+/// ```
+pub fn rereference(schema: &mut Value, min_size: usize) -> usize {
+    let defs_keyword = if schema.get("$defs").is_none() && schema.get("definitions").is_some() {
+        "definitions"
+    } else {
+        "$defs"
+    };
+
+    let mut existing: HashMap<String, String> = HashMap::new();
+    if let Some(defs) = schema.get(defs_keyword).and_then(|defs| defs.as_object()) {
+        for (name, definition) in defs {
+            existing.entry(definition.to_string()).or_insert_with(|| name.clone());
+        }
+    }
+
+    // Serialized subschema with the pointers of where it appears, in document order.
+    let mut occurrences: Vec<(String, Vec<String>)> = vec![];
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let defs_prefix = format!("/{}/", escape_pointer(defs_keyword));
+    walk_schemas_mut(schema, "", &mut |pointer, subschema| {
+        let is_definition = pointer
+            .strip_prefix(&defs_prefix)
+            .is_some_and(|name| !name.contains('/'));
+        if pointer.is_empty() || is_definition || !subschema.is_object() {
+            return;
+        }
+        let key = subschema.to_string();
+        if key.len() < min_size {
+            return;
+        }
+        let position = *index.entry(key.clone()).or_insert_with(|| {
+            occurrences.push((key, vec![]));
+            occurrences.len() - 1
+        });
+        occurrences[position].1.push(pointer.to_owned());
+    });
+    occurrences.sort_by_key(|(key, _)| std::cmp::Reverse(key.len()));
+
+    let mut new_defs = Map::new();
+    let mut replaced: Vec<String> = vec![];
+    for (key, pointers) in occurrences {
+        let remaining: Vec<String> = pointers
+            .into_iter()
+            .filter(|pointer| {
+                !replaced
+                    .iter()
+                    .any(|parent| pointer.starts_with(parent.as_str()) && pointer[parent.len()..].starts_with('/'))
+            })
+            .collect();
+        let existing_name = existing.get(&key).cloned();
+        if remaining.is_empty() || (remaining.len() < 2 && existing_name.is_none()) {
+            continue;
+        }
+
+        let definition = match schema.pointer(&remaining[0]) {
+            Some(definition) => definition.clone(),
+            None => continue,
+        };
+        let name = match existing_name {
+            Some(name) => name,
+            None => {
+                let base_name = definition_name(&definition);
+                let taken = |name: &str| {
+                    new_defs.contains_key(name)
+                        || existing.values().any(|existing_name| existing_name == name)
+                };
+                let mut name = base_name.clone();
+                let mut suffix = 2;
+                while taken(&name) {
+                    name = format!("{}_{}", base_name, suffix);
+                    suffix += 1;
+                }
+                new_defs.insert(name.clone(), definition);
+                name
+            }
+        };
+
+        let reference = serde_json::json!({"$ref": format!("#/{}/{}", defs_keyword, escape_pointer(&name))});
+        for pointer in remaining {
+            if let Some(occurrence) = schema.pointer_mut(&pointer) {
+                *occurrence = reference.clone();
+            }
+            replaced.push(pointer);
+        }
+    }
+
+    let count = replaced.len();
+    if !new_defs.is_empty() {
+        if let Some(obj) = schema.as_object_mut() {
+            let defs = obj
+                .entry(defs_keyword)
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Some(defs) = defs.as_object_mut() {
+                defs.extend(new_defs);
+            }
+        }
+    }
+    count
+}
+
+/// Split a schema with a top-level `oneOf` or `anyOf` into one standalone schema per branch,
+/// each branch merged with the keywords beside it, as form generators and test data tools need.
+///
+/// When both are present every combination of a `oneOf` branch and an `anyOf` branch is
+/// returned. A branch that conflicts with the keywords beside it is combined with them in an
+/// `allOf` instead, and a `false` branch, which nothing matches, is left out. A schema without
+/// either keyword is returned as it is. Refs are not followed, so dereference first.
+///
+/// ```
+/// use serde_json::json;
+///
+/// let schema = json!(
+///     {"type": "object",
+///      "required": ["kind"],
+///      "oneOf": [{"properties": {"kind": {"const": "cat"}, "lives": {"type": "integer"}}},
+///                {"properties": {"kind": {"const": "dog"}}}]}
+/// );
+///
+/// let variants = jsonref::transform::split_variants(&schema);
+///
+/// assert_eq!(variants, vec![
+///     json!({"type": "object", "required": ["kind"],
+///            "properties": {"kind": {"const": "cat"}, "lives": {"type": "integer"}}}),
+///     json!({"type": "object", "required": ["kind"], "properties": {"kind": {"const": "dog"}}}),
+/// ]);
+/// ```
+pub fn split_variants(schema: &Value) -> Vec<Value> {
+    let mut base = match schema.as_object() {
+        Some(obj) => obj.clone(),
+        None => return vec![schema.clone()],
+    };
+    let mut variants = vec![];
+    for keyword in ["oneOf", "anyOf"] {
+        if let Some(Value::Array(branches)) = base.get(keyword) {
+            variants.push(branches.clone());
+            base.remove(keyword);
+        }
+    }
+
+    let mut schemas = vec![Value::Object(base)];
+    for branches in variants {
+        schemas = schemas
+            .iter()
+            .flat_map(|schema| branches.iter().filter_map(move |branch| combine(schema, branch)))
+            .collect();
+    }
+    schemas
+}
+
+/// `schema` restricted to `branch`, or `None` if `branch` is `false`.
+fn combine(schema: &Value, branch: &Value) -> Option<Value> {
+    let mut merged = schema.as_object().cloned().unwrap_or_default();
+    match branch {
+        Value::Bool(false) => None,
+        Value::Object(obj) if merge_into(&mut merged, obj) => Some(Value::Object(merged)),
+        Value::Object(_) => Some(serde_json::json!({"allOf": [schema, branch]})),
+        _ => Some(schema.clone()),
+    }
+}
+
+/// The `$schema` of draft 2020-12, given to migrated schemas.
+const DRAFT_2020_12: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Rewrite a draft-04, -06 or -07 schema in the form of draft 2020-12, so that legacy schemas
+/// come out as modern ones:
+///
+/// - `definitions` becomes `$defs`,
+/// - `id` becomes `$id`, and plain name fragments (`#name`) in either become `$anchor`,
+/// - boolean `exclusiveMinimum` and `exclusiveMaximum` take the value of `minimum` and
+///   `maximum`,
+/// - an array of `items` becomes `prefixItems`, and `additionalItems` becomes `items`,
+/// - `dependencies` is split into `dependentRequired` and `dependentSchemas`,
+/// - `$schema` names draft 2020-12.
+///
+/// JSON pointers in `$ref`s are rewritten to match. Schemas whose `$schema` already names draft
+/// 2019-09 or later are left as they are.
+///
+/// ```
+/// use serde_json::json;
+///
+/// let mut schema = json!(
+///     {"$schema": "http://json-schema.org/draft-04/schema#",
+///      "definitions": {"age": {"type": "integer", "minimum": 0, "exclusiveMinimum": true}},
+///      "properties": {"pair": {"items": [{"$ref": "#/definitions/age"}, {"type": "string"}]}}}
+/// );
+///
+/// jsonref::transform::migrate_to_2020_12(&mut schema);
+///
+/// assert_eq!(schema, json!(
+///     {"$schema": "https://json-schema.org/draft/2020-12/schema",
+///      "$defs": {"age": {"type": "integer", "exclusiveMinimum": 0}},
+///      "properties": {"pair": {"prefixItems": [{"$ref": "#/$defs/age"}, {"type": "string"}]}}}
+/// ));
+/// ```
+pub fn migrate_to_2020_12(schema: &mut Value) {
+    let current = schema.get("$schema").and_then(|meta_schema| meta_schema.as_str()).unwrap_or("");
+    if current.contains("json-schema.org/draft/") {
+        return;
+    }
+    schema::normalize_definitions(schema, DefinitionsKeyword::Defs);
+    walk_schemas_mut(schema, "", &mut |_, subschema| {
+        if let Some(obj) = subschema.as_object_mut() {
+            migrate_keywords(obj);
+        }
+    });
+}
+
+fn migrate_keywords(obj: &mut Map<String, Value>) {
+    if let Some(Value::String(meta_schema)) = obj.get("$schema") {
+        if meta_schema.starts_with("http://json-schema.org/draft-") {
+            obj.insert("$schema".to_owned(), Value::String(DRAFT_2020_12.to_owned()));
+        }
+    }
+
+    if !obj.contains_key("$id") {
+        if let Some(id) = obj.get("id").filter(|id| id.is_string()).cloned() {
+            obj.remove("id");
+            obj.insert("$id".to_owned(), id);
+        }
+    }
+    if let Some(Value::String(id)) = obj.get("$id").cloned() {
+        if let Some((base, anchor)) = id.split_once('#') {
+            if !anchor.is_empty() && !anchor.starts_with('/') {
+                obj.insert("$anchor".to_owned(), Value::String(anchor.to_owned()));
+            }
+            if base.is_empty() {
+                obj.remove("$id");
+            } else {
+                obj.insert("$id".to_owned(), Value::String(base.to_owned()));
+            }
+        }
+    }
+
+    for (exclusive, limit) in [("exclusiveMinimum", "minimum"), ("exclusiveMaximum", "maximum")] {
+        if let Some(Value::Bool(exclusive_limit)) = obj.get(exclusive).cloned() {
+            obj.remove(exclusive);
+            if exclusive_limit {
+                if let Some(value) = obj.remove(limit) {
+                    obj.insert(exclusive.to_owned(), value);
+                }
+            }
+        }
+    }
+
+    if obj.get("items").is_some_and(|items| items.is_array()) {
+        if let Some(items) = obj.remove("items") {
+            obj.insert("prefixItems".to_owned(), items);
+        }
+        if let Some(additional) = obj.remove("additionalItems") {
+            obj.insert("items".to_owned(), additional);
+        }
+    }
+
+    if let Some(Value::Object(dependencies)) = obj.remove("dependencies") {
+        for (name, dependency) in dependencies {
+            let keyword = if dependency.is_array() { "dependentRequired" } else { "dependentSchemas" };
+            if let Value::Object(dependents) = obj.entry(keyword).or_insert_with(|| Value::Object(Map::new())) {
+                dependents.entry(name).or_insert(dependency);
+            }
+        }
+    }
+
+    if let Some(Value::String(reference)) = obj.get_mut("$ref") {
+        if let Some((document, fragment)) = reference.split_once('#') {
+            *reference = format!("{}#{}", document, migrate_pointer(fragment));
+        }
+    }
+}
+
+/// Rewrite a JSON pointer into a legacy schema to point at the same place once it has been
+/// migrated, renaming `items` followed by an index and `dependencies`. Whether an `items` held
+/// an array is told from the index following it.
+pub(crate) fn migrate_pointer(pointer: &str) -> String {
+    let tokens: Vec<&str> = pointer.split('/').collect();
+    let mut migrated = vec![];
+    // Whether the next token is a keyword of a schema, rather than a name or an index.
+    let mut at_keyword = true;
+    for (index, token) in tokens.iter().enumerate() {
+        if index == 0 {
+            migrated.push(token.to_string());
+            continue;
+        }
+        if !at_keyword {
+            at_keyword = true;
+            migrated.push(token.to_string());
+            continue;
+        }
+        let next_is_index = tokens.get(index + 1).is_some_and(|next| next.parse::<usize>().is_ok());
+        let renamed = match *token {
+            "items" if next_is_index => "prefixItems",
+            "additionalItems" => "items",
+            "dependencies" => "dependentSchemas",
+            "definitions" => "$defs",
+            token => token,
+        };
+        at_keyword = !(SCHEMA_MAP_KEYWORDS.contains(&renamed) || SCHEMA_ARRAY_KEYWORDS.contains(&renamed) && next_is_index);
+        if !at_keyword || SCHEMA_KEYWORDS.contains(&renamed) || renamed != *token {
+            migrated.push(renamed.to_owned());
+        } else {
+            // Left the keywords known to hold schemas, so nothing further is renamed.
+            migrated.extend(tokens[index..].iter().map(|token| token.to_string()));
+            break;
+        }
+    }
+    migrated.join("/")
+}
+
+/// Name for a factored out definition, from its title when it has one.
+fn definition_name(definition: &Value) -> String {
+    let name: String = definition
+        .get("title")
+        .and_then(|title| title.as_str())
+        .unwrap_or("")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .collect();
+    if name.is_empty() {
+        "schema".to_owned()
+    } else {
+        name
+    }
+}
+
+/// Merge the keywords of `branch` into `target`, returning `false` on a conflict.
+fn merge_into(target: &mut Map<String, Value>, branch: &Map<String, Value>) -> bool {
+    let scoped = |obj: &Map<String, Value>| obj.keys().any(|key| MERGE_SCOPED.contains(&key.as_str()));
+    let constrains = |obj: &Map<String, Value>| obj.keys().any(|key| !MERGE_ANNOTATIONS.contains(&key.as_str()));
+    if scoped(target) && constrains(branch) || scoped(branch) && constrains(target) {
+        return false;
+    }
+
+    for (key, value) in branch {
+        let existing = match target.get_mut(key) {
+            Some(existing) => existing,
+            None => {
+                target.insert(key.clone(), value.clone());
+                continue;
+            }
+        };
+        if existing == value || MERGE_ANNOTATIONS.contains(&key.as_str()) {
+            continue;
+        }
+        match (key.as_str(), existing, value) {
+            ("properties", Value::Object(existing), Value::Object(properties)) => {
+                for (name, property) in properties {
+                    match existing.get_mut(name) {
+                        Some(current) if current != property => {
+                            let combined = serde_json::json!({"allOf": [current.take(), property.clone()]});
+                            *current = combined;
+                        }
+                        Some(_) => {}
+                        None => {
+                            existing.insert(name.clone(), property.clone());
+                        }
+                    }
+                }
+            }
+            ("required", Value::Array(existing), Value::Array(required)) => {
+                for name in required {
+                    if !existing.contains(name) {
+                        existing.push(name.clone());
+                    }
+                }
+            }
+            _ => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{flatten_all_of, migrate_pointer, FlattenAllOf, StripKeywords, migrate_to_2020_12, rereference, split_variants, strip_keywords};
+    use serde_json::json;
+
+    #[test]
+    fn rereference_nested_and_existing() {
+        let name = json!({"type": "string", "minLength": 1});
+        let person = json!({"properties": {"first": name.clone(), "last": name.clone()}});
+        let mut schema = json!(
+            {"definitions": {"name": name.clone()},
+             "properties": {"owner": person.clone(), "tenant": person.clone(), "agent": {"items": name.clone()}}}
+        );
+
+        let replaced = rereference(&mut schema, 5);
+
+        assert_eq!(replaced, 3);
+        assert_eq!(schema["definitions"]["schema"]["properties"]["first"], name);
+        assert_eq!(schema["properties"]["owner"], json!({"$ref": "#/definitions/schema"}));
+        assert_eq!(schema["properties"]["tenant"], json!({"$ref": "#/definitions/schema"}));
+        assert_eq!(schema["properties"]["agent"]["items"], json!({"$ref": "#/definitions/name"}));
+        assert_eq!(schema["definitions"]["name"], name);
+    }
+
+    #[test]
+    fn strip_nested_keywords() {
+        let mut schema = json!(
+            {"$comment": "root",
+             "items": [{"title": "first", "examples": ["a"]}],
+             "$defs": {"title": {"title": "a definition called title"}},
+             "enum": [{"title": "data, not a schema"}]}
+        );
+
+        strip_keywords(&mut schema, &["title", "examples", "$comment"]);
+
+        assert_eq!(
+            schema,
+            json!({"items": [{}], "$defs": {"title": {}}, "enum": [{"title": "data, not a schema"}]})
+        );
+    }
+
+    #[test]
+    fn flatten_nested_and_conflicting() {
+        let mut schema = json!(
+            {"title": "outer",
+             "properties": {
+                "merged": {"allOf": [{"title": "a", "properties": {"x": {"type": "string"}}},
+                                     {"title": "b", "properties": {"x": {"maxLength": 3}}}]},
+                "conflict": {"allOf": [{"type": "string"}, {"type": "number"}]},
+                "closed": {"allOf": [{"properties": {"a": {}}, "additionalProperties": false},
+                                     {"properties": {"b": {}}}]},
+                "with_ref": {"allOf": [{"$ref": "#/definitions/a"}]}}}
+        );
+
+        let conflicts = flatten_all_of(&mut schema);
+
+        assert_eq!(conflicts, vec!["/properties/closed/allOf", "/properties/conflict/allOf"]);
+        assert!(schema["properties"]["closed"].get("allOf").is_some());
+        assert_eq!(
+            schema["properties"]["merged"],
+            json!({"title": "a", "properties": {"x": {"type": "string", "maxLength": 3}}})
+        );
+        assert_eq!(
+            schema["properties"]["conflict"],
+            json!({"allOf": [{"type": "string"}, {"type": "number"}]})
+        );
+        assert_eq!(
+            schema["properties"]["with_ref"],
+            json!({"allOf": [{"$ref": "#/definitions/a"}]})
+        );
+    }
+
+    #[test]
+    fn split_combinations_and_conflicts() {
+        let schema = json!(
+            {"type": "string",
+             "oneOf": [{"maxLength": 3}, {"type": "number"}, false],
+             "anyOf": [{"pattern": "a"}, true]}
+        );
+
+        assert_eq!(
+            split_variants(&schema),
+            vec![
+                json!({"type": "string", "maxLength": 3, "pattern": "a"}),
+                json!({"type": "string", "maxLength": 3}),
+                json!({"allOf": [{"type": "string"}, {"type": "number"}], "pattern": "a"}),
+                json!({"allOf": [{"type": "string"}, {"type": "number"}]}),
+            ]
+        );
+        assert_eq!(split_variants(&json!({"type": "string"})), vec![json!({"type": "string"})]);
+    }
+
+    #[test]
+    fn migrate_legacy_keywords() {
+        let mut schema = json!(
+            {"id": "https://example.com/person.json",
+             "properties": {
+                "id": {"id": "#id", "type": "string"},
+                "score": {"maximum": 10, "exclusiveMaximum": false},
+                "tuple": {"items": [{"type": "string"}], "additionalItems": false},
+                "ref": {"$ref": "#/properties/tuple/items/0"}},
+             "dependencies": {"a": ["b"], "c": {"required": ["d"]}}}
+        );
+
+        migrate_to_2020_12(&mut schema);
+
+        assert_eq!(
+            schema,
+            json!(
+                {"$id": "https://example.com/person.json",
+                 "properties": {
+                    "id": {"$anchor": "id", "type": "string"},
+                    "score": {"maximum": 10},
+                    "tuple": {"prefixItems": [{"type": "string"}], "items": false},
+                    "ref": {"$ref": "#/properties/tuple/prefixItems/0"}},
+                 "dependentRequired": {"a": ["b"]},
+                 "dependentSchemas": {"c": {"required": ["d"]}}}
+            )
+        );
+        assert_eq!(migrate_pointer("/dependencies/c/items/0"), "/dependentSchemas/c/prefixItems/0");
+        assert_eq!(migrate_pointer("/properties/items/items"), "/properties/items/items");
+        assert_eq!(migrate_pointer("/paths/items/0"), "/paths/items/0");
+    }
+
+    #[test]
+    fn passes_in_order() {
+        let mut jsonref = crate::JsonRef::new();
+        jsonref.add_pass(StripKeywords(vec!["title".to_owned()]));
+        jsonref.add_pass(FlattenAllOf);
+        jsonref.add_pass(|schema: &mut serde_json::Value| schema["x-passes"] = json!(true));
+
+        let mut input = json!(
+            {"$defs": {"a": {"title": "a", "type": "string"}, "b": {"title": "b", "maxLength": 3}},
+             "allOf": [{"$ref": "#/$defs/a"}, {"$ref": "#/$defs/b"}]}
+        );
+        jsonref.deref_value(&mut input).unwrap();
+
+        assert_eq!(
+            input,
+            json!({"$defs": {"a": {"type": "string"}, "b": {"maxLength": 3}},
+                   "type": "string", "maxLength": 3, "x-passes": true})
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! HTTP over Unix domain sockets, for `http+unix` urls such as those of schema services run as
+//! sidecars. The socket path is the percent-encoded host of the url, as in
+//! `http+unix://%2Fvar%2Frun%2Fregistry.sock/schemas/order.json`. Only sockets added with
+//! [`JsonRef::add_unix_socket`](crate::JsonRef::add_unix_socket) are connected to.
+
+use crate::http::percent_decode;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use url::Url;
+
+/// Send `request`, a `GET` of the `http+unix` url `url`, on a connection of its own and read
+/// the response. Redirects are not followed. The socket must have been allowed by the caller.
+pub(crate) fn call(url: &Url, request: &ureq::Request, timeout: Option<Duration>) -> Result<ureq::Response, ureq::Error> {
+    let socket = percent_decode(url.host_str().unwrap_or_default());
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+
+    let target = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned(),
+    };
+    let mut head = format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n", target);
+    for name in request.header_names() {
+        for value in request.all(&name) {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+
+    // The server closes the connection after the response, so it is all that is read.
+    let mut response = vec![];
+    stream.read_to_end(&mut response)?;
+    let response = parse_response(&response)?;
+    match response.status() {
+        status if status >= 400 => Err(ureq::Error::Status(status, response)),
+        _ => Ok(response),
+    }
+}
+
+/// Parse the HTTP/1.1 response `response`, its head as text and its body, chunked or not, as
+/// bytes whatever they are.
+fn parse_response(response: &[u8]) -> io::Result<ureq::Response> {
+    let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, format!("bad response: {}", reason));
+    let head_end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| invalid("no end of the head"))?;
+    let head = String::from_utf8_lossy(&response[..head_end]);
+    let mut body = &response[head_end + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|status_line| status_line.split(' ').nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| invalid("no status"))?;
+    let mut builder = http::Response::builder().status(status);
+    let mut chunked = false;
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or_else(|| invalid("header without a colon"))?;
+        let (name, value) = (name.trim(), value.trim());
+        if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+            continue;
+        }
+        if name.eq_ignore_ascii_case("content-length") {
+            let length = value.parse::<usize>().map_err(|_| invalid("bad content length"))?;
+            body = body.get(..length).ok_or_else(|| invalid("body shorter than its content length"))?;
+        }
+        builder = builder.header(name, value);
+    }
+    let body = if chunked { dechunk(body).ok_or_else(|| invalid("bad chunk"))? } else { body.to_vec() };
+    let response = builder.body(body).map_err(|error| invalid(&error.to_string()))?;
+    Ok(response.into())
+}
+
+/// The data of the chunked body `body`, `None` if it is cut short or malformed.
+fn dechunk(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut data = vec![];
+    loop {
+        let line_end = body.windows(2).position(|window| window == b"\r\n")?;
+        let size = std::str::from_utf8(&body[..line_end]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Some(data);
+        }
+        data.extend_from_slice(body.get(..size)?);
+        body = body.get(size..)?.strip_prefix(b"\r\n")?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_response;
+    use crate::http::HostOptions;
+    use crate::JsonRef;
+    use serde_json::json;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::os::unix::net::UnixListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn documents_over_unix_socket() {
+        let socket = std::env::temp_dir().join(format!("jsonref-unix-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || {
+            let bodies = [r#"{"properties": {"id": {"$ref": "id.json"}}}"#, r#"{"type": "integer"}"#];
+            for (body, stream) in bodies.iter().zip(listener.incoming()) {
+                let mut stream = stream.unwrap();
+                let lines: Vec<String> = BufReader::new(&stream)
+                    .lines()
+                    .map(Result::unwrap)
+                    .take_while(|line| !line.is_empty())
+                    .collect();
+                sender.send(lines).unwrap();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        let base = format!("http+unix://{}", socket.to_str().unwrap().replace('/', "%2F"));
+        let mut jsonref = JsonRef::new();
+        jsonref.set_host_options(&base, HostOptions::new().header("X-Team", "schemas"));
+        let mut input = json!({"$ref": format!("{}/orders/order.json", base)});
+        let error = jsonref.deref_value(&mut input.clone()).unwrap_err();
+        assert!(error.to_string().contains("has not been added"), "{}", error);
+
+        jsonref.add_unix_socket(&socket);
+        jsonref.deref_value(&mut input).unwrap();
+        assert_eq!(input, json!({"properties": {"id": {"type": "integer"}}}));
+
+        let order = requests.recv().unwrap();
+        assert_eq!(order[0], "GET /orders/order.json HTTP/1.1");
+        assert!(order.contains(&"x-team: schemas".to_owned()));
+        assert_eq!(requests.recv().unwrap()[0], "GET /orders/id.json HTTP/1.1");
+        std::fs::remove_file(&socket).unwrap();
+    }
+
+    #[test]
+    fn bodies_kept_as_bytes() {
+        let mut response = b"HTTP/1.1 200 OK\r\nContent-Type: application/json; charset=iso-8859-1\r\n\
+            Transfer-Encoding: chunked\r\n\r\n11\r\n{\"title\": \"caf\xe9\"}\r\n0\r\n\r\n"
+            .to_vec();
+        let parsed = parse_response(&response).unwrap();
+        assert_eq!(parsed.into_string().unwrap(), r#"{"title": "café"}"#);
+
+        response.truncate(response.len() - 5);
+        assert!(parse_response(&response).is_err());
+        let parsed = parse_response(b"HTTP/1.1 404 Not Found\r\nContent-Length: 2\r\n\r\n\xff\xfe extra").unwrap();
+        assert_eq!(parsed.status(), 404);
+        let mut body = vec![];
+        parsed.into_reader().read_to_end(&mut body).unwrap();
+        assert_eq!(body, b"\xff\xfe");
+    }
+}
@@ -0,0 +1,248 @@
+//! Checks of dereferenced schemas using the [`jsonschema`] validator. Needs the `jsonschema`
+//! feature.
+
+use crate::{Error, JSONRefError, JsonRef, Result, SchemaFromFile, SchemaNotJsonSerde};
+use jsonschema::{Retrieve, Uri};
+use serde_json::{json, Value};
+use snafu::ResultExt;
+use std::collections::HashSet;
+use std::fs;
+use std::sync::Arc;
+use url::Url;
+
+/// The meta-schema used for schemas without a `$schema` keyword.
+pub const DEFAULT_META_SCHEMA: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// A part of a document that fails validation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Violation {
+    /// JSON pointer to the invalid part of the document.
+    pub pointer: String,
+    /// What is wrong with it.
+    pub message: String,
+}
+
+/// A sample instance that the original and the dereferenced schema disagree on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The position of the instance in the samples.
+    pub index: usize,
+    /// Why the original schema rejects the instance, empty if it accepts it.
+    pub original: Vec<Violation>,
+    /// Why the dereferenced schema rejects the instance, empty if it accepts it.
+    pub dereferenced: Vec<Violation>,
+}
+
+/// A [`Retrieve`] implementation for `jsonschema` validators sharing the documents of the
+/// [`JsonRef`] it was created from with [`JsonRef::retriever`]. Documents neither has loaded are
+/// fetched as that `JsonRef` fetches refs, through its layers, URL rewriter and mirrors, and kept
+/// where both see them.
+#[derive(Debug, Clone)]
+pub struct CacheRetriever {
+    jsonref: Arc<JsonRef>,
+}
+
+impl CacheRetriever {
+    pub(crate) fn new(jsonref: JsonRef) -> CacheRetriever {
+        CacheRetriever { jsonref: Arc::new(jsonref) }
+    }
+}
+
+impl Retrieve for CacheRetriever {
+    fn retrieve(&self, uri: &Uri<&str>) -> std::result::Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let mut url = Url::parse(uri.as_str())?;
+        url.set_fragment(None);
+        let document = self.jsonref.load(&url)?;
+        Ok(Value::clone(&document))
+    }
+}
+
+/// Every violation of `schema` against the meta-schema named by its `$schema` keyword, or
+/// [`DEFAULT_META_SCHEMA`] when it has none.
+///
+/// ```
+/// use jsonref::validate::meta_schema_violations;
+/// use serde_json::json;
+///
+/// let violations = meta_schema_violations(&json!({"properties": {"a": {"type": 5}}})).unwrap();
+///
+/// assert_eq!(violations[0].pointer, "/properties/a/type");
+/// ```
+pub fn meta_schema_violations(schema: &Value) -> Result<Vec<Violation>> {
+    let meta_schema = schema
+        .get("$schema")
+        .and_then(Value::as_str)
+        .unwrap_or(DEFAULT_META_SCHEMA);
+    let validator = jsonschema::validator_for(&json!({ "$ref": meta_schema })).map_err(|error| Error::Validator {
+        reason: format!("meta-schema {}: {}", meta_schema, error),
+    })?;
+    Ok(violations(&validator, schema))
+}
+
+/// Validate each of `instances` against both the `original` schema found at `url` and its
+/// `dereferenced` form, returning the instances only one of them accepts. Refs in the original
+/// are resolved by the validator relative to `url`.
+///
+/// ```
+/// use jsonref::validate::divergences;
+/// use serde_json::json;
+///
+/// let original = json!({"$defs": {"name": {"type": "string", "maxLength": 3}},
+///                       "properties": {"name": {"$ref": "#/$defs/name"}}});
+/// let dereferenced = json!({"properties": {"name": {"type": "string"}}});
+///
+/// let found = divergences(&original, "file:///schema.json", &dereferenced,
+///                         &[json!({"name": "abc"}), json!({"name": "abcd"})]).unwrap();
+///
+/// assert_eq!(found.len(), 1);
+/// assert_eq!(found[0].index, 1);
+/// ```
+pub fn divergences(original: &Value, url: &str, dereferenced: &Value, instances: &[Value]) -> Result<Vec<Divergence>> {
+    let mut original = original.clone();
+    if let Some(obj) = original.as_object_mut() {
+        obj.entry("$id").or_insert_with(|| Value::String(url.to_owned()));
+    }
+    let original = build(&original, url, None)?;
+    let dereferenced = build(dereferenced, url, None)?;
+    Ok(compare(&original, &dereferenced, instances))
+}
+
+/// Dereference the schema in `file_path` with `jsonref` and compare it with the original as
+/// [`divergences`] does. The documents loaded while dereferencing are reused by the validators.
+pub fn file_divergences(jsonref: &mut JsonRef, file_path: &str, instances: &[Value]) -> Result<Vec<Divergence>> {
+    let file = fs::File::open(file_path).context(SchemaFromFile { filename: file_path.to_owned() })?;
+    let mut original: Value = serde_json::from_reader(file).context(SchemaNotJsonSerde { url: file_path.to_owned() })?;
+    let absolute_path = fs::canonicalize(file_path).context(JSONRefError {})?;
+    let url = format!("file://{}", absolute_path.to_string_lossy());
+    if let Some(obj) = original.as_object_mut() {
+        obj.entry("$id").or_insert_with(|| Value::String(url.clone()));
+    }
+
+    let dereferenced = jsonref.deref_file(file_path)?;
+    let retriever = jsonref.retriever();
+    let original = build(&original, &url, Some(retriever.clone()))?;
+    let dereferenced = build(&dereferenced, &url, Some(retriever))?;
+    Ok(compare(&original, &dereferenced, instances))
+}
+
+fn compare(original: &jsonschema::Validator, dereferenced: &jsonschema::Validator, instances: &[Value]) -> Vec<Divergence> {
+    let mut found = vec![];
+    for (index, instance) in instances.iter().enumerate() {
+        let original_violations = violations(original, instance);
+        let dereferenced_violations = violations(dereferenced, instance);
+        if original_violations.is_empty() != dereferenced_violations.is_empty() {
+            found.push(Divergence {
+                index,
+                original: original_violations,
+                dereferenced: dereferenced_violations,
+            });
+        }
+    }
+    found
+}
+
+fn build(schema: &Value, url: &str, retriever: Option<CacheRetriever>) -> Result<jsonschema::Validator> {
+    let built = match retriever {
+        Some(retriever) => jsonschema::options().with_retriever(retriever).build(schema),
+        None => jsonschema::validator_for(schema),
+    };
+    built.map_err(|error| Error::Validator {
+        reason: format!("schema {}: {}", url, error),
+    })
+}
+
+fn violations(validator: &jsonschema::Validator, instance: &Value) -> Vec<Violation> {
+    // Draft 2020-12 checks each vocabulary's meta-schema separately, so one mistake can be
+    // reported several times at the same place.
+    let mut seen = HashSet::new();
+    validator
+        .iter_errors(instance)
+        .map(|error| Violation {
+            pointer: error.instance_path.to_string(),
+            message: error.to_string(),
+        })
+        .filter(|violation| seen.insert(violation.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{file_divergences, meta_schema_violations};
+    use crate::{Error, JsonRef};
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn duplicate_violations_reported_once() {
+        let violations = meta_schema_violations(&json!({"properties": {"a": 5}})).unwrap();
+
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].pointer, "/properties/a");
+    }
+
+    #[test]
+    fn invalid_after_inlining() {
+        let draft7 = json!({"$schema": "http://json-schema.org/draft-07/schema#", "required": "name"});
+        assert_eq!(meta_schema_violations(&draft7).unwrap()[0].pointer, "/required");
+
+        // The target is data rather than a schema, so inlining it breaks the document.
+        let mut input = json!(
+            {"examples": [5],
+             "properties": {"prop1": {"$ref": "#/examples/0"}}}
+        );
+        let mut jsonref = JsonRef::new();
+        jsonref.set_validate_meta_schema(true);
+
+        match jsonref.deref_value(&mut input) {
+            Err(Error::MetaSchemaViolations { violations }) => {
+                assert_eq!(violations.len(), 1);
+                assert_eq!(violations[0].pointer, "/properties/prop1");
+            }
+            other => panic!("expected violations, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fixture_equivalent() {
+        let mut jsonref = JsonRef::new();
+        let samples = [json!({"prop3": {}}), json!(5)];
+        assert!(file_divergences(&mut jsonref, "fixtures/nested_relative/base.json", &samples)
+            .unwrap()
+            .is_empty());
+
+        // Kept refs are followed by the validator itself.
+        let mut jsonref = JsonRef::new();
+        jsonref.set_max_depth(0);
+        jsonref.set_absolute_refs(true);
+        assert!(file_divergences(&mut jsonref, "fixtures/nested_relative/base.json", &samples)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn retriever_shares_documents() {
+        let mut jsonref = JsonRef::new();
+        jsonref.register_at("file:///not/on/disk.json", json!({"$defs": {"a": {"type": "string"}}}));
+
+        let validator = jsonschema::options()
+            .with_retriever(jsonref.retriever())
+            .build(&json!({"$ref": "file:///not/on/disk.json#/$defs/a"}))
+            .unwrap();
+        assert!(validator.is_valid(&json!("a")));
+        assert!(!validator.is_valid(&json!(1)));
+
+        // What the validator fetches is there for later refs.
+        let other = fs::canonicalize("fixtures/nested_relative/other.json").unwrap();
+        let other_url = format!("file://{}", other.to_string_lossy());
+        jsonschema::options()
+            .with_retriever(jsonref.retriever())
+            .build(&json!({"$ref": other_url}))
+            .unwrap();
+        assert!(jsonref.documents().contains(&other_url));
+
+        let unsupported = jsonschema::options()
+            .with_retriever(jsonref.retriever())
+            .build(&json!({"$ref": "urn:example:missing"}));
+        assert!(unsupported.is_err());
+    }
+}
@@ -0,0 +1,114 @@
+//! Walking every subschema of a schema, following only keywords known to contain subschemas,
+//! so that property names, `enum` values and examples are never mistaken for schemas.
+
+use crate::schema::{children, walk_schemas_mut};
+use serde_json::Value;
+
+/// A subschema found by [`subschemas`] or [`JsonRef::subschemas`](crate::JsonRef::subschemas).
+#[derive(Debug, Clone)]
+pub struct Subschema<'a> {
+    /// JSON pointer to the subschema.
+    pub pointer: String,
+    pub schema: &'a Value,
+    /// The URL, with a fragment, the subschema was inlined from, if known.
+    pub source: Option<&'a str>,
+}
+
+/// Iterate over `schema` and every subschema in it, parents before children, in the order
+/// [`walk_mut`] visits them. Sources are not known and left `None`.
+///
+/// ```
+/// use serde_json::json;
+///
+/// let schema = json!(
+///     {"properties": {"name": {"type": "string"}, "age": {"type": "integer"}},
+///      "examples": [{"type": "string"}]}
+/// );
+///
+/// let strings: Vec<String> = jsonref::walk::subschemas(&schema)
+///     .filter(|subschema| subschema.schema["type"] == "string")
+///     .map(|subschema| subschema.pointer)
+///     .collect();
+///
+/// assert_eq!(strings, ["/properties/name"]);
+/// ```
+pub fn subschemas(schema: &Value) -> Subschemas<'_> {
+    Subschemas {
+        stack: vec![(String::new(), schema)],
+    }
+}
+
+/// The iterator returned by [`subschemas`].
+#[derive(Debug, Clone)]
+pub struct Subschemas<'a> {
+    /// The subschemas still to visit, the next one last.
+    stack: Vec<(String, &'a Value)>,
+}
+
+impl<'a> Iterator for Subschemas<'a> {
+    type Item = Subschema<'a>;
+
+    fn next(&mut self) -> Option<Subschema<'a>> {
+        let (pointer, schema) = self.stack.pop()?;
+        let children = children(schema)
+            .into_iter()
+            .map(|(child_pointer, subschema)| (format!("{}{}", pointer, child_pointer), subschema));
+        self.stack.extend(children.rev());
+        Some(Subschema {
+            pointer,
+            schema,
+            source: None,
+        })
+    }
+}
+
+/// Call `visit` with the JSON pointer and a mutable reference to `schema` and every subschema in
+/// it, parents before children. Changes made to a schema are seen when walking its children, so
+/// subschemas added are visited too and ones removed are not.
+///
+/// ```
+/// use serde_json::json;
+///
+/// let mut schema = json!(
+///     {"properties": {"type": {"type": "string"}, "tags": {"items": {"type": "string"}}},
+///      "enum": [{"type": "string"}]}
+/// );
+///
+/// let mut changed = vec![];
+/// jsonref::walk::walk_mut(&mut schema, |pointer, subschema| {
+///     if subschema.get("type") == Some(&json!("string")) {
+///         subschema["minLength"] = json!(1);
+///         changed.push(pointer.to_owned());
+///     }
+/// });
+///
+/// assert_eq!(changed, ["/properties/tags/items", "/properties/type"]);
+/// assert_eq!(schema["properties"]["tags"]["items"], json!({"type": "string", "minLength": 1}));
+/// assert_eq!(schema["enum"], json!([{"type": "string"}]));
+/// ```
+pub fn walk_mut<F>(schema: &mut Value, mut visit: F)
+where
+    F: FnMut(&str, &mut Value),
+{
+    walk_schemas_mut(schema, "", &mut visit);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{subschemas, walk_mut};
+    use serde_json::json;
+
+    #[test]
+    fn pointers_parents_first() {
+        let mut schema = json!(
+            {"properties": {"a/b": {"allOf": [{"not": {}}]}},
+             "x-vendor": {"properties": {"c": {}}}}
+        );
+        let mut pointers = vec![];
+        walk_mut(&mut schema, |pointer, _| pointers.push(pointer.to_owned()));
+
+        assert_eq!(pointers, ["", "/properties/a~1b", "/properties/a~1b/allOf/0", "/properties/a~1b/allOf/0/not"]);
+        let iterated: Vec<String> = subschemas(&schema).map(|subschema| subschema.pointer).collect();
+        assert_eq!(iterated, pointers);
+    }
+}
@@ -0,0 +1,64 @@
+//! Compile time dereferencing for [jsonref](https://docs.rs/jsonref).
+//!
+//! `jsonref!("schemas/base.json")` dereferences the schema while the crate is built and expands
+//! to a `&'static serde_json::Value` of the result, so applications shipping fixed schemas need
+//! no filesystem or network access at runtime. The path is relative to the crate's
+//! `Cargo.toml`, and the crate is rebuilt when any file used to dereference it changes. Crates
+//! using the macro need `serde_json` as a dependency.
+//!
+//! The `macros` feature of jsonref re-exports the macro as `jsonref::jsonref!`, so this crate
+//! need not be a dependency of its own.
+//!
+//! ```
+//! use jsonref_macros::jsonref;
+//!
+//! let schema: &'static serde_json::Value = jsonref!("fixtures/base.json");
+//!
+//! assert_eq!(schema["properties"]["name"]["title"], "Name");
+//! ```
+
+use jsonref_core::JsonRef;
+use proc_macro::TokenStream;
+use quote::quote;
+use std::env;
+use std::path::Path;
+use syn::{parse_macro_input, LitStr};
+use url::Url;
+
+/// Dereference the schema file at the given path, relative to the crate's `Cargo.toml`, and
+/// expand to a `&'static serde_json::Value` of the result.
+#[proc_macro]
+pub fn jsonref(input: TokenStream) -> TokenStream {
+    let path = parse_macro_input!(input as LitStr);
+    match expand(&path) {
+        Ok(tokens) => tokens.into(),
+        Err(message) => syn::Error::new(path.span(), message).to_compile_error().into(),
+    }
+}
+
+fn expand(path: &LitStr) -> Result<proc_macro2::TokenStream, String> {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").map_err(|_| "CARGO_MANIFEST_DIR is not set".to_owned())?;
+    let file_path = Path::new(&manifest_dir).join(path.value());
+    let file_path = file_path
+        .to_str()
+        .ok_or_else(|| format!("path {} is not valid UTF-8", file_path.display()))?;
+
+    let mut jsonref = JsonRef::new();
+    let schema = jsonref.deref_file(file_path).map_err(|error| error.to_string())?;
+    let json = serde_json::to_string(&schema).map_err(|error| error.to_string())?;
+
+    // Including the files makes the compiler rebuild the crate when they change.
+    let files: Vec<String> = jsonref
+        .documents()
+        .into_iter()
+        .filter_map(|url| Url::parse(&url).ok()?.to_file_path().ok())
+        .filter(|file| file.exists())
+        .filter_map(|file| file.to_str().map(str::to_owned))
+        .collect();
+
+    Ok(quote! {{
+        #( const _: &[u8] = include_bytes!(#files); )*
+        static SCHEMA: ::std::sync::OnceLock<::serde_json::Value> = ::std::sync::OnceLock::new();
+        SCHEMA.get_or_init(|| ::serde_json::from_str(#json).expect("schema embedded by jsonref! is valid JSON"))
+    }})
+}
@@ -0,0 +1,23 @@
+use jsonref_macros::jsonref;
+use serde_json::json;
+
+#[test]
+fn embeds_dereferenced_schema() {
+    let embedded: &'static serde_json::Value = jsonref!("fixtures/base.json");
+
+    assert_eq!(embedded["properties"]["name"], json!({"title": "Name", "type": "string", "minLength": 1}));
+    assert_eq!(embedded["properties"]["email"], json!({"type": "string", "format": "email"}));
+}
+
+#[test]
+fn expands_to_the_same_value() {
+    let first: &'static serde_json::Value = jsonref!("fixtures/base.json");
+    let second: &'static serde_json::Value = jsonref!("fixtures/name.json");
+
+    assert_eq!(first["properties"]["name"], *second);
+}
+
+#[test]
+fn compile_errors() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,5 @@
+use jsonref_macros::jsonref;
+
+fn main() {
+    let _ = jsonref!(42);
+}
@@ -0,0 +1,789 @@
+//! The `jsonref` command: dereference a schema file, or stdin, and write the result to stdout,
+//! print the documents it refers to with `jsonref graph` or check its refs with `jsonref check`.
+
+use jsonref::resolve::Retry;
+use jsonref::spans::{self, Location};
+use jsonref::{Error, JsonRef};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+const USAGE: &str = "\
+usage: jsonref [SCHEMA]
+       jsonref graph [SCHEMA] [--format dot|json]
+       jsonref check SCHEMA...
+
+Dereference SCHEMA, or stdin when it is missing or `-`, and write the result to stdout.
+Relative refs in stdin are resolved against the current directory.
+
+`jsonref graph` instead prints the documents SCHEMA refers to, directly or indirectly, as a
+graph with an edge from each document to every other document it has a ref into. The format
+is a Graphviz digraph (the default) or a JSON object of document URL to the URLs it refers to.
+
+`jsonref check` checks that every ref in each SCHEMA, and in the files they refer to, can be
+resolved, without writing the dereferenced schema. It lists every ref that can not and exits
+with status 1 if there are any.
+
+options:
+  -o, --output FILE
+               write the result to FILE instead of stdout
+  --watch      dereference again whenever SCHEMA or a file it refers to changes
+  --output-format FORMAT
+               json, json-pretty (the default) or yaml. YAML needs the serde_yaml feature,
+               which also reads `.yaml` and `.yml` files as YAML
+  --mode MODE  how refs are replaced (default: inline)
+                 inline   replace every ref with its target
+                 bundle   move targets in other files into `$defs`, keeping refs within the
+                          schema, to give a single self-contained schema
+                 rewrite  keep every ref, rewritten to an absolute URL
+                 crd      inline every ref and make a Kubernetes CRD structural schema,
+                          warning on stderr about anything that can not be represented
+  --strict     fail on refs that are not valid URI-references, such as ones with spaces or
+               backslashes, rather than resolving them as well as possible
+  --openapi    treat SCHEMA as an OpenAPI document, leaving example data alone and keeping
+               the operationRef of links valid
+  --openrpc    treat SCHEMA as an OpenRPC document, leaving example data alone
+  --keep-components
+               with --openapi or --openrpc, leave `components` as they are and only inline
+               refs elsewhere
+  --migrate    rewrite draft-04, -06 and -07 schemas as draft 2020-12 ones
+  --schema-registry URL
+               resolve refs such as `registry://subject/version` from the Confluent API
+               compatible schema registry at URL
+  --retries N  try documents up to N times when fetching them fails with a connection error,
+               429 or 5xx response, waiting longer between each attempt
+  --resolve HOST=ADDRESS
+               connect to the IP ADDRESS for requests to HOST rather than looking it up; may be
+               given more than once
+  --mirror PREFIX=BASE
+               fetch documents with URLs starting with PREFIX from BASE instead, with the rest
+               of the URL appended; may be given more than once
+  --snapshot FILE
+               resolve refs from the documents in FILE, written by --write-snapshot, rather than
+               fetching them
+  --write-snapshot FILE
+               write every document read, including SCHEMA, to FILE as a JSON object of URL to
+               document
+  --error-format FORMAT
+               text (the default) or json, which writes each error to stderr as a JSON object
+               on its own line, with `kind`, `message` and, for errors about a single ref,
+               `file`, `pointer`, `ref` and, for JSON files, `line` and `column`
+  -h, --help   print this help
+
+Built with the miette feature, text errors about a ref show it in its source.
+
+exit status:
+  0  success
+  1  a ref can not be resolved
+  2  bad arguments
+  3  a file can not be read or written, or is not valid JSON";
+
+/// What went wrong, which decides the exit status. When there are several errors the status is
+/// that of the greatest kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ErrorKind {
+    Resolution,
+    Usage,
+    Io,
+}
+
+impl ErrorKind {
+    fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Resolution => 1,
+            ErrorKind::Usage => 2,
+            ErrorKind::Io => 3,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ErrorKind::Resolution => "resolution",
+            ErrorKind::Usage => "usage",
+            ErrorKind::Io => "io",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Diagnostic {
+    kind: ErrorKind,
+    message: String,
+    /// The file, or URL of another document, the error is in.
+    file: Option<String>,
+    /// JSON pointer to the `$ref` in `file`, for errors about a single ref.
+    pointer: Option<String>,
+    reference: Option<String>,
+    /// Where the `$ref` is in `file`.
+    location: Option<Location>,
+    /// The error drawn by miette with the `$ref` in its source, shown instead of the text form.
+    report: Option<String>,
+}
+
+impl Diagnostic {
+    fn new(kind: ErrorKind, message: String) -> Diagnostic {
+        Diagnostic {
+            kind,
+            message,
+            file: None,
+            pointer: None,
+            reference: None,
+            location: None,
+            report: None,
+        }
+    }
+
+    /// A diagnostic for an error from dereferencing `input`, `None` for stdin. Errors reading
+    /// the input itself are IO errors and any others are resolution errors.
+    fn from_jsonref(error: Error, input: Option<&str>) -> Diagnostic {
+        let in_input = |url: &str| Some(url) == input;
+        let kind = match &error {
+            Error::SchemaFromFile { filename, .. } if in_input(filename) => ErrorKind::Io,
+            Error::SchemaNotJsonSerde { url, .. } if in_input(url) => ErrorKind::Io,
+            #[cfg(feature = "serde_yaml")]
+            Error::SchemaNotYaml { url, .. } if in_input(url) => ErrorKind::Io,
+            Error::JSONRefError { .. } | Error::WriteOutput { .. } => ErrorKind::Io,
+            _ => ErrorKind::Resolution,
+        };
+        Diagnostic {
+            file: input.map(str::to_owned),
+            ..Diagnostic::new(kind, error.to_string())
+        }
+    }
+
+    fn text(&self) -> String {
+        let file = match (&self.file, self.location) {
+            (Some(file), Some(location)) => format!("{}:{}:{}", file, location.line, location.column),
+            (Some(file), None) => file.clone(),
+            (None, _) => return self.message.clone(),
+        };
+        match (&self.pointer, &self.reference) {
+            (Some(pointer), Some(reference)) => {
+                format!("{}: $ref {} at {}: {}", file, reference, pointer, self.message)
+            }
+            _ => self.message.clone(),
+        }
+    }
+
+    fn json(&self) -> Value {
+        let mut json = json!({"kind": self.kind.name(), "message": self.message});
+        for (key, value) in [("file", &self.file), ("pointer", &self.pointer), ("ref", &self.reference)] {
+            if let Some(value) = value {
+                json[key] = json!(value);
+            }
+        }
+        if let Some(location) = self.location {
+            json["line"] = json!(location.line);
+            json["column"] = json!(location.column);
+        }
+        json
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl ErrorFormat {
+    /// Write `diagnostics` to stderr.
+    fn report(self, diagnostics: &[Diagnostic]) {
+        for diagnostic in diagnostics {
+            match self {
+                ErrorFormat::Text => match &diagnostic.report {
+                    Some(report) => eprintln!("{}", report),
+                    None => eprintln!("jsonref: {}", diagnostic.text()),
+                },
+                ErrorFormat::Json => eprintln!("{}", diagnostic.json()),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Json,
+    #[default]
+    JsonPretty,
+    Yaml,
+}
+
+impl OutputFormat {
+    fn serialize(self, output: &Value) -> Result<Vec<u8>, Diagnostic> {
+        let io_error = |error: &dyn std::fmt::Display| Diagnostic::new(ErrorKind::Io, error.to_string());
+        let mut bytes = match self {
+            OutputFormat::Json => serde_json::to_vec(output).map_err(|error| io_error(&error))?,
+            OutputFormat::JsonPretty => serde_json::to_vec_pretty(output).map_err(|error| io_error(&error))?,
+            #[cfg(feature = "serde_yaml")]
+            OutputFormat::Yaml => return serde_yaml::to_string(output).map(String::into_bytes).map_err(|error| io_error(&error)),
+            #[cfg(not(feature = "serde_yaml"))]
+            OutputFormat::Yaml => unreachable!("rejected by parse_args"),
+        };
+        bytes.push(b'\n');
+        Ok(bytes)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Command {
+    #[default]
+    Deref,
+    Graph,
+    Check,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum GraphFormat {
+    #[default]
+    Dot,
+    Json,
+}
+
+impl GraphFormat {
+    fn render(self, graph: &BTreeMap<String, BTreeSet<String>>) -> Vec<u8> {
+        match self {
+            GraphFormat::Dot => {
+                let quote = |url: &str| format!("\"{}\"", url.replace('\\', "\\\\").replace('"', "\\\""));
+                let mut dot = String::from("digraph refs {\n");
+                for (document, dependencies) in graph {
+                    dot.push_str(&format!("  {};\n", quote(document)));
+                    for dependency in dependencies {
+                        dot.push_str(&format!("  {} -> {};\n", quote(document), quote(dependency)));
+                    }
+                }
+                dot.push_str("}\n");
+                dot.into_bytes()
+            }
+            GraphFormat::Json => {
+                let mut json = serde_json::to_vec_pretty(graph).expect("string maps serialize");
+                json.push(b'\n');
+                json
+            }
+        }
+    }
+}
+
+/// The library's output strategies.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Mode {
+    #[default]
+    Inline,
+    Bundle,
+    Rewrite,
+    Crd,
+}
+
+impl Mode {
+    fn configure(self, jsonref: &mut JsonRef) {
+        match self {
+            Mode::Inline => {}
+            Mode::Bundle => jsonref.set_codegen_profile(true),
+            Mode::Rewrite => {
+                jsonref.set_max_depth(0);
+                jsonref.set_absolute_refs(true);
+            }
+            Mode::Crd => jsonref.set_crd_structural(true),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    command: Command,
+    /// The schema files. Only `check` takes more than one, and `-` or none means stdin.
+    paths: Vec<String>,
+    /// The output file, `None` for stdout.
+    output: Option<String>,
+    output_format: OutputFormat,
+    error_format: ErrorFormat,
+    graph_format: Option<GraphFormat>,
+    mode: Mode,
+    strict: bool,
+    migrate: bool,
+    openapi: bool,
+    openrpc: bool,
+    keep_components: bool,
+    schema_registry: Option<String>,
+    /// The attempts to fetch each document, with retries if more than one.
+    retries: Option<u32>,
+    /// Fixed addresses for host names.
+    addresses: Vec<(String, IpAddr)>,
+    /// URL prefixes and the bases to fetch them from instead.
+    mirrors: Vec<(String, String)>,
+    /// A snapshot to load before dereferencing.
+    snapshot: Option<String>,
+    /// Where to write a snapshot after dereferencing.
+    write_snapshot: Option<String>,
+    watch: bool,
+    help: bool,
+}
+
+impl Args {
+    /// The schema file, `None` for stdin.
+    fn path(&self) -> Option<&str> {
+        self.paths.first().map(String::as_str).filter(|path| *path != "-")
+    }
+}
+
+fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter().peekable();
+    parsed.command = match args.peek().map(String::as_str) {
+        Some("graph") => Command::Graph,
+        Some("check") => Command::Check,
+        _ => Command::Deref,
+    };
+    if parsed.command != Command::Deref {
+        args.next();
+    }
+    while let Some(arg) = args.next() {
+        // Options with values are given as `--name value` or `--name=value`.
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name.to_owned(), Some(value.to_owned())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{} needs a value", name))
+        };
+        match name.as_str() {
+            "--mode" => {
+                parsed.mode = match value()?.as_str() {
+                    "inline" => Mode::Inline,
+                    "bundle" => Mode::Bundle,
+                    "rewrite" => Mode::Rewrite,
+                    "crd" => Mode::Crd,
+                    other => return Err(format!("unknown mode {}", other)),
+                }
+            }
+            "--output-format" => {
+                parsed.output_format = match value()?.as_str() {
+                    "json" => OutputFormat::Json,
+                    "json-pretty" => OutputFormat::JsonPretty,
+                    "yaml" if cfg!(feature = "serde_yaml") => OutputFormat::Yaml,
+                    "yaml" => return Err("yaml output needs jsonref built with the serde_yaml feature".to_owned()),
+                    other => return Err(format!("unknown output format {}", other)),
+                }
+            }
+            "--format" => {
+                parsed.graph_format = Some(match value()?.as_str() {
+                    "dot" => GraphFormat::Dot,
+                    "json" => GraphFormat::Json,
+                    other => return Err(format!("unknown graph format {}", other)),
+                })
+            }
+            "--error-format" => {
+                parsed.error_format = match value()?.as_str() {
+                    "text" => ErrorFormat::Text,
+                    "json" => ErrorFormat::Json,
+                    other => return Err(format!("unknown error format {}", other)),
+                }
+            }
+            "-o" | "--output" => parsed.output = Some(value()?),
+            "--strict" => parsed.strict = true,
+            "--migrate" => parsed.migrate = true,
+            "--openapi" => parsed.openapi = true,
+            "--openrpc" => parsed.openrpc = true,
+            "--keep-components" => parsed.keep_components = true,
+            "--schema-registry" => parsed.schema_registry = Some(value()?),
+            "--retries" => {
+                let retries = value()?;
+                parsed.retries = Some(retries.parse().map_err(|_| format!("--retries needs a number, not {}", retries))?);
+            }
+            "--resolve" => {
+                let mapping = value()?;
+                let address = mapping
+                    .split_once('=')
+                    .and_then(|(host, address)| Some((host.to_owned(), address.parse().ok()?)))
+                    .ok_or_else(|| format!("--resolve needs HOST=ADDRESS with an IP address, not {}", mapping))?;
+                parsed.addresses.push(address);
+            }
+            "--mirror" => {
+                let mapping = value()?;
+                let (prefix, base) = mapping
+                    .split_once('=')
+                    .ok_or_else(|| format!("--mirror needs PREFIX=BASE, not {}", mapping))?;
+                parsed.mirrors.push((prefix.to_owned(), base.to_owned()));
+            }
+            "--snapshot" => parsed.snapshot = Some(value()?),
+            "--write-snapshot" => parsed.write_snapshot = Some(value()?),
+            "--watch" => parsed.watch = true,
+            "-h" | "--help" => parsed.help = true,
+            flag if flag.starts_with('-') && flag != "-" => return Err(format!("unknown option {}", flag)),
+            _ => parsed.paths.push(arg),
+        }
+    }
+    if parsed.command == Command::Check {
+        if parsed.paths.is_empty() || parsed.paths.iter().any(|path| path == "-") {
+            return Err("jsonref check needs SCHEMA files".to_owned());
+        }
+    } else if let Some(unexpected) = parsed.paths.get(1) {
+        return Err(format!("unexpected argument {}", unexpected));
+    }
+    if parsed.graph_format.is_some() && parsed.command != Command::Graph {
+        return Err("--format is only for jsonref graph".to_owned());
+    }
+    if parsed.openapi && parsed.openrpc {
+        return Err("--openapi and --openrpc can not be used together".to_owned());
+    }
+    if parsed.keep_components && !parsed.openapi && !parsed.openrpc {
+        return Err("--keep-components needs --openapi or --openrpc".to_owned());
+    }
+    if parsed.write_snapshot.is_some() && parsed.command == Command::Check {
+        return Err("--write-snapshot is not for jsonref check".to_owned());
+    }
+    if parsed.watch && (parsed.command == Command::Check || parsed.path().is_none()) {
+        return Err("--watch needs a SCHEMA file".to_owned());
+    }
+    Ok(parsed)
+}
+
+fn main() {
+    let arguments: Vec<String> = env::args().skip(1).collect();
+    let args = match parse_args(arguments.clone()) {
+        Ok(args) => args,
+        Err(message) => {
+            // The arguments are bad, but still honour the error format if it was given.
+            let json = arguments.iter().any(|arg| arg == "--error-format=json")
+                || arguments.windows(2).any(|pair| pair[0] == "--error-format" && pair[1] == "json");
+            let diagnostic = Diagnostic::new(ErrorKind::Usage, message);
+            if json {
+                ErrorFormat::Json.report(&[diagnostic]);
+            } else {
+                eprintln!("jsonref: {}\n\n{}", diagnostic.message, USAGE);
+            }
+            process::exit(ErrorKind::Usage.exit_code());
+        }
+    };
+    if args.help {
+        println!("{}", USAGE);
+        return;
+    }
+    if let Err(diagnostics) = run(&args) {
+        args.error_format.report(&diagnostics);
+        if args.command == Command::Check && args.error_format == ErrorFormat::Text {
+            match diagnostics.len() {
+                1 => eprintln!("jsonref: 1 error"),
+                errors => eprintln!("jsonref: {} errors", errors),
+            }
+        }
+        let kind = diagnostics.iter().map(|diagnostic| diagnostic.kind).max();
+        process::exit(kind.unwrap_or(ErrorKind::Resolution).exit_code());
+    }
+}
+
+fn run(args: &Args) -> Result<(), Vec<Diagnostic>> {
+    if args.command == Command::Check {
+        let diagnostics = check(args);
+        return if diagnostics.is_empty() { Ok(()) } else { Err(diagnostics) };
+    }
+    if !args.watch {
+        let (output, _) = render(args).map_err(|diagnostic| vec![diagnostic])?;
+        return write_output(args, &output).map_err(|diagnostic| vec![diagnostic]);
+    }
+
+    let mut watched = vec![];
+    loop {
+        match render(args) {
+            Ok((output, files)) => {
+                write_output(args, &output).map_err(|diagnostic| vec![diagnostic])?;
+                if let Some(path) = &args.output {
+                    eprintln!("jsonref: wrote {}", path);
+                }
+                watched = files;
+            }
+            // Keep watching the files from the last good run, as the one in error may be
+            // among them.
+            Err(diagnostic) => args.error_format.report(&[diagnostic]),
+        }
+        if let Some(path) = args.path() {
+            watched.push(PathBuf::from(path));
+        }
+        wait_for_change(&watched);
+    }
+}
+
+/// Dereference, or make the graph, returning the output and the files that were read.
+fn render(args: &Args) -> Result<(Vec<u8>, Vec<PathBuf>), Diagnostic> {
+    let (output, jsonref) = deref(args)?;
+    let output = if args.command == Command::Graph {
+        args.graph_format.unwrap_or_default().render(&jsonref.ref_graph())
+    } else {
+        args.output_format.serialize(&output)?
+    };
+    let files = jsonref
+        .documents()
+        .into_iter()
+        .filter_map(|url| Url::parse(&url).ok()?.to_file_path().ok())
+        .collect();
+    Ok((output, files))
+}
+
+/// Check the refs in every file, returning an error for each one that can not be resolved, or for
+/// a file that can not be read.
+fn check(args: &Args) -> Vec<Diagnostic> {
+    let mut jsonref = JsonRef::new();
+    jsonref.set_strict_refs(args.strict);
+    jsonref.set_migrate_drafts(args.migrate);
+    if let Some(registry) = &args.schema_registry {
+        jsonref.set_schema_registry(registry);
+    }
+    if let Some(retries) = args.retries {
+        jsonref.add_layer(Retry::new(retries));
+    }
+    for (host, address) in &args.addresses {
+        jsonref.set_host_address(host, *address);
+    }
+    for (prefix, base) in &args.mirrors {
+        jsonref.set_mirror(prefix, base);
+    }
+    if let Some(snapshot) = &args.snapshot {
+        if let Err(error) = jsonref.load_snapshot(snapshot) {
+            return vec![Diagnostic::new(ErrorKind::Io, error.to_string())];
+        }
+    }
+    let mut diagnostics = vec![];
+    // The text of the files with broken refs, to find the refs in.
+    let mut sources: HashMap<String, Option<String>> = HashMap::new();
+    for path in &args.paths {
+        match jsonref.check_file(path) {
+            Ok(refs) => diagnostics.extend(refs.into_iter().map(|broken| {
+                let source = sources.entry(broken.document.clone()).or_insert_with(|| {
+                    let file = Url::parse(&broken.document).ok()?.to_file_path().ok()?;
+                    fs::read_to_string(file).ok()
+                });
+                let location = source
+                    .as_deref()
+                    .and_then(|source| spans::locate(source, &format!("{}/$ref", broken.pointer)));
+                let file = display_url(&broken.document);
+                let message = broken.error.to_string();
+                #[cfg(feature = "miette")]
+                let report = source.clone().map(|source| {
+                    let located = jsonref::diagnostic::LocatedError::new(broken.error, &file, source, &broken.pointer);
+                    format!("{:?}", miette::Report::new(located))
+                });
+                #[cfg(not(feature = "miette"))]
+                let report = None;
+                Diagnostic {
+                    file: Some(file),
+                    pointer: Some(broken.pointer),
+                    reference: Some(broken.reference),
+                    location,
+                    report,
+                    ..Diagnostic::new(ErrorKind::Resolution, message)
+                }
+            })),
+            Err(error) => diagnostics.push(Diagnostic::from_jsonref(error, Some(path))),
+        }
+    }
+    diagnostics
+}
+
+/// `url` as a path relative to the current directory if it is a file URL below it.
+fn display_url(url: &str) -> String {
+    let file = match Url::parse(url).ok().and_then(|url| url.to_file_path().ok()) {
+        Some(file) => file,
+        None => return url.to_owned(),
+    };
+    match env::current_dir().ok().and_then(|dir| file.strip_prefix(dir).ok().map(PathBuf::from)) {
+        Some(relative) => relative.display().to_string(),
+        None => file.display().to_string(),
+    }
+}
+
+/// Dereference the input, returning the output and the `JsonRef` holding every document read.
+fn deref(args: &Args) -> Result<(Value, JsonRef), Diagnostic> {
+    let mut jsonref = JsonRef::new();
+    args.mode.configure(&mut jsonref);
+    jsonref.set_strict_refs(args.strict);
+    jsonref.set_migrate_drafts(args.migrate);
+    jsonref.set_openapi(args.openapi);
+    jsonref.set_openrpc(args.openrpc);
+    jsonref.set_keep_components(args.keep_components);
+    if let Some(registry) = &args.schema_registry {
+        jsonref.set_schema_registry(registry);
+    }
+    if let Some(retries) = args.retries {
+        jsonref.add_layer(Retry::new(retries));
+    }
+    for (host, address) in &args.addresses {
+        jsonref.set_host_address(host, *address);
+    }
+    for (prefix, base) in &args.mirrors {
+        jsonref.set_mirror(prefix, base);
+    }
+    if let Some(snapshot) = &args.snapshot {
+        jsonref
+            .load_snapshot(snapshot)
+            .map_err(|error| Diagnostic::new(ErrorKind::Io, error.to_string()))?;
+    }
+    let output = match args.path() {
+        Some(path) => jsonref.deref_file(path).map_err(|error| Diagnostic::from_jsonref(error, Some(path)))?,
+        None => {
+            let mut input = String::new();
+            io::stdin()
+                .read_to_string(&mut input)
+                .map_err(|error| Diagnostic::new(ErrorKind::Io, format!("could not read stdin: {}", error)))?;
+            let mut value: Value = serde_json::from_str(&input)
+                .map_err(|error| Diagnostic::new(ErrorKind::Io, format!("stdin is not valid JSON: {}", error)))?;
+            jsonref.deref_value(&mut value).map_err(|error| Diagnostic::from_jsonref(error, None))?;
+            value
+        }
+    };
+    if args.mode == Mode::Crd {
+        for warning in jsonref.warnings() {
+            eprintln!("jsonref: warning: {}", warning);
+        }
+    }
+    if let Some(snapshot) = &args.write_snapshot {
+        jsonref
+            .write_snapshot(snapshot)
+            .map_err(|error| Diagnostic::new(ErrorKind::Io, error.to_string()))?;
+    }
+    Ok((output, jsonref))
+}
+
+fn write_output(args: &Args, output: &[u8]) -> Result<(), Diagnostic> {
+    if let Some(path) = &args.output {
+        return fs::write(path, output)
+            .map_err(|error| Diagnostic::new(ErrorKind::Io, format!("could not write {}: {}", path, error)));
+    }
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    match stdout.write_all(output).and_then(|_| stdout.flush()) {
+        // The reader, such as `head`, has seen all it wants.
+        Err(error) if error.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        written => written.map_err(|error| Diagnostic::new(ErrorKind::Io, format!("could not write output: {}", error))),
+    }
+}
+
+/// Block until the modification time of one of `files` changes, polling twice a second.
+fn wait_for_change(files: &[PathBuf]) {
+    let modified = |file: &PathBuf| -> Option<SystemTime> { fs::metadata(file).and_then(|meta| meta.modified()).ok() };
+    let before: Vec<_> = files.iter().map(modified).collect();
+    loop {
+        thread::sleep(Duration::from_millis(500));
+        if files.iter().map(modified).ne(before.iter().cloned()) {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, parse_args, render, Args, Command, ErrorKind, Mode, OutputFormat};
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_paths() {
+        assert_eq!(parse(&[]).unwrap(), Args::default());
+        assert_eq!(parse(&["-"]).unwrap().path(), None);
+        assert_eq!(parse(&["base.json"]).unwrap().path(), Some("base.json"));
+        assert!(parse(&["a.json", "b.json"]).is_err());
+        assert!(parse(&["--nope"]).is_err());
+    }
+
+    #[test]
+    fn parse_mode() {
+        assert_eq!(parse(&["--mode", "bundle", "a.json"]).unwrap().mode, Mode::Bundle);
+        assert_eq!(parse(&["--mode=rewrite"]).unwrap().mode, Mode::Rewrite);
+        assert!(parse(&["--mode"]).is_err());
+        assert!(parse(&["--mode", "expand"]).is_err());
+        assert_eq!(
+            parse(&["--schema-registry=http://localhost:8081"]).unwrap().schema_registry.as_deref(),
+            Some("http://localhost:8081")
+        );
+        assert_eq!(parse(&["--snapshot", "s.json"]).unwrap().snapshot.as_deref(), Some("s.json"));
+        assert_eq!(parse(&["--retries", "3"]).unwrap().retries, Some(3));
+        assert!(parse(&["--retries", "often"]).is_err());
+        assert!(parse(&["check", "a.json", "--write-snapshot", "s.json"]).is_err());
+        assert!(parse(&["--keep-components", "a.json"]).is_err());
+        assert!(parse(&["--openapi", "--keep-components", "a.json"]).unwrap().keep_components);
+        assert!(parse(&["--openrpc", "--keep-components", "a.json"]).unwrap().keep_components);
+        assert!(parse(&["--openapi", "--openrpc", "a.json"]).is_err());
+        assert_eq!(
+            parse(&["--resolve", "schemas.example.com=10.0.0.7"]).unwrap().addresses,
+            [("schemas.example.com".to_owned(), "10.0.0.7".parse().unwrap())]
+        );
+        assert!(parse(&["--resolve", "schemas.example.com=mirror.local"]).is_err());
+        assert_eq!(
+            parse(&["--mirror=https://schemas.example.com/=file:///srv/schemas/"]).unwrap().mirrors,
+            [("https://schemas.example.com/".to_owned(), "file:///srv/schemas/".to_owned())]
+        );
+    }
+
+    #[test]
+    fn output_formats() {
+        let output = serde_json::json!({"a": [1]});
+        let format = |name| parse(&["--output-format", name]).map(|args| args.output_format);
+        assert_eq!(format("json").unwrap().serialize(&output).unwrap(), b"{\"a\":[1]}\n");
+        assert_eq!(format("json-pretty").unwrap(), OutputFormat::JsonPretty);
+        assert_eq!(format("yaml").is_ok(), cfg!(feature = "serde_yaml"));
+        assert!(format("toml").is_err());
+    }
+
+    #[test]
+    fn graph() {
+        let args = parse(&["graph", "jsonref-core/fixtures/nested_relative/base.json"]).unwrap();
+        assert_eq!(args.command, Command::Graph);
+        let (output, _) = render(&args).unwrap();
+        let dot = String::from_utf8(output).unwrap();
+        let edge = dot.lines().find(|line| line.contains(" -> ")).unwrap();
+        assert!(edge.contains("/base.json\" -> \"file://") && edge.ends_with("/other.json\";"));
+
+        assert!(parse(&["graph", "--format", "json"]).is_ok());
+        assert!(parse(&["--format", "json"]).is_err());
+    }
+
+    #[test]
+    fn check_refs() {
+        let args = parse(&["check", "jsonref-core/fixtures/check/broken.json", "jsonref-core/fixtures/nested_relative/base.json"]).unwrap();
+        let diagnostics = check(&args);
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics.iter().all(|diagnostic| diagnostic.kind == ErrorKind::Resolution));
+        assert!(diagnostics[0].text().starts_with("jsonref-core/fixtures/check/broken.json:7:25: $ref #/$defs/age at /properties/age: "));
+        assert_eq!(diagnostics[1].json()["line"], 9);
+        assert_eq!(diagnostics[1].json()["pointer"], "/properties/missing");
+        assert_eq!(diagnostics[1].json()["ref"], "missing.json");
+
+        assert!(parse(&["check"]).is_err());
+    }
+
+    #[test]
+    fn error_kinds() {
+        let args = parse(&["--error-format", "json", "missing.json"]).unwrap();
+        let diagnostic = render(&args).unwrap_err();
+        assert_eq!(diagnostic.kind, ErrorKind::Io);
+        assert_eq!(diagnostic.json()["file"], "missing.json");
+
+        let args = parse(&["jsonref-core/fixtures/check/broken.json"]).unwrap();
+        assert_eq!(render(&args).unwrap_err().kind, ErrorKind::Resolution);
+    }
+
+    #[test]
+    fn watched_files() {
+        let args = parse(&["--watch", "jsonref-core/fixtures/nested_relative/base.json", "-o", "out.json"]).unwrap();
+        assert_eq!(args.output.as_deref(), Some("out.json"));
+        let (_, files) = render(&args).unwrap();
+        let names: Vec<_> = files.iter().map(|file| file.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, ["base.json", "other.json"]);
+
+        assert!(parse(&["--watch"]).is_err());
+    }
+}